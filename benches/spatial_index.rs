@@ -0,0 +1,89 @@
+// 笔画空间索引的基准测试：1万和2万条笔画的页面上，网格查询与逐条扫描包围盒的对比
+use criterion::{black_box, criterion_group, criterion_main, BenchmarkId, Criterion};
+
+#[allow(dead_code, unused_imports)]
+#[path = "../src/geometry.rs"]
+mod geometry;
+#[allow(dead_code, unused_imports)]
+#[path = "../src/spatial.rs"]
+mod spatial;
+
+use geometry::{Rect, Vec2};
+use spatial::SpatialIndex;
+
+const PAGE_SIZE: f32 = 8000.0;
+
+// 固定种子的线性同余随机数，保证每次运行的数据相同
+fn stroke_bounds(count: usize) -> Vec<Option<Rect>> {
+    let mut seed: u32 = 12345;
+    let mut next = move || {
+        seed = seed.wrapping_mul(1_103_515_245).wrapping_add(12345);
+        (seed >> 8) as f32 / (1u32 << 24) as f32
+    };
+    (0..count)
+        .map(|_| {
+            let min = Vec2::new(next() * PAGE_SIZE, next() * PAGE_SIZE);
+            Some(Rect::from_min_size(min, 5.0 + next() * 200.0, 5.0 + next() * 200.0))
+        })
+        .collect()
+}
+
+fn linear_query(bounds: &[Option<Rect>], area: &Rect) -> Vec<usize> {
+    bounds.iter()
+        .enumerate()
+        .filter(|(_, bounds)| bounds.is_some_and(|bounds| bounds.intersects(area)))
+        .map(|(index, _)| index)
+        .collect()
+}
+
+fn bench_queries(c: &mut Criterion) {
+    let mut group = c.benchmark_group("stroke_query");
+    for count in [10_000, 20_000] {
+        let bounds = stroke_bounds(count);
+        let index = SpatialIndex::from_bounds(bounds.iter().copied());
+        // 橡皮擦一次移动的范围和一个屏幕大小的视口
+        let eraser = Rect { min: Vec2::new(4000.0, 4000.0), max: Vec2::new(4016.0, 4016.0) };
+        let viewport = Rect::from_min_size(Vec2::new(2000.0, 3000.0), 1920.0, 1080.0);
+
+        group.bench_with_input(BenchmarkId::new("eraser/index", count), &eraser, |b, area| {
+            b.iter(|| index.query(black_box(area)))
+        });
+        group.bench_with_input(BenchmarkId::new("eraser/linear", count), &eraser, |b, area| {
+            b.iter(|| linear_query(&bounds, black_box(area)))
+        });
+        group.bench_with_input(BenchmarkId::new("viewport/index", count), &viewport, |b, area| {
+            b.iter(|| index.query(black_box(area)))
+        });
+        group.bench_with_input(BenchmarkId::new("viewport/linear", count), &viewport, |b, area| {
+            b.iter(|| linear_query(&bounds, black_box(area)))
+        });
+    }
+    group.finish();
+}
+
+fn bench_updates(c: &mut Criterion) {
+    let bounds = stroke_bounds(10_000);
+    let extra = stroke_bounds(1)[0];
+
+    c.bench_function("stroke_index/build_10000", |b| {
+        b.iter(|| SpatialIndex::from_bounds(black_box(&bounds).iter().copied()))
+    });
+
+    // 新笔画追加在末尾；撤销删除中间的笔画需要给后面的编号减一
+    let mut index = SpatialIndex::from_bounds(bounds.iter().copied());
+    c.bench_function("stroke_index/push_and_remove_last", |b| {
+        b.iter(|| {
+            index.push(black_box(extra));
+            index.remove(index.len() - 1);
+        })
+    });
+    c.bench_function("stroke_index/insert_and_remove_middle", |b| {
+        b.iter(|| {
+            index.insert(5_000, black_box(extra));
+            index.remove(5_000);
+        })
+    });
+}
+
+criterion_group!(benches, bench_queries, bench_updates);
+criterion_main!(benches);
@@ -0,0 +1,411 @@
+// 复制粘贴用的内容片段：序列化后嵌在一份SVG里放到系统剪贴板，
+// 其他应用得到矢量图，本应用从SVG的metadata中取回原始数据
+use crate::geometry::{Transform, Vec2};
+use crate::markdown;
+use crate::note::{Color, Element, ItemRef, LinkTarget, Note, ShapeKind, Stroke, TextBox, FORMAT_VERSION, TEXT_BOX_PADDING};
+use crate::outline::PathSegment;
+use crate::storage;
+use serde::{Deserialize, Serialize};
+use std::fmt::Write;
+
+// 嵌入SVG的片段数据所在的metadata标签
+const METADATA_START: &str = "<metadata id=\"speedynote-fragment\">";
+const METADATA_END: &str = "</metadata>";
+
+// 复制时放到系统剪贴板的两种内容：内嵌片段数据的SVG文本，以及给其他应用使用的PNG图片
+#[derive(Debug, Clone, Serialize)]
+pub struct ClipboardContent {
+    pub text: String,
+    pub png: Vec<u8>,
+}
+
+// 坐标相对于片段包围盒的左上角
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Fragment {
+    pub format_version: u32,
+    // 图片资源所在的笔记，粘贴到其他笔记时从这里复制
+    pub source_note_id: String,
+    // 复制时在原页面上的位置，没有光标位置时粘贴回这里
+    pub origin: Vec2,
+    pub width: f32,
+    pub height: f32,
+    pub strokes: Vec<Stroke>,
+    pub elements: Vec<Element>,
+}
+
+impl Fragment {
+    pub fn from_items(note: &Note, page_index: usize, items: &[ItemRef]) -> Option<Fragment> {
+        let page = note.pages.get(page_index)?;
+        let bounds = page.items_bounds(items)?;
+        let to_local = Transform::Translate(bounds.min * -1.0);
+
+        let mut fragment = Fragment {
+            format_version: FORMAT_VERSION,
+            source_note_id: note.id.clone(),
+            origin: bounds.min,
+            width: bounds.width(),
+            height: bounds.height(),
+            strokes: Vec::new(),
+            elements: Vec::new(),
+        };
+        for item in items {
+            match *item {
+                ItemRef::Stroke { layer, index } => {
+                    if let Some(stroke) = page.layers.get(layer).and_then(|l| l.strokes.get(index)) {
+                        let mut stroke = stroke.clone();
+                        stroke.transform(&to_local);
+                        fragment.strokes.push(stroke);
+                    }
+                }
+                ItemRef::Element { layer, index } => {
+                    if let Some(element) = page.layers.get(layer).and_then(|l| l.elements.get(index)) {
+                        let mut element = element.clone();
+                        element.transform(&to_local);
+                        fragment.elements.push(element);
+                    }
+                }
+            }
+        }
+        Some(fragment)
+    }
+
+    // 放到系统剪贴板的文本：带片段数据的SVG
+    pub fn to_clipboard_text(&self) -> Result<String, serde_json::Error> {
+        let data = serde_json::to_string(self)?;
+        Ok(self.to_svg(&escape_xml(&data)))
+    }
+
+    // 从剪贴板文本中取回片段；不是本应用复制的内容或版本不兼容时返回None
+    pub fn from_clipboard_text(text: &str) -> Option<Fragment> {
+        let start = text.find(METADATA_START)? + METADATA_START.len();
+        let end = start + text[start..].find(METADATA_END)?;
+        let fragment: Fragment = serde_json::from_str(&unescape_xml(&text[start..end])).ok()?;
+
+        if fragment.format_version != FORMAT_VERSION {
+            log::warn!("剪贴板内容的格式版本 {} 与当前版本不兼容", fragment.format_version);
+            return None;
+        }
+        Some(fragment)
+    }
+
+    fn to_svg(&self, metadata: &str) -> String {
+        let mut svg = String::new();
+        let _ = write!(
+            svg,
+            "<svg xmlns=\"http://www.w3.org/2000/svg\" width=\"{w}\" height=\"{h}\" viewBox=\"0 0 {w} {h}\">",
+            w = self.width,
+            h = self.height,
+        );
+        let _ = write!(svg, "{}{}{}", METADATA_START, metadata, METADATA_END);
+
+        // 与页面上的绘制顺序一致：元素在下，笔迹在上
+        for element in &self.elements {
+            match element {
+                Element::Text(text) => write_text_box(&mut svg, text),
+                Element::Image(image) => {
+                    // 图片以data URI内嵌，资源读不到时只保留占位框
+                    let href = storage::read_image_asset(&self.source_note_id, &image.asset)
+                        .ok()
+                        .map(|data| format!("data:{};base64,{}", image_mime(&image.asset), base64_encode(&data)));
+                    let center = image.center();
+                    let _ = write!(
+                        svg,
+                        "<g transform=\"rotate({} {} {})\">",
+                        image.rotation.to_degrees(), center.x, center.y,
+                    );
+                    match href {
+                        Some(href) => {
+                            let _ = write!(
+                                svg,
+                                "<image x=\"{}\" y=\"{}\" width=\"{}\" height=\"{}\" preserveAspectRatio=\"none\" href=\"{}\"/>",
+                                image.x, image.y, image.width, image.height, href,
+                            );
+                        }
+                        None => {
+                            let _ = write!(
+                                svg,
+                                "<rect x=\"{}\" y=\"{}\" width=\"{}\" height=\"{}\" fill=\"none\" stroke=\"gray\"/>",
+                                image.x, image.y, image.width, image.height,
+                            );
+                        }
+                    }
+                    svg.push_str("</g>");
+                }
+                Element::Shape(shape) => {
+                    let (points, closed) = shape.kind.path();
+                    let fill = shape.fill.map_or("fill=\"none\"".to_string(), |fill| paint("fill", fill, 1.0));
+                    let _ = write!(
+                        svg,
+                        "<{} points=\"{}\" {} {} stroke-width=\"{}\" stroke-linejoin=\"round\" stroke-linecap=\"round\"/>",
+                        if closed { "polygon" } else { "polyline" },
+                        points.iter().map(|p| format!("{},{}", p.x, p.y)).collect::<Vec<_>>().join(" "),
+                        fill,
+                        paint("stroke", shape.color, 1.0),
+                        shape.thickness,
+                    );
+                    if let ShapeKind::Arrow { start, end } = shape.kind {
+                        let head = ShapeKind::arrow_head(start, end, shape.thickness);
+                        let _ = write!(
+                            svg,
+                            "<polygon points=\"{},{} {},{} {},{}\" {}/>",
+                            head[0].x, head[0].y, head[1].x, head[1].y, head[2].x, head[2].y,
+                            paint("fill", shape.color, 1.0),
+                        );
+                    }
+                }
+            }
+        }
+
+        // 荧光笔画在其他笔迹下面
+        let mut strokes: Vec<&Stroke> = self.strokes.iter().collect();
+        strokes.sort_by_key(|stroke| !stroke.tool.is_underlay());
+        for stroke in strokes {
+            let mut data = String::new();
+            for segment in &stroke.outline().path {
+                let _ = match *segment {
+                    PathSegment::MoveTo(p) => write!(data, "M{} {}", p.x, p.y),
+                    PathSegment::LineTo(p) => write!(data, "L{} {}", p.x, p.y),
+                    PathSegment::CurveTo(c1, c2, p) => write!(data, "C{} {} {} {} {} {}", c1.x, c1.y, c2.x, c2.y, p.x, p.y),
+                    PathSegment::Close => write!(data, "Z"),
+                };
+            }
+            let _ = write!(
+                svg,
+                "<path d=\"{}\" {}/>",
+                data,
+                paint("fill", stroke.color, stroke.tool.opacity()),
+            );
+        }
+
+        svg.push_str("</svg>");
+        svg
+    }
+}
+
+// SVG不会自动换行，每个Markdown块输出为一行
+fn write_text_box(svg: &mut String, text: &TextBox) {
+    if let Some(background) = &text.background {
+        let _ = write!(
+            svg,
+            "<rect x=\"{}\" y=\"{}\" width=\"{}\" height=\"{}\" rx=\"4\" {}/>",
+            text.x, text.y, text.width, text.height, paint("fill", *background, 1.0),
+        );
+    }
+
+    let mut baseline = text.y + TEXT_BOX_PADDING;
+    for block in markdown::parse(&text.content) {
+        let size = text.font_size * block.kind.font_scale();
+        baseline += size;
+        let content: String = block.kind.prefix().into_iter()
+            .chain(block.spans.iter().map(|span| span.text.clone()))
+            .collect();
+        let _ = write!(
+            svg,
+            "<text x=\"{}\" y=\"{}\" font-size=\"{}\" {}>{}</text>",
+            text.x + TEXT_BOX_PADDING, baseline, size, paint("fill", text.color, 1.0), escape_xml(&content),
+        );
+        baseline += size * 0.3;
+    }
+}
+
+// 把片段粘贴到页面的活动图层，center为粘贴内容的中心，None时放回复制时的位置；
+// 返回粘贴出的内容，便于直接选中
+pub fn paste(note: &mut Note, page_index: usize, fragment: &Fragment, center: Option<Vec2>) -> Result<Vec<ItemRef>, String> {
+    let page = note.pages.get(page_index).ok_or_else(|| "Page index out of bounds".to_string())?;
+    let layer_index = page.active_layer;
+    let layer = page.layers.get(layer_index).ok_or_else(|| "Layer index out of bounds".to_string())?;
+    if !layer.is_editable() {
+        return Err("Active layer is hidden or locked".to_string());
+    }
+    let (stroke_start, element_start) = (layer.strokes.len(), layer.elements.len());
+
+    let position = center.map_or(fragment.origin, |center| {
+        Vec2::new(center.x - fragment.width / 2.0, center.y - fragment.height / 2.0)
+    });
+    let to_page = Transform::Translate(position);
+    // 页面链接按id指向原笔记的页面，粘贴到其他笔记时改为指向原笔记
+    let foreign = fragment.source_note_id != note.id;
+    let qualify = |link: &mut Option<LinkTarget>| {
+        if let Some(target) = link.as_mut().filter(|_| foreign) {
+            *target = target.qualified(&fragment.source_note_id);
+        }
+    };
+
+    let mut elements = fragment.elements.clone();
+    for element in &mut elements {
+        element.transform(&to_page);
+        element.regenerate_id();
+
+        // 粘贴到其他笔记时把图片资源一起带过去
+        match element {
+            Element::Image(image) if foreign => {
+                if let Err(e) = storage::copy_image_asset(&fragment.source_note_id, &note.id, &image.asset) {
+                    log::warn!("粘贴时复制图片资源失败 {}: {}", image.asset, e);
+                }
+            }
+            Element::Text(text) => qualify(&mut text.link),
+            _ => {}
+        }
+    }
+    let strokes: Vec<Stroke> = fragment.strokes.iter()
+        .map(|stroke| {
+            let mut stroke = stroke.clone();
+            stroke.transform(&to_page);
+            qualify(&mut stroke.link);
+            stroke
+        })
+        .collect();
+
+    let items = (0..strokes.len())
+        .map(|i| ItemRef::Stroke { layer: layer_index, index: stroke_start + i })
+        .chain((0..elements.len()).map(|i| ItemRef::Element { layer: layer_index, index: element_start + i }))
+        .collect();
+
+    note.edit_page(page_index, |page| {
+        let layer = &mut page.layers[layer_index];
+        for stroke in strokes {
+            layer.push_stroke(stroke);
+        }
+        layer.elements.extend(elements);
+    })?;
+    Ok(items)
+}
+
+// 系统剪贴板中的图片，剪贴板里没有图片时返回None
+pub fn read_image() -> Result<Option<image::RgbaImage>, Box<dyn std::error::Error>> {
+    let image = match arboard::Clipboard::new()?.get_image() {
+        Ok(image) => image,
+        Err(arboard::Error::ContentNotAvailable) => return Ok(None),
+        Err(e) => return Err(e.into()),
+    };
+    let rgba = image::RgbaImage::from_raw(image.width as u32, image.height as u32, image.bytes.into_owned())
+        .ok_or("Invalid clipboard image")?;
+    Ok(Some(rgba))
+}
+
+// 把片段同时以文本和图片放到系统剪贴板，替换原有内容：纯文本是内嵌片段数据的SVG，
+// 粘贴回本应用时还原；HTML里是栅格化的PNG，其他应用按图片粘贴。
+// arboard一次只能写入一种格式，只有set_html会同时写入HTML和纯文本，所以图片放在HTML里
+pub fn write_fragment(text: &str, image: &image::RgbaImage) -> Result<(), Box<dyn std::error::Error>> {
+    let html = format!("<img src=\"data:image/png;base64,{}\" width=\"{}\" height=\"{}\">",
+        base64_encode(&encode_png(image)?), image.width(), image.height());
+    arboard::Clipboard::new()?.set_html(html, Some(text.to_string()))?;
+    Ok(())
+}
+
+pub fn encode_png(image: &image::RgbaImage) -> Result<Vec<u8>, Box<dyn std::error::Error>> {
+    let mut png = Vec::new();
+    image.write_to(&mut std::io::Cursor::new(&mut png), image::ImageOutputFormat::Png)?;
+    Ok(png)
+}
+
+// SVG 1.1不支持#RRGGBBAA，透明度单独输出为fill-opacity/stroke-opacity
+fn paint(attribute: &str, color: Color, opacity: f32) -> String {
+    let opacity = color.alpha() * opacity;
+    if opacity >= 1.0 {
+        format!("{}=\"{}\"", attribute, color.to_rgb_hex())
+    } else {
+        format!("{}=\"{}\" {}-opacity=\"{}\"", attribute, color.to_rgb_hex(), attribute, opacity)
+    }
+}
+
+fn escape_xml(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+fn unescape_xml(text: &str) -> String {
+    text.replace("&quot;", "\"")
+        .replace("&gt;", ">")
+        .replace("&lt;", "<")
+        .replace("&amp;", "&")
+}
+
+fn image_mime(asset: &str) -> &'static str {
+    match asset.rsplit('.').next().map(str::to_ascii_lowercase).as_deref() {
+        Some("jpg") | Some("jpeg") => "image/jpeg",
+        Some("gif") => "image/gif",
+        Some("webp") => "image/webp",
+        Some("bmp") => "image/bmp",
+        _ => "image/png",
+    }
+}
+
+fn base64_encode(data: &[u8]) -> String {
+    use base64::Engine;
+    base64::engine::general_purpose::STANDARD.encode(data)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::note::{Point, ShapeElement, StrokeTool};
+
+    fn note_with_content() -> Note {
+        let mut note = Note::new("source".to_string());
+        note.add_stroke(Stroke {
+            points: vec![
+                Point { x: 100.0, y: 100.0, pressure: 1.0, timestamp: 0, tilt: None },
+                Point { x: 140.0, y: 120.0, pressure: 1.0, timestamp: 1, tilt: None },
+            ],
+            color: Color::BLACK,
+            thickness: 2.0,
+            tool: StrokeTool::Pen,
+            link: None,
+            fitted: Default::default(),
+        });
+        note.add_element(Element::Shape(ShapeElement::new(
+            ShapeKind::Rectangle { min: Vec2::new(120.0, 90.0), max: Vec2::new(160.0, 130.0) },
+            Color::rgb(255, 0, 0),
+            2.0,
+        )));
+        note
+    }
+
+    fn all_items() -> Vec<ItemRef> {
+        vec![ItemRef::Stroke { layer: 0, index: 0 }, ItemRef::Element { layer: 0, index: 0 }]
+    }
+
+    #[test]
+    fn test_clipboard_text_round_trip() {
+        let note = note_with_content();
+        let fragment = Fragment::from_items(&note, 0, &all_items()).unwrap();
+
+        // 包围盒包含笔画和矩形各自一半的线宽
+        assert_eq!(fragment.origin, Vec2::new(99.0, 89.0));
+        assert_eq!((fragment.width, fragment.height), (62.0, 42.0));
+        assert_eq!(fragment.strokes[0].points[0].x, 1.0);
+
+        let text = fragment.to_clipboard_text().unwrap();
+        assert!(text.starts_with("<svg"));
+        assert!(text.contains("<path d=\"M"));
+
+        let restored = Fragment::from_clipboard_text(&text).unwrap();
+        assert_eq!(restored.strokes.len(), 1);
+        assert_eq!(restored.elements.len(), 1);
+        assert_eq!(restored.strokes[0].points[1].y, fragment.strokes[0].points[1].y);
+        assert!(Fragment::from_clipboard_text("<svg></svg>").is_none());
+    }
+
+    #[test]
+    fn test_paste_into_other_note_at_cursor() {
+        let source = note_with_content();
+        let fragment = Fragment::from_items(&source, 0, &all_items()).unwrap();
+
+        let mut target = Note::new("target".to_string());
+        let items = paste(&mut target, 0, &fragment, Some(Vec2::new(300.0, 300.0))).unwrap();
+        assert_eq!(items, vec![ItemRef::Stroke { layer: 0, index: 0 }, ItemRef::Element { layer: 0, index: 0 }]);
+
+        let page = &target.pages[0];
+        let bounds = page.items_bounds(&items).unwrap();
+        assert!(bounds.center().distance(Vec2::new(300.0, 300.0)) < 1e-3);
+        assert_ne!(page.layers[0].elements[0].id(), source.pages[0].layers[0].elements[0].id());
+
+        // 粘贴是一次可撤销的操作
+        target.undo();
+        assert!(target.pages[0].layers[0].strokes.is_empty());
+        assert!(target.pages[0].layers[0].elements.is_empty());
+    }
+}
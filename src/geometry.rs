@@ -0,0 +1,548 @@
+// 二维几何辅助函数，供橡皮擦、选择等功能使用
+
+use serde::{Deserialize, Serialize};
+use std::ops::{Add, Mul, Sub};
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct Vec2 {
+    pub x: f32,
+    pub y: f32,
+}
+
+impl Vec2 {
+    pub fn new(x: f32, y: f32) -> Self {
+        Self { x, y }
+    }
+
+    pub fn dot(self, other: Vec2) -> f32 {
+        self.x * other.x + self.y * other.y
+    }
+
+    pub fn cross(self, other: Vec2) -> f32 {
+        self.x * other.y - self.y * other.x
+    }
+
+    pub fn length(self) -> f32 {
+        self.dot(self).sqrt()
+    }
+
+    pub fn distance(self, other: Vec2) -> f32 {
+        (self - other).length()
+    }
+
+    pub fn lerp(self, other: Vec2, t: f32) -> Vec2 {
+        self + (other - self) * t
+    }
+}
+
+impl Add for Vec2 {
+    type Output = Vec2;
+
+    fn add(self, other: Vec2) -> Vec2 {
+        Vec2::new(self.x + other.x, self.y + other.y)
+    }
+}
+
+impl Sub for Vec2 {
+    type Output = Vec2;
+
+    fn sub(self, other: Vec2) -> Vec2 {
+        Vec2::new(self.x - other.x, self.y - other.y)
+    }
+}
+
+impl Mul<f32> for Vec2 {
+    type Output = Vec2;
+
+    fn mul(self, factor: f32) -> Vec2 {
+        Vec2::new(self.x * factor, self.y * factor)
+    }
+}
+
+// 轴对齐矩形
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct Rect {
+    pub min: Vec2,
+    pub max: Vec2,
+}
+
+impl Rect {
+    pub fn from_min_size(min: Vec2, width: f32, height: f32) -> Self {
+        Self {
+            min,
+            max: Vec2::new(min.x + width, min.y + height),
+        }
+    }
+
+    pub fn width(&self) -> f32 {
+        self.max.x - self.min.x
+    }
+
+    pub fn height(&self) -> f32 {
+        self.max.y - self.min.y
+    }
+
+    pub fn contains(&self, p: Vec2) -> bool {
+        p.x >= self.min.x && p.x <= self.max.x && p.y >= self.min.y && p.y <= self.max.y
+    }
+
+    pub fn intersects(&self, other: &Rect) -> bool {
+        self.min.x <= other.max.x && other.min.x <= self.max.x && self.min.y <= other.max.y && other.min.y <= self.max.y
+    }
+
+    pub fn center(&self) -> Vec2 {
+        self.min.lerp(self.max, 0.5)
+    }
+
+    // 顺时针的四个角：左上、右上、右下、左下
+    pub fn corners(&self) -> [Vec2; 4] {
+        [
+            self.min,
+            Vec2::new(self.max.x, self.min.y),
+            self.max,
+            Vec2::new(self.min.x, self.max.y),
+        ]
+    }
+
+    pub fn union(&self, other: &Rect) -> Rect {
+        Rect {
+            min: Vec2::new(self.min.x.min(other.min.x), self.min.y.min(other.min.y)),
+            max: Vec2::new(self.max.x.max(other.max.x), self.max.y.max(other.max.y)),
+        }
+    }
+
+    // 两个矩形的重叠部分，不重叠时返回None
+    pub fn intersection(&self, other: &Rect) -> Option<Rect> {
+        if !self.intersects(other) {
+            return None;
+        }
+        Some(Rect {
+            min: Vec2::new(self.min.x.max(other.min.x), self.min.y.max(other.min.y)),
+            max: Vec2::new(self.max.x.min(other.max.x), self.max.y.min(other.max.y)),
+        })
+    }
+
+    // 包含所有点的最小矩形，没有点时返回None
+    pub fn from_points(points: impl IntoIterator<Item = Vec2>) -> Option<Rect> {
+        points.into_iter().fold(None, |bounds: Option<Rect>, p| {
+            let point = Rect { min: p, max: p };
+            Some(bounds.map_or(point, |bounds| bounds.union(&point)))
+        })
+    }
+
+    pub fn expand(&self, amount: f32) -> Rect {
+        Rect {
+            min: Vec2::new(self.min.x - amount, self.min.y - amount),
+            max: Vec2::new(self.max.x + amount, self.max.y + amount),
+        }
+    }
+}
+
+// 对选中内容的平移、缩放或旋转
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Transform {
+    Translate(Vec2),
+    Scale { origin: Vec2, x: f32, y: f32 },
+    Rotate { origin: Vec2, angle: f32 },
+}
+
+impl Transform {
+    pub fn apply(&self, p: Vec2) -> Vec2 {
+        match *self {
+            Transform::Translate(offset) => p + offset,
+            Transform::Scale { origin, x, y } => {
+                let d = p - origin;
+                origin + Vec2::new(d.x * x, d.y * y)
+            }
+            Transform::Rotate { origin, angle } => {
+                let (sin, cos) = angle.sin_cos();
+                let d = p - origin;
+                origin + Vec2::new(d.x * cos - d.y * sin, d.x * sin + d.y * cos)
+            }
+        }
+    }
+
+    // 线宽等一维尺寸的缩放比例
+    pub fn scale_factor(&self) -> f32 {
+        match *self {
+            Transform::Scale { x, y, .. } => (x * y).abs().sqrt(),
+            _ => 1.0,
+        }
+    }
+}
+
+// 点到线段的最短距离
+pub fn point_segment_distance(p: Vec2, a: Vec2, b: Vec2) -> f32 {
+    let ab = b - a;
+    let len_sq = ab.dot(ab);
+    if len_sq <= f32::EPSILON {
+        return p.distance(a);
+    }
+
+    let t = ((p - a).dot(ab) / len_sq).clamp(0.0, 1.0);
+    p.distance(a.lerp(b, t))
+}
+
+// 两条线段是否相交（含端点接触）
+pub fn segments_intersect(a0: Vec2, a1: Vec2, b0: Vec2, b1: Vec2) -> bool {
+    let d1 = (a1 - a0).cross(b0 - a0);
+    let d2 = (a1 - a0).cross(b1 - a0);
+    let d3 = (b1 - b0).cross(a0 - b0);
+    let d4 = (b1 - b0).cross(a1 - b0);
+
+    if ((d1 > 0.0 && d2 < 0.0) || (d1 < 0.0 && d2 > 0.0))
+        && ((d3 > 0.0 && d4 < 0.0) || (d3 < 0.0 && d4 > 0.0))
+    {
+        return true;
+    }
+
+    // 共线或端点落在另一条线段上的情况
+    (d1 == 0.0 && point_segment_distance(b0, a0, a1) <= f32::EPSILON)
+        || (d2 == 0.0 && point_segment_distance(b1, a0, a1) <= f32::EPSILON)
+        || (d3 == 0.0 && point_segment_distance(a0, b0, b1) <= f32::EPSILON)
+        || (d4 == 0.0 && point_segment_distance(a1, b0, b1) <= f32::EPSILON)
+}
+
+// 两条线段之间的最短距离
+pub fn segment_distance(a0: Vec2, a1: Vec2, b0: Vec2, b1: Vec2) -> f32 {
+    if segments_intersect(a0, a1, b0, b1) {
+        return 0.0;
+    }
+
+    point_segment_distance(a0, b0, b1)
+        .min(point_segment_distance(a1, b0, b1))
+        .min(point_segment_distance(b0, a0, a1))
+        .min(point_segment_distance(b1, a0, a1))
+}
+
+// 点到折线的最短距离，单点折线按点处理
+pub fn point_polyline_distance(p: Vec2, polyline: &[Vec2]) -> f32 {
+    match polyline {
+        [] => f32::INFINITY,
+        [only] => p.distance(*only),
+        _ => polyline
+            .windows(2)
+            .map(|w| point_segment_distance(p, w[0], w[1]))
+            .fold(f32::INFINITY, f32::min),
+    }
+}
+
+// 线段a→b上与折线距离不超过radius的部分，以参数t（0到1）表示，按顺序排列且互不重叠
+pub fn segment_polyline_ranges(a: Vec2, b: Vec2, polyline: &[Vec2], radius: f32) -> Vec<(f32, f32)> {
+    let mut ranges: Vec<(f32, f32)> = match polyline {
+        [] => Vec::new(),
+        [only] => circle_range(a, b, *only, radius).into_iter().collect(),
+        _ => polyline
+            .windows(2)
+            .filter_map(|w| capsule_range(a, b, w[0], w[1], radius))
+            .collect(),
+    };
+    ranges.sort_by(|x, y| x.0.total_cmp(&y.0));
+
+    let mut merged: Vec<(f32, f32)> = Vec::with_capacity(ranges.len());
+    for (start, end) in ranges {
+        match merged.last_mut() {
+            Some(last) if start <= last.1 => last.1 = last.1.max(end),
+            _ => merged.push((start, end)),
+        }
+    }
+    merged
+}
+
+// 线段c→d向外扩展radius得到的胶囊形是凸的，与线段a→b的交集是一个区间，
+// 等于两端的圆和中间的矩形各自交出的区间的并
+fn capsule_range(a: Vec2, b: Vec2, c: Vec2, d: Vec2, radius: f32) -> Option<(f32, f32)> {
+    let body = if c.distance(d) > f32::EPSILON { slab_range(a, b, c, d, radius) } else { None };
+    [circle_range(a, b, c, radius), circle_range(a, b, d, radius), body]
+        .into_iter()
+        .flatten()
+        .reduce(|x, y| (x.0.min(y.0), x.1.max(y.1)))
+}
+
+fn circle_range(a: Vec2, b: Vec2, center: Vec2, radius: f32) -> Option<(f32, f32)> {
+    let ab = b - a;
+    let offset = a - center;
+    let qa = ab.dot(ab);
+    let qc = offset.dot(offset) - radius * radius;
+    if qa <= f32::EPSILON {
+        return (qc <= 0.0).then_some((0.0, 1.0));
+    }
+
+    let qb = 2.0 * offset.dot(ab);
+    let discriminant = qb * qb - 4.0 * qa * qc;
+    if discriminant < 0.0 {
+        return None;
+    }
+    let root = discriminant.sqrt();
+    clip_unit((-qb - root) / (2.0 * qa), (-qb + root) / (2.0 * qa))
+}
+
+// 线段c→d两侧各radius宽的矩形
+fn slab_range(a: Vec2, b: Vec2, c: Vec2, d: Vec2, radius: f32) -> Option<(f32, f32)> {
+    let length = c.distance(d);
+    let axis = (d - c) * (1.0 / length);
+    let normal = Vec2::new(-axis.y, axis.x);
+    let (offset, ab) = (a - c, b - a);
+
+    let mut range = (0.0f32, 1.0f32);
+    for (start, delta, min, max) in [
+        (offset.dot(axis), ab.dot(axis), 0.0, length),
+        (offset.dot(normal), ab.dot(normal), -radius, radius),
+    ] {
+        if delta.abs() <= f32::EPSILON {
+            if start < min || start > max {
+                return None;
+            }
+            continue;
+        }
+        let (t0, t1) = ((min - start) / delta, (max - start) / delta);
+        range = (range.0.max(t0.min(t1)), range.1.min(t0.max(t1)));
+    }
+    clip_unit(range.0, range.1)
+}
+
+fn clip_unit(start: f32, end: f32) -> Option<(f32, f32)> {
+    let (start, end) = (start.max(0.0), end.min(1.0));
+    (start <= end).then_some((start, end))
+}
+
+// 两条折线之间的最短距离
+pub fn polyline_distance(a: &[Vec2], b: &[Vec2]) -> f32 {
+    if a.is_empty() || b.is_empty() {
+        return f32::INFINITY;
+    }
+
+    let segments_a: Vec<(Vec2, Vec2)> = segments(a);
+    let segments_b: Vec<(Vec2, Vec2)> = segments(b);
+
+    let mut best = f32::INFINITY;
+    for &(a0, a1) in &segments_a {
+        for &(b0, b1) in &segments_b {
+            best = best.min(segment_distance(a0, a1, b0, b1));
+            if best == 0.0 {
+                return best;
+            }
+        }
+    }
+    best
+}
+
+// Ramer–Douglas–Peucker折线简化，返回保留下来的点的索引（首尾总是保留）
+pub fn simplify_rdp(points: &[Vec2], tolerance: f32) -> Vec<usize> {
+    if points.len() <= 2 {
+        return (0..points.len()).collect();
+    }
+
+    let mut keep = vec![false; points.len()];
+    keep[0] = true;
+    keep[points.len() - 1] = true;
+
+    // 用显式栈代替递归，避免长笔画导致栈溢出
+    let mut stack = vec![(0, points.len() - 1)];
+    while let Some((first, last)) = stack.pop() {
+        let mut max_distance = 0.0;
+        let mut max_index = first;
+        for i in first + 1..last {
+            let distance = point_segment_distance(points[i], points[first], points[last]);
+            if distance > max_distance {
+                max_distance = distance;
+                max_index = i;
+            }
+        }
+
+        if max_distance > tolerance {
+            keep[max_index] = true;
+            stack.push((first, max_index));
+            stack.push((max_index, last));
+        }
+    }
+
+    (0..points.len()).filter(|&i| keep[i]).collect()
+}
+
+// 射线法判断点是否在多边形内（多边形首尾自动闭合）
+pub fn point_in_polygon(p: Vec2, polygon: &[Vec2]) -> bool {
+    if polygon.len() < 3 {
+        return false;
+    }
+
+    let mut inside = false;
+    let mut previous = polygon[polygon.len() - 1];
+    for &current in polygon {
+        if (current.y > p.y) != (previous.y > p.y) {
+            let x = current.x + (p.y - current.y) / (previous.y - current.y) * (previous.x - current.x);
+            if p.x < x {
+                inside = !inside;
+            }
+        }
+        previous = current;
+    }
+    inside
+}
+
+// 耳切法三角剖分，返回顶点索引，凹多边形也能正确填充；
+// 自相交或退化的多边形找不到耳朵时，剩下的部分按扇形补齐
+pub fn triangulate_polygon(polygon: &[Vec2]) -> Vec<[usize; 3]> {
+    let count = polygon.len();
+    if count < 3 {
+        return Vec::new();
+    }
+    let area: f32 = (0..count).map(|i| polygon[i].cross(polygon[(i + 1) % count])).sum();
+    let orientation = area.signum();
+
+    let is_ear = |remaining: &[usize], i: usize| {
+        let n = remaining.len();
+        let (a, b, c) = (remaining[(i + n - 1) % n], remaining[i], remaining[(i + 1) % n]);
+        let (pa, pb, pc) = (polygon[a], polygon[b], polygon[c]);
+        if (pb - pa).cross(pc - pb) * orientation <= 0.0 {
+            return false;
+        }
+        // 其余顶点都不能落在这个三角形内部
+        let inside = |p: Vec2| {
+            (pb - pa).cross(p - pa) * orientation > 0.0
+                && (pc - pb).cross(p - pb) * orientation > 0.0
+                && (pa - pc).cross(p - pc) * orientation > 0.0
+        };
+        !remaining.iter().any(|&j| j != a && j != b && j != c && inside(polygon[j]))
+    };
+
+    let mut remaining: Vec<usize> = (0..count).collect();
+    let mut triangles = Vec::with_capacity(count - 2);
+    while remaining.len() > 3 {
+        let n = remaining.len();
+        match (0..n).find(|&i| is_ear(&remaining, i)) {
+            Some(i) => {
+                triangles.push([remaining[(i + n - 1) % n], remaining[i], remaining[(i + 1) % n]]);
+                remaining.remove(i);
+            }
+            None => break,
+        }
+    }
+    for i in 1..remaining.len() - 1 {
+        triangles.push([remaining[0], remaining[i], remaining[i + 1]]);
+    }
+    triangles
+}
+
+// 单点折线退化为零长度线段
+fn segments(polyline: &[Vec2]) -> Vec<(Vec2, Vec2)> {
+    if polyline.len() == 1 {
+        return vec![(polyline[0], polyline[0])];
+    }
+    polyline.windows(2).map(|w| (w[0], w[1])).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_point_segment_distance() {
+        let a = Vec2::new(0.0, 0.0);
+        let b = Vec2::new(10.0, 0.0);
+
+        assert_eq!(point_segment_distance(Vec2::new(5.0, 3.0), a, b), 3.0);
+        assert_eq!(point_segment_distance(Vec2::new(-4.0, 3.0), a, b), 5.0);
+        assert_eq!(point_segment_distance(Vec2::new(2.0, 2.0), a, a), 8.0_f32.sqrt());
+    }
+
+    #[test]
+    fn test_segment_polyline_ranges() {
+        let (a, b) = (Vec2::new(0.0, 0.0), Vec2::new(100.0, 0.0));
+        // 竖直的橡皮擦路径在x=50处横穿，半径5
+        let ranges = segment_polyline_ranges(a, b, &[Vec2::new(50.0, -20.0), Vec2::new(50.0, 20.0)], 5.0);
+        assert_eq!(ranges.len(), 1);
+        assert!((ranges[0].0 - 0.45).abs() < 1e-5 && (ranges[0].1 - 0.55).abs() < 1e-5);
+
+        // 单点路径按圆处理；两段重叠的区间合并
+        let ranges = segment_polyline_ranges(a, b, &[Vec2::new(0.0, 3.0)], 5.0);
+        assert_eq!(ranges.len(), 1);
+        assert!(ranges[0].0 == 0.0 && (ranges[0].1 - 0.04).abs() < 1e-5);
+        let ranges = segment_polyline_ranges(a, b, &[Vec2::new(20.0, 0.0), Vec2::new(30.0, 0.0), Vec2::new(30.0, 20.0)], 1.0);
+        assert_eq!(ranges.len(), 1);
+        assert!((ranges[0].0 - 0.19).abs() < 1e-5 && (ranges[0].1 - 0.31).abs() < 1e-5);
+
+        assert!(segment_polyline_ranges(a, b, &[Vec2::new(50.0, 10.0)], 5.0).is_empty());
+    }
+
+    #[test]
+    fn test_segments_intersect() {
+        let cross = segments_intersect(
+            Vec2::new(0.0, 0.0), Vec2::new(10.0, 10.0),
+            Vec2::new(0.0, 10.0), Vec2::new(10.0, 0.0),
+        );
+        let parallel = segments_intersect(
+            Vec2::new(0.0, 0.0), Vec2::new(10.0, 0.0),
+            Vec2::new(0.0, 1.0), Vec2::new(10.0, 1.0),
+        );
+        let touching = segments_intersect(
+            Vec2::new(0.0, 0.0), Vec2::new(10.0, 0.0),
+            Vec2::new(5.0, 0.0), Vec2::new(5.0, 5.0),
+        );
+
+        assert!(cross);
+        assert!(!parallel);
+        assert!(touching);
+    }
+
+    #[test]
+    fn test_polyline_distance() {
+        let horizontal = [Vec2::new(0.0, 0.0), Vec2::new(10.0, 0.0)];
+        let above = [Vec2::new(0.0, 4.0), Vec2::new(10.0, 4.0)];
+        let crossing = [Vec2::new(5.0, -5.0), Vec2::new(5.0, 5.0)];
+
+        assert_eq!(polyline_distance(&horizontal, &above), 4.0);
+        assert_eq!(polyline_distance(&horizontal, &crossing), 0.0);
+        assert_eq!(polyline_distance(&horizontal, &[Vec2::new(3.0, 2.0)]), 2.0);
+    }
+
+    #[test]
+    fn test_point_in_polygon() {
+        // 凹的L形
+        let polygon = [
+            Vec2::new(0.0, 0.0), Vec2::new(10.0, 0.0), Vec2::new(10.0, 4.0),
+            Vec2::new(4.0, 4.0), Vec2::new(4.0, 10.0), Vec2::new(0.0, 10.0),
+        ];
+
+        assert!(point_in_polygon(Vec2::new(2.0, 8.0), &polygon));
+        assert!(point_in_polygon(Vec2::new(8.0, 2.0), &polygon));
+        assert!(!point_in_polygon(Vec2::new(8.0, 8.0), &polygon));
+        assert!(!point_in_polygon(Vec2::new(-1.0, 2.0), &polygon));
+    }
+
+    #[test]
+    fn test_simplify_rdp() {
+        let points = [
+            Vec2::new(0.0, 0.0),
+            Vec2::new(5.0, 0.1),
+            Vec2::new(10.0, 0.0),
+            Vec2::new(10.0, 5.0),
+            Vec2::new(10.1, 10.0),
+        ];
+
+        assert_eq!(simplify_rdp(&points, 0.5), vec![0, 2, 4]);
+        assert_eq!(simplify_rdp(&points, 0.01), vec![0, 1, 2, 3, 4]);
+    }
+
+    #[test]
+    fn test_triangulate_concave_polygon() {
+        let polygon = [
+            Vec2::new(0.0, 0.0), Vec2::new(10.0, 0.0), Vec2::new(10.0, 4.0),
+            Vec2::new(4.0, 4.0), Vec2::new(4.0, 10.0), Vec2::new(0.0, 10.0),
+        ];
+        let triangles = triangulate_polygon(&polygon);
+        assert_eq!(triangles.len(), 4);
+
+        // 三角形面积之和等于L形的面积，并且都在多边形内
+        let mut area = 0.0;
+        for [a, b, c] in triangles {
+            let (pa, pb, pc) = (polygon[a], polygon[b], polygon[c]);
+            area += (pb - pa).cross(pc - pa).abs() / 2.0;
+            let centroid = (pa + pb + pc) * (1.0 / 3.0);
+            assert!(point_in_polygon(centroid, &polygon));
+        }
+        assert!((area - 64.0).abs() < 1e-3);
+
+        assert!(triangulate_polygon(&polygon[..2]).is_empty());
+    }
+}
@@ -0,0 +1,418 @@
+use crate::geometry::Vec2;
+use crate::note::{Background, Element, Layer, LinkRegion, Note, OutlineEntry, Page, ShapeKind, Stroke};
+use std::collections::VecDeque;
+use std::mem::size_of;
+
+// 撤销历史默认占用的内存上限（字节）
+const DEFAULT_MEMORY_LIMIT: usize = 32 * 1024 * 1024;
+
+// 对笔记的一次可撤销修改
+#[derive(Debug, Clone)]
+pub enum Command {
+    AddStroke {
+        page_index: usize,
+        layer_index: usize,
+        stroke_index: usize,
+        stroke: Stroke,
+    },
+    AddElement {
+        page_index: usize,
+        layer_index: usize,
+        element_index: usize,
+        element: Element,
+    },
+    InsertPage {
+        page_index: usize,
+        page: Page,
+        previous_page: usize,
+    },
+    RemovePage {
+        page_index: usize,
+        page: Page,
+        previous_page: usize,
+    },
+    // 连续的多页插入、删除和移动
+    InsertPages {
+        page_index: usize,
+        pages: Vec<Page>,
+        previous_page: usize,
+    },
+    RemovePages {
+        page_index: usize,
+        pages: Vec<Page>,
+        previous_page: usize,
+    },
+    // 把from开始的count页移动到to，to是移动后第一页所在的位置
+    MovePages {
+        from: usize,
+        count: usize,
+        to: usize,
+    },
+    SetBackground {
+        page_index: usize,
+        old: Background,
+        new: Background,
+    },
+    // 通用的整页替换，用于没有专门命令的复杂编辑
+    ReplacePage {
+        page_index: usize,
+        before: Page,
+        after: Page,
+    },
+    SetOutline {
+        old: Vec<OutlineEntry>,
+        new: Vec<OutlineEntry>,
+    },
+}
+
+impl Command {
+    pub fn apply(&self, note: &mut Note) {
+        match self {
+            Command::AddStroke { page_index, layer_index, stroke_index, stroke } => {
+                if let Some(layer) = note.pages.get_mut(*page_index)
+                    .and_then(|page| page.layers.get_mut(*layer_index))
+                {
+                    layer.insert_stroke(*stroke_index, stroke.clone());
+                }
+            }
+            Command::AddElement { page_index, layer_index, element_index, element } => {
+                if let Some(layer) = note.pages.get_mut(*page_index)
+                    .and_then(|page| page.layers.get_mut(*layer_index))
+                {
+                    layer.elements.insert(*element_index, element.clone());
+                }
+            }
+            Command::InsertPage { page_index, page, .. } => {
+                note.pages.insert(*page_index, page.clone());
+                note.current_page = *page_index;
+            }
+            Command::RemovePage { page_index, .. } => {
+                note.pages.remove(*page_index);
+                if note.current_page >= *page_index && note.current_page > 0 {
+                    note.current_page -= 1;
+                }
+            }
+            Command::InsertPages { page_index, pages, .. } => {
+                note.pages.splice(*page_index..*page_index, pages.iter().cloned());
+                note.current_page = *page_index;
+            }
+            Command::RemovePages { page_index, pages, .. } => {
+                let end = *page_index + pages.len();
+                note.pages.drain(*page_index..end);
+                // 与删除单页一致：当前页被删除时退到前一页
+                if note.current_page >= end {
+                    note.current_page -= pages.len();
+                } else if note.current_page >= *page_index {
+                    note.current_page = page_index.saturating_sub(1);
+                }
+            }
+            Command::MovePages { from, count, to } => move_pages(note, *from, *count, *to),
+            Command::SetBackground { page_index, new, .. } => {
+                if let Some(page) = note.pages.get_mut(*page_index) {
+                    page.background = new.clone();
+                }
+            }
+            Command::ReplacePage { page_index, after, .. } => {
+                if let Some(page) = note.pages.get_mut(*page_index) {
+                    *page = after.clone();
+                }
+            }
+            Command::SetOutline { new, .. } => note.outline = new.clone(),
+        }
+    }
+
+    pub fn revert(&self, note: &mut Note) {
+        match self {
+            Command::AddStroke { page_index, layer_index, stroke_index, .. } => {
+                if let Some(layer) = note.pages.get_mut(*page_index)
+                    .and_then(|page| page.layers.get_mut(*layer_index))
+                {
+                    layer.remove_stroke(*stroke_index);
+                }
+            }
+            Command::AddElement { page_index, layer_index, element_index, .. } => {
+                if let Some(layer) = note.pages.get_mut(*page_index)
+                    .and_then(|page| page.layers.get_mut(*layer_index))
+                {
+                    if *element_index < layer.elements.len() {
+                        layer.elements.remove(*element_index);
+                    }
+                }
+            }
+            Command::InsertPage { page_index, previous_page, .. } => {
+                note.pages.remove(*page_index);
+                note.current_page = *previous_page;
+            }
+            Command::RemovePage { page_index, page, previous_page } => {
+                note.pages.insert(*page_index, page.clone());
+                note.current_page = *previous_page;
+            }
+            Command::InsertPages { page_index, pages, previous_page } => {
+                note.pages.drain(*page_index..*page_index + pages.len());
+                note.current_page = *previous_page;
+            }
+            Command::RemovePages { page_index, pages, previous_page } => {
+                note.pages.splice(*page_index..*page_index, pages.iter().cloned());
+                note.current_page = *previous_page;
+            }
+            Command::MovePages { from, count, to } => move_pages(note, *to, *count, *from),
+            Command::SetBackground { page_index, old, .. } => {
+                if let Some(page) = note.pages.get_mut(*page_index) {
+                    page.background = old.clone();
+                }
+            }
+            Command::ReplacePage { page_index, before, .. } => {
+                if let Some(page) = note.pages.get_mut(*page_index) {
+                    *page = before.clone();
+                }
+            }
+            Command::SetOutline { old, .. } => note.outline = old.clone(),
+        }
+    }
+
+    // 粗略估算命令占用的内存，用于限制历史大小
+    fn estimated_size(&self) -> usize {
+        let payload = match self {
+            Command::AddStroke { stroke, .. } => stroke_size(stroke),
+            Command::AddElement { element, .. } => element_size(element),
+            Command::InsertPage { page, .. } | Command::RemovePage { page, .. } => page_size(page),
+            Command::InsertPages { pages, .. } | Command::RemovePages { pages, .. } => {
+                pages.iter().map(|page| size_of::<Page>() + page_size(page)).sum()
+            }
+            Command::MovePages { .. } => 0,
+            Command::SetBackground { old, new, .. } => background_size(old) + background_size(new),
+            Command::ReplacePage { before, after, .. } => page_size(before) + page_size(after),
+            Command::SetOutline { old, new } => outline_size(old) + outline_size(new),
+        };
+        size_of::<Command>() + payload
+    }
+}
+
+// 移动一段页面，当前页跟随所在的页面移动
+fn move_pages(note: &mut Note, from: usize, count: usize, to: usize) {
+    let moved: Vec<Page> = note.pages.drain(from..from + count).collect();
+    note.pages.splice(to..to, moved);
+    
+    let current = note.current_page;
+    note.current_page = if (from..from + count).contains(&current) {
+        to + (current - from)
+    } else {
+        // 先按移出后的位置计算，再按插入的位置调整
+        let without = if current >= from + count { current - count } else { current };
+        if without >= to { without + count } else { without }
+    };
+}
+
+fn stroke_size(stroke: &Stroke) -> usize {
+    stroke.points.capacity() * size_of::<crate::note::Point>()
+}
+
+fn element_size(element: &Element) -> usize {
+    let payload = match element {
+        Element::Text(text) => {
+            text.id.capacity() + text.content.capacity()
+        }
+        Element::Image(image) => image.id.capacity() + image.asset.capacity(),
+        Element::Shape(shape) => {
+            let points = match &shape.kind {
+                ShapeKind::Polygon { points } => points.capacity() * size_of::<Vec2>(),
+                _ => 0,
+            };
+            shape.id.capacity() + points
+        }
+    };
+    size_of::<Element>() + payload
+}
+
+fn outline_size(entries: &[OutlineEntry]) -> usize {
+    entries.iter()
+        .map(|entry| size_of::<OutlineEntry>() + entry.title.capacity() + entry.page_id.capacity() + outline_size(&entry.children))
+        .sum()
+}
+
+fn page_size(page: &Page) -> usize {
+    page.layers.iter()
+        .map(|layer| size_of::<Layer>() + layer.name.capacity())
+        .sum::<usize>()
+        + page.strokes().map(|s| size_of::<Stroke>() + stroke_size(s)).sum::<usize>()
+        + page.elements().map(element_size).sum::<usize>()
+        + background_size(&page.background)
+        + page.id.capacity()
+        + page.links.capacity() * size_of::<LinkRegion>()
+}
+
+fn background_size(background: &Background) -> usize {
+    match background {
+        Background::Pdf { file_path, .. } => file_path.capacity(),
+        _ => 0,
+    }
+}
+
+// 撤销/重做栈，超过内存上限时丢弃最早的记录
+#[derive(Debug, Clone)]
+pub struct History {
+    undo_stack: VecDeque<Command>,
+    redo_stack: Vec<Command>,
+    memory_limit: usize,
+    memory_used: usize,
+}
+
+impl Default for History {
+    fn default() -> Self {
+        Self::with_memory_limit(DEFAULT_MEMORY_LIMIT)
+    }
+}
+
+impl History {
+    pub fn with_memory_limit(memory_limit: usize) -> Self {
+        Self {
+            undo_stack: VecDeque::new(),
+            redo_stack: Vec::new(),
+            memory_limit,
+            memory_used: 0,
+        }
+    }
+
+    pub fn set_memory_limit(&mut self, memory_limit: usize) {
+        self.memory_limit = memory_limit;
+        self.enforce_limit();
+    }
+
+    pub fn can_undo(&self) -> bool {
+        !self.undo_stack.is_empty()
+    }
+
+    pub fn can_redo(&self) -> bool {
+        !self.redo_stack.is_empty()
+    }
+
+    pub fn clear(&mut self) {
+        self.undo_stack.clear();
+        self.redo_stack.clear();
+        self.memory_used = 0;
+    }
+
+    // 记录一条新执行的命令，同时清空重做栈
+    pub fn push(&mut self, command: Command) {
+        for dropped in self.redo_stack.drain(..) {
+            self.memory_used -= dropped.estimated_size();
+        }
+        self.memory_used += command.estimated_size();
+        self.undo_stack.push_back(command);
+        self.enforce_limit();
+    }
+
+    pub fn pop_undo(&mut self) -> Option<Command> {
+        let command = self.undo_stack.pop_back()?;
+        self.memory_used -= command.estimated_size();
+        Some(command)
+    }
+
+    pub fn pop_redo(&mut self) -> Option<Command> {
+        let command = self.redo_stack.pop()?;
+        self.memory_used -= command.estimated_size();
+        Some(command)
+    }
+
+    pub fn push_undo(&mut self, command: Command) {
+        self.memory_used += command.estimated_size();
+        self.undo_stack.push_back(command);
+        self.enforce_limit();
+    }
+
+    pub fn push_redo(&mut self, command: Command) {
+        self.memory_used += command.estimated_size();
+        self.redo_stack.push(command);
+    }
+
+    fn enforce_limit(&mut self) {
+        // 至少保留最近一条记录，保证刚做的操作总能撤销
+        while self.memory_used > self.memory_limit && self.undo_stack.len() > 1 {
+            if let Some(dropped) = self.undo_stack.pop_front() {
+                self.memory_used -= dropped.estimated_size();
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::note::{Color, Point, StrokeTool};
+
+    fn dot_stroke(x: f32) -> Stroke {
+        Stroke {
+            points: vec![Point { x, y: 10.0, pressure: 0.5, timestamp: 0, tilt: None }],
+            color: Color::BLACK,
+            thickness: 2.0,
+            tool: StrokeTool::Pen,
+            link: None,
+            fitted: Default::default(),
+        }
+    }
+
+    fn add_stroke(note: &mut Note, x: f32) {
+        let stroke_index = note.pages[0].layers[0].strokes.len();
+        note.execute(Command::AddStroke { page_index: 0, layer_index: 0, stroke_index, stroke: dot_stroke(x) });
+    }
+
+    fn stroke_xs(note: &Note) -> Vec<f32> {
+        note.pages[0].strokes().map(|stroke| stroke.points[0].x).collect()
+    }
+
+    #[test]
+    fn test_execute_undo_redo() {
+        let mut note = Note::new("test".to_string());
+        add_stroke(&mut note, 1.0);
+        add_stroke(&mut note, 2.0);
+        note.execute(Command::SetBackground {
+            page_index: 0,
+            old: Background::Blank,
+            new: Background::Lined { spacing: 30.0, style: Default::default() },
+        });
+        assert_eq!(stroke_xs(&note), vec![1.0, 2.0]);
+
+        assert!(note.undo());
+        assert!(matches!(note.pages[0].background, Background::Blank));
+        assert!(note.undo());
+        assert_eq!(stroke_xs(&note), vec![1.0]);
+        assert!(note.can_redo());
+
+        assert!(note.redo());
+        assert_eq!(stroke_xs(&note), vec![1.0, 2.0]);
+        assert!(note.redo());
+        assert!(matches!(note.pages[0].background, Background::Lined { .. }));
+        assert!(!note.redo());
+
+        assert!(note.undo() && note.undo() && note.undo());
+        assert!(stroke_xs(&note).is_empty());
+        assert!(!note.undo());
+    }
+
+    #[test]
+    fn test_new_edit_clears_redo() {
+        let mut note = Note::new("test".to_string());
+        add_stroke(&mut note, 1.0);
+        add_stroke(&mut note, 2.0);
+        assert!(note.undo());
+        assert!(note.can_redo());
+
+        add_stroke(&mut note, 3.0);
+        assert!(!note.can_redo());
+        assert!(!note.redo());
+        assert_eq!(stroke_xs(&note), vec![1.0, 3.0]);
+        assert_eq!(note.history.memory_used, note.history.undo_stack.iter().map(Command::estimated_size).sum::<usize>());
+    }
+
+    #[test]
+    fn test_memory_limit_drops_oldest() {
+        let mut history = History::with_memory_limit(0);
+        let command = |x| Command::AddStroke { page_index: 0, layer_index: 0, stroke_index: 0, stroke: dot_stroke(x) };
+        history.push(command(1.0));
+        history.push(command(2.0));
+        // 超出上限时只保留最近一条
+        assert!(matches!(history.pop_undo(), Some(Command::AddStroke { stroke, .. }) if stroke.points[0].x == 2.0));
+        assert!(!history.can_undo());
+        assert_eq!(history.memory_used, 0);
+    }
+}
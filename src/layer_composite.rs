@@ -0,0 +1,256 @@
+// 半透明图层的合成：图层中的笔画和图形先在CPU上栅格化成一张图（图层内按正常不透明度叠加），
+// 再把整张图按图层不透明度画到画布上，同一图层内重叠的笔画不会因为逐笔半透明而叠加变深。
+// 栅格化在页面坐标中进行，结果按(页面, 图层)缓存；平移只移动绘制的位置，
+// 笔记内容或屏幕缩放变化、或可见范围移出已合成的区域时才重新栅格化
+// 复制到系统剪贴板的图片也用这里的栅格化，文字和图片从纹理中采样
+use eframe::egui;
+use egui::epaint::{Mesh, Tessellator, TessellationOptions};
+use std::collections::HashMap;
+
+// 最多保留的图层纹理数，超出时丢弃最久未使用的
+const MAX_CACHED_LAYERS: usize = 8;
+// 合成区域在可见范围外多留的边距（点），并按同样的间隔对齐，小范围平移不必重新栅格化
+const COMPOSITE_MARGIN: f32 = 128.0;
+
+// 决定缓存是否仍然有效的条件（可见范围另外判断）
+#[derive(Debug, Clone, PartialEq)]
+pub struct CompositeKey {
+    pub revision: u64,
+    pub pixels_per_point: f32,
+}
+
+struct CachedLayer {
+    key: CompositeKey,
+    // 已合成的页面坐标范围
+    area: egui::Rect,
+    texture: egui::TextureHandle,
+    last_used: u64,
+}
+
+#[derive(Default)]
+pub struct LayerCompositor {
+    layers: HashMap<(String, usize), CachedLayer>,
+    frame: u64,
+}
+
+impl LayerCompositor {
+    // 每帧调用一次，淘汰多余的纹理
+    pub fn update(&mut self) {
+        self.frame += 1;
+        while self.layers.len() > MAX_CACHED_LAYERS {
+            let oldest = self.layers.iter()
+                .min_by_key(|(_, layer)| layer.last_used)
+                .map(|(key, _)| key.clone());
+            match oldest {
+                Some(key) => self.layers.remove(&key),
+                None => break,
+            };
+        }
+    }
+
+    // 合成后的图层纹理及其覆盖的页面坐标范围。visible为需要显示的页面坐标范围；
+    // 内容没变且visible仍在已合成的范围内时直接使用缓存，shapes（页面坐标）只在需要重新栅格化时调用
+    pub fn texture(
+        &mut self,
+        ctx: &egui::Context,
+        page_id: &str,
+        layer_index: usize,
+        key: CompositeKey,
+        visible: egui::Rect,
+        shapes: impl FnOnce() -> Vec<egui::Shape>,
+    ) -> (egui::TextureId, egui::Rect) {
+        let frame = self.frame;
+        let cache_key = (page_id.to_string(), layer_index);
+        match self.layers.get_mut(&cache_key) {
+            Some(layer) if layer.key == key && layer.area.contains_rect(visible) => {
+                layer.last_used = frame;
+                (layer.texture.id(), layer.area)
+            }
+            _ => {
+                let area = composite_area(visible);
+                let image = rasterize(shapes(), area, key.pixels_per_point);
+                let name = format!("layer/{}/{}", page_id, layer_index);
+                let texture = ctx.load_texture(name, image, egui::TextureOptions::LINEAR);
+                let id = texture.id();
+                self.layers.insert(cache_key, CachedLayer { key, area, texture, last_used: frame });
+                (id, area)
+            }
+        }
+    }
+}
+
+// 可见范围加上边距后向外对齐到COMPOSITE_MARGIN的整数倍
+pub fn composite_area(visible: egui::Rect) -> egui::Rect {
+    let snap = |value: f32, round: fn(f32) -> f32| round(value / COMPOSITE_MARGIN) * COMPOSITE_MARGIN;
+    egui::Rect::from_min_max(
+        egui::Pos2::new(snap(visible.left() - COMPOSITE_MARGIN, f32::floor), snap(visible.top() - COMPOSITE_MARGIN, f32::floor)),
+        egui::Pos2::new(snap(visible.right() + COMPOSITE_MARGIN, f32::ceil), snap(visible.bottom() + COMPOSITE_MARGIN, f32::ceil)),
+    )
+}
+
+// 合成图在屏幕上的位置按物理像素取整，避免平移到非整像素位置时被重新采样而发虚
+pub fn screen_rect(area: egui::Rect, origin: egui::Pos2, pixels_per_point: f32) -> egui::Rect {
+    let min = ((origin + area.min.to_vec2()).to_vec2() * pixels_per_point).round() / pixels_per_point;
+    egui::Rect::from_min_size(min.to_pos2(), area.size())
+}
+
+// 把图形栅格化到area范围内，返回预乘透明度的图像。只支持纯色的图形（不含文字和图片）
+pub fn rasterize(shapes: Vec<egui::Shape>, area: egui::Rect, pixels_per_point: f32) -> egui::ColorImage {
+    rasterize_textured(shapes, area, pixels_per_point, &HashMap::new())
+}
+
+// 同rasterize，文字和图片从textures中采样：文字使用TextureId::default()对应的字体图集，
+// 没有提供的纹理按白色处理
+pub fn rasterize_textured(
+    shapes: Vec<egui::Shape>,
+    area: egui::Rect,
+    pixels_per_point: f32,
+    textures: &HashMap<egui::TextureId, egui::ColorImage>,
+) -> egui::ColorImage {
+    let width = (area.width() * pixels_per_point).round().max(1.0) as usize;
+    let height = (area.height() * pixels_per_point).round().max(1.0) as usize;
+
+    // 与屏幕上相同的细分和抗锯齿
+    let font_tex_size = textures.get(&egui::TextureId::default()).map_or([1, 1], |atlas| atlas.size);
+    let mut tessellator = Tessellator::new(pixels_per_point, TessellationOptions::default(), font_tex_size, Vec::new());
+
+    let mut pixels = vec![[0.0f32; 4]; width * height];
+    for shape in shapes {
+        // 每个图形单独细分，网格的纹理各不相同
+        let mut mesh = Mesh::default();
+        tessellator.tessellate_shape(shape, &mut mesh);
+        let texture = textures.get(&mesh.texture_id);
+        for triangle in mesh.indices.chunks_exact(3) {
+            let corner = |index: u32| {
+                let vertex = &mesh.vertices[index as usize];
+                Corner {
+                    position: ((vertex.pos - area.min) * pixels_per_point).to_pos2(),
+                    color: vertex.color.to_array().map(|c| c as f32 / 255.0),
+                    uv: vertex.uv,
+                }
+            };
+            fill_triangle(&mut pixels, width, height, [corner(triangle[0]), corner(triangle[1]), corner(triangle[2])], texture);
+        }
+    }
+
+    let bytes: Vec<u8> = pixels.iter()
+        .flat_map(|pixel| pixel.map(|c| (c.clamp(0.0, 1.0) * 255.0).round() as u8))
+        .collect();
+    egui::ColorImage::from_rgba_premultiplied([width, height], &bytes)
+}
+
+// 三角形的顶点：像素坐标、预乘透明度的颜色和纹理坐标
+struct Corner {
+    position: egui::Pos2,
+    color: [f32; 4],
+    uv: egui::Pos2,
+}
+
+// 以像素中心采样填充三角形，顶点颜色和纹理坐标按重心坐标插值，预乘透明度下做source-over混合
+fn fill_triangle(pixels: &mut [[f32; 4]], width: usize, height: usize, corners: [Corner; 3], texture: Option<&egui::ColorImage>) {
+    let [a, b, c] = [corners[0].position, corners[1].position, corners[2].position];
+    let edge = |from: egui::Pos2, to: egui::Pos2, p: egui::Pos2| (to - from).x * (p - from).y - (to - from).y * (p - from).x;
+    let area = edge(a, b, c);
+    if area.abs() <= f32::EPSILON {
+        return;
+    }
+
+    let columns = a.x.min(b.x).min(c.x).floor().max(0.0) as usize..(a.x.max(b.x).max(c.x).ceil().max(0.0) as usize).min(width);
+    let rows = a.y.min(b.y).min(c.y).floor().max(0.0) as usize..(a.y.max(b.y).max(c.y).ceil().max(0.0) as usize).min(height);
+    for y in rows {
+        for x in columns.clone() {
+            let p = egui::Pos2::new(x as f32 + 0.5, y as f32 + 0.5);
+            let weights = [edge(b, c, p) / area, edge(c, a, p) / area, edge(a, b, p) / area];
+            if weights.iter().any(|w| *w < 0.0) {
+                continue;
+            }
+
+            let mut source = [0.0f32; 4];
+            for (channel, value) in source.iter_mut().enumerate() {
+                *value = corners.iter().zip(weights).map(|(corner, w)| w * corner.color[channel]).sum();
+            }
+            if let Some(texture) = texture {
+                let uv = corners.iter().zip(weights).fold(egui::Vec2::ZERO, |uv, (corner, w)| uv + corner.uv.to_vec2() * w);
+                let texel = sample(texture, uv);
+                for (value, t) in source.iter_mut().zip(texel) {
+                    *value *= t;
+                }
+            }
+
+            let pixel = &mut pixels[y * width + x];
+            let alpha = source[3];
+            for channel in 0..4 {
+                pixel[channel] = source[channel] + pixel[channel] * (1.0 - alpha);
+            }
+        }
+    }
+}
+
+// 最近邻采样，uv为0到1之间的归一化坐标
+fn sample(texture: &egui::ColorImage, uv: egui::Vec2) -> [f32; 4] {
+    let [width, height] = texture.size;
+    let x = ((uv.x * width as f32) as usize).min(width.saturating_sub(1));
+    let y = ((uv.y * height as f32) as usize).min(height.saturating_sub(1));
+    texture.pixels.get(y * width + x).map_or([1.0; 4], |texel| texel.to_array().map(|c| c as f32 / 255.0))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_overlapping_strokes_do_not_darken() {
+        let area = egui::Rect::from_min_size(egui::Pos2::ZERO, egui::Vec2::new(20.0, 10.0));
+        let ink = egui::Color32::from_rgb(0, 0, 200);
+        let shapes = vec![
+            egui::Shape::rect_filled(egui::Rect::from_min_max(egui::Pos2::new(0.0, 0.0), egui::Pos2::new(12.0, 10.0)), 0.0, ink),
+            egui::Shape::rect_filled(egui::Rect::from_min_max(egui::Pos2::new(8.0, 0.0), egui::Pos2::new(20.0, 10.0)), 0.0, ink),
+        ];
+        let image = rasterize(shapes, area, 1.0);
+        assert_eq!(image.size, [20, 10]);
+
+        // 重叠部分与单独一笔的颜色相同，整张图按图层不透明度合成时也就一致
+        let single = image[(3, 5)];
+        let overlap = image[(10, 5)];
+        assert_eq!(single, overlap);
+        assert_eq!(single.a(), 255);
+        assert_eq!(single.b(), 200);
+
+        let empty = rasterize(Vec::new(), area, 2.0);
+        assert_eq!(empty.size, [40, 20]);
+        assert!(empty.pixels.iter().all(|pixel| *pixel == egui::Color32::TRANSPARENT));
+    }
+
+    #[test]
+    fn test_composite_area_and_screen_rect() {
+        let visible = egui::Rect::from_min_max(egui::Pos2::new(-10.0, 300.0), egui::Pos2::new(500.0, 700.0));
+        let area = composite_area(visible);
+        assert_eq!(area, egui::Rect::from_min_max(egui::Pos2::new(-256.0, 128.0), egui::Pos2::new(640.0, 896.0)));
+        // 平移不超过边距时可见范围仍在已合成的区域内
+        assert!(area.contains_rect(visible.translate(egui::Vec2::new(100.0, -100.0))));
+        assert!(!area.contains_rect(visible.translate(egui::Vec2::new(300.0, 0.0))));
+
+        let screen = screen_rect(area, egui::Pos2::new(10.3, 20.0), 2.0);
+        assert_eq!(screen.min, egui::Pos2::new(-245.5, 148.0));
+        assert_eq!(screen.size(), area.size());
+    }
+
+    #[test]
+    fn test_textured_mesh_samples_texture() {
+        let area = egui::Rect::from_min_size(egui::Pos2::ZERO, egui::Vec2::new(4.0, 2.0));
+        let texture_id = egui::TextureId::User(1);
+        // 左半红色、右半透明的2x1纹理
+        let texture = egui::ColorImage::from_rgba_unmultiplied([2, 1], &[255, 0, 0, 255, 0, 0, 0, 0]);
+        let mut mesh = Mesh::with_texture(texture_id);
+        mesh.add_rect_with_uv(area, egui::Rect::from_min_max(egui::Pos2::ZERO, egui::Pos2::new(1.0, 1.0)), egui::Color32::WHITE);
+
+        let textures = HashMap::from([(texture_id, texture)]);
+        let image = rasterize_textured(vec![egui::Shape::mesh(mesh.clone())], area, 1.0, &textures);
+        assert_eq!(image[(0, 0)], egui::Color32::from_rgb(255, 0, 0));
+        assert_eq!(image[(3, 1)], egui::Color32::TRANSPARENT);
+
+        // 没有提供纹理时按白色填充
+        let image = rasterize(vec![egui::Shape::mesh(mesh)], area, 1.0);
+        assert_eq!(image[(3, 1)], egui::Color32::WHITE);
+    }
+}
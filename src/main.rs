@@ -7,8 +7,21 @@ use tauri::Manager;
 use std::sync::Mutex;
 use tokio::runtime::Runtime;
 
+mod clipboard;
+mod geometry;
+mod history;
+mod layer_composite;
+mod markdown;
+mod migration;
 mod note;
+mod outline;
+mod recognizer;
+mod selection;
+mod smoothing;
+mod spatial;
+mod template;
 mod pdf;
+mod pdf_render;
 mod ui;
 mod storage;
 mod server;
@@ -36,7 +49,43 @@ fn main() {
             load_note,
             export_pdf,
             import_pdf,
-            get_notes_list
+            get_notes_list,
+            get_all_tags,
+            get_note_metadata,
+            set_note_metadata,
+            get_folder_tree,
+            create_folder,
+            rename_folder,
+            move_folder,
+            delete_folder,
+            move_note_to_folder,
+            undo,
+            redo,
+            add_layer,
+            remove_layer,
+            set_active_layer,
+            set_layer_visible,
+            set_layer_locked,
+            set_layer_opacity,
+            rename_layer,
+            move_layer,
+            insert_image,
+            copy_items,
+            paste_fragment,
+            insert_page,
+            duplicate_page,
+            move_pages,
+            remove_pages,
+            set_page_size,
+            set_page_kind,
+            set_page_background,
+            bookmark_page,
+            set_outline,
+            set_items_link,
+            add_link_region,
+            remove_link_regions,
+            link_at,
+            get_backlinks
         ])
         .setup(|app| {
             let window = app.get_window("main").unwrap();
@@ -73,9 +122,10 @@ fn create_note(title: String, state: tauri::State<AppState>) -> Result<String, S
 
 #[tauri::command]
 fn save_note(note_data: note::Note, state: tauri::State<AppState>) -> Result<(), String> {
+    // 前端的修改记入已有笔记的撤销历史
     let mut notes = state.notes.lock().unwrap();
-    if let Some(index) = notes.iter().position(|n| n.id == note_data.id) {
-        notes[index] = note_data;
+    if let Some(note) = notes.iter_mut().find(|n| n.id == note_data.id) {
+        note.apply_edited(note_data);
     }
     Ok(())
 }
@@ -90,19 +140,106 @@ fn load_note(note_id: String, state: tauri::State<AppState>) -> Result<note::Not
 }
 
 #[tauri::command]
-fn get_notes_list(state: tauri::State<AppState>) -> Result<Vec<note::Note>, String> {
+fn undo(note_id: String, state: tauri::State<AppState>) -> Result<note::Note, String> {
+    let mut notes = state.notes.lock().unwrap();
+    let note = notes.iter_mut()
+        .find(|n| n.id == note_id)
+        .ok_or_else(|| "Note not found".to_string())?;
+    
+    if !note.undo() {
+        return Err("Nothing to undo".to_string());
+    }
+    Ok(note.clone())
+}
+
+#[tauri::command]
+fn redo(note_id: String, state: tauri::State<AppState>) -> Result<note::Note, String> {
+    let mut notes = state.notes.lock().unwrap();
+    let note = notes.iter_mut()
+        .find(|n| n.id == note_id)
+        .ok_or_else(|| "Note not found".to_string())?;
+    
+    if !note.redo() {
+        return Err("Nothing to redo".to_string());
+    }
+    Ok(note.clone())
+}
+
+// 指定tag时只返回带有该标签的笔记（忽略大小写）
+#[tauri::command]
+fn get_notes_list(tag: Option<String>, state: tauri::State<AppState>) -> Result<Vec<note::Note>, String> {
+    let notes = state.notes.lock().unwrap();
+    Ok(notes.iter()
+        .filter(|n| tag.as_deref().is_none_or(|tag| n.metadata.has_tag(tag)))
+        .cloned()
+        .collect())
+}
+
+#[tauri::command]
+fn get_all_tags(state: tauri::State<AppState>) -> Result<Vec<String>, String> {
     let notes = state.notes.lock().unwrap();
-    Ok(notes.clone())
+    Ok(note::collect_tags(notes.iter().map(|n| &n.metadata)))
 }
 
 #[tauri::command]
-fn export_pdf(note_id: String, file_path: String, state: tauri::State<AppState>) -> Result<(), String> {
+fn get_note_metadata(note_id: String, state: tauri::State<AppState>) -> Result<note::NoteMetadata, String> {
+    let notes = state.notes.lock().unwrap();
+    notes.iter()
+        .find(|n| n.id == note_id)
+        .map(|n| n.metadata.clone())
+        .ok_or_else(|| "Note not found".to_string())
+}
+
+// 返回整理后的元数据（标签去重、去掉空白）
+#[tauri::command]
+fn set_note_metadata(note_id: String, metadata: note::NoteMetadata, state: tauri::State<AppState>) -> Result<note::NoteMetadata, String> {
+    with_note_mut(&state, &note_id, |note| {
+        note.set_metadata(metadata);
+        Ok(note.metadata.clone())
+    })
+}
+
+// 文件夹操作直接作用于notes目录中保存的笔记，路径用"/"分隔，根目录为空字符串
+#[tauri::command]
+fn get_folder_tree() -> Result<storage::Folder, String> {
+    storage::folder_tree().map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+fn create_folder(parent: String, name: String) -> Result<String, String> {
+    storage::create_folder(&parent, &name).map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+fn rename_folder(folder: String, new_name: String) -> Result<String, String> {
+    storage::rename_folder(&folder, &new_name).map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+fn move_folder(folder: String, new_parent: String) -> Result<String, String> {
+    storage::move_folder(&folder, &new_parent).map_err(|e| e.to_string())
+}
+
+// recursive为false时只能删除空文件夹
+#[tauri::command]
+fn delete_folder(folder: String, recursive: bool) -> Result<(), String> {
+    storage::delete_folder(&folder, recursive).map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+fn move_note_to_folder(note_id: String, folder: String) -> Result<(), String> {
+    storage::move_note(&note_id, &folder).map_err(|e| e.to_string())
+}
+
+// infinite_export决定无限画布导出成一页还是分页，默认为一页
+#[tauri::command]
+fn export_pdf(note_id: String, file_path: String, infinite_export: Option<pdf::InfiniteExport>, state: tauri::State<AppState>) -> Result<(), String> {
     let notes = state.notes.lock().unwrap();
     let note = notes.iter()
         .find(|n| n.id == note_id)
         .ok_or_else(|| "Note not found".to_string())?;
     
-    pdf::export_to_pdf(note, &file_path)
+    pdf::export_to_pdf(note, &file_path, infinite_export.unwrap_or_default())
         .map_err(|e| e.to_string())
 }
 
@@ -115,4 +252,206 @@ fn import_pdf(file_path: String, state: tauri::State<AppState>) -> Result<String
     let note_id = note.id.clone();
     notes.push(note);
     Ok(note_id)
-}
\ No newline at end of file
+}
+
+// 读取指定笔记
+fn with_note<T, F>(state: &tauri::State<AppState>, note_id: &str, read: F) -> Result<T, String>
+where
+    F: FnOnce(&note::Note) -> Result<T, String>,
+{
+    let notes = state.notes.lock().unwrap();
+    let note = notes.iter()
+        .find(|n| n.id == note_id)
+        .ok_or_else(|| "Note not found".to_string())?;
+    read(note)
+}
+
+// 在指定笔记上执行修改
+fn with_note_mut<T, F>(state: &tauri::State<AppState>, note_id: &str, edit: F) -> Result<T, String>
+where
+    F: FnOnce(&mut note::Note) -> Result<T, String>,
+{
+    let mut notes = state.notes.lock().unwrap();
+    let note = notes.iter_mut()
+        .find(|n| n.id == note_id)
+        .ok_or_else(|| "Note not found".to_string())?;
+    edit(note)
+}
+
+#[tauri::command]
+fn add_layer(note_id: String, page_index: usize, name: String, state: tauri::State<AppState>) -> Result<(), String> {
+    with_note_mut(&state, &note_id, |note| note.add_layer(page_index, name))
+}
+
+#[tauri::command]
+fn remove_layer(note_id: String, page_index: usize, layer_index: usize, state: tauri::State<AppState>) -> Result<(), String> {
+    with_note_mut(&state, &note_id, |note| note.remove_layer(page_index, layer_index))
+}
+
+#[tauri::command]
+fn set_active_layer(note_id: String, page_index: usize, layer_index: usize, state: tauri::State<AppState>) -> Result<(), String> {
+    with_note_mut(&state, &note_id, |note| note.set_active_layer(page_index, layer_index))
+}
+
+#[tauri::command]
+fn set_layer_visible(note_id: String, page_index: usize, layer_index: usize, visible: bool, state: tauri::State<AppState>) -> Result<(), String> {
+    with_note_mut(&state, &note_id, |note| note.set_layer_visible(page_index, layer_index, visible))
+}
+
+#[tauri::command]
+fn set_layer_locked(note_id: String, page_index: usize, layer_index: usize, locked: bool, state: tauri::State<AppState>) -> Result<(), String> {
+    with_note_mut(&state, &note_id, |note| note.set_layer_locked(page_index, layer_index, locked))
+}
+
+#[tauri::command]
+fn set_layer_opacity(note_id: String, page_index: usize, layer_index: usize, opacity: f32, state: tauri::State<AppState>) -> Result<(), String> {
+    with_note_mut(&state, &note_id, |note| note.set_layer_opacity(page_index, layer_index, opacity))
+}
+
+#[tauri::command]
+fn rename_layer(note_id: String, page_index: usize, layer_index: usize, name: String, state: tauri::State<AppState>) -> Result<(), String> {
+    with_note_mut(&state, &note_id, |note| note.rename_layer(page_index, layer_index, name))
+}
+
+// 把from处的图层移动到to，活动图层跟随移动
+#[tauri::command]
+fn move_layer(note_id: String, page_index: usize, from: usize, to: usize, state: tauri::State<AppState>) -> Result<(), String> {
+    with_note_mut(&state, &note_id, |note| note.move_layer(page_index, from, to))
+}
+
+#[tauri::command]
+fn insert_page(note_id: String, page_index: usize, background: note::Background, state: tauri::State<AppState>) -> Result<(), String> {
+    with_note_mut(&state, &note_id, |note| note.insert_page(page_index, background))
+}
+
+#[tauri::command]
+fn duplicate_page(note_id: String, page_index: usize, state: tauri::State<AppState>) -> Result<(), String> {
+    with_note_mut(&state, &note_id, |note| note.duplicate_page(page_index))
+}
+
+// 移动[start, end)范围内的页面，to为移动后第一页所在的位置
+#[tauri::command]
+fn move_pages(note_id: String, start: usize, end: usize, to: usize, state: tauri::State<AppState>) -> Result<(), String> {
+    with_note_mut(&state, &note_id, |note| note.move_pages(start..end, to))
+}
+
+// 删除[start, end)范围内的页面
+#[tauri::command]
+fn remove_pages(note_id: String, start: usize, end: usize, state: tauri::State<AppState>) -> Result<(), String> {
+    with_note_mut(&state, &note_id, |note| note.remove_pages(start..end))
+}
+
+#[tauri::command]
+fn set_page_size(note_id: String, page_index: usize, format: note::PageFormat, orientation: note::Orientation, state: tauri::State<AppState>) -> Result<(), String> {
+    with_note_mut(&state, &note_id, |note| note.set_page_size(page_index, format.size(orientation)))
+}
+
+#[tauri::command]
+fn set_page_kind(note_id: String, page_index: usize, kind: note::PageKind, state: tauri::State<AppState>) -> Result<(), String> {
+    with_note_mut(&state, &note_id, |note| note.set_page_kind(page_index, kind))
+}
+
+// 更换纸张模板或调整模板的颜色和线宽
+#[tauri::command]
+fn set_page_background(note_id: String, page_index: usize, background: note::Background, state: tauri::State<AppState>) -> Result<(), String> {
+    with_note_mut(&state, &note_id, |note| note.set_page_background(page_index, background))
+}
+
+// 给页面加书签，title为空时使用页码
+#[tauri::command]
+fn bookmark_page(note_id: String, page_index: usize, title: Option<String>, state: tauri::State<AppState>) -> Result<(), String> {
+    with_note_mut(&state, &note_id, |note| note.bookmark_page(page_index, title))
+}
+
+// 前端编辑大纲（重命名、调整层级和顺序）后整体提交
+#[tauri::command]
+fn set_outline(note_id: String, outline: Vec<note::OutlineEntry>, state: tauri::State<AppState>) -> Result<(), String> {
+    with_note_mut(&state, &note_id, |note| note.set_outline(outline))
+}
+
+// 给选中的笔画和文本框设置链接，link为空时清除
+#[tauri::command]
+fn set_items_link(note_id: String, page_index: usize, items: Vec<note::ItemRef>, link: Option<note::LinkTarget>, state: tauri::State<AppState>) -> Result<(), String> {
+    with_note_mut(&state, &note_id, |note| note.set_items_link(page_index, &items, link))
+}
+
+// 返回新链接区域的id
+#[tauri::command]
+fn add_link_region(note_id: String, page_index: usize, x: f32, y: f32, width: f32, height: f32, target: note::LinkTarget, state: tauri::State<AppState>) -> Result<String, String> {
+    let rect = geometry::Rect::from_min_size(geometry::Vec2::new(x, y), width, height);
+    with_note_mut(&state, &note_id, |note| note.add_link_region(page_index, rect, target))
+}
+
+#[tauri::command]
+fn remove_link_regions(note_id: String, page_index: usize, link_ids: Vec<String>, state: tauri::State<AppState>) -> Result<(), String> {
+    with_note_mut(&state, &note_id, |note| note.remove_link_regions(page_index, &link_ids))
+}
+
+// 点击位置（画布坐标）上的链接，由前端负责跳转
+#[tauri::command]
+fn link_at(note_id: String, page_index: usize, x: f32, y: f32, state: tauri::State<AppState>) -> Result<Option<note::LinkTarget>, String> {
+    with_note(&state, &note_id, |note| {
+        let page = note.pages.get(page_index).ok_or_else(|| "Page index out of bounds".to_string())?;
+        Ok(page.link_at(geometry::Vec2::new(x, y)).cloned())
+    })
+}
+
+// 已保存和已打开的笔记中指向该笔记的链接
+#[tauri::command]
+fn get_backlinks(note_id: String, state: tauri::State<AppState>) -> Result<Vec<note::Backlink>, String> {
+    let notes = state.notes.lock().unwrap();
+    let note = notes.iter()
+        .find(|n| n.id == note_id)
+        .ok_or_else(|| "Note not found".to_string())?;
+    let stored = storage::stored_backlinks(note).map_err(|e| e.to_string())?;
+    let open: Vec<&note::Note> = notes.iter().collect();
+    Ok(note::merge_backlinks(note, &stored, &open))
+}
+
+// 插入图片（例如前端从剪贴板读取的截图）到笔记的当前页面
+#[tauri::command]
+fn insert_image(note_id: String, image_data: Vec<u8>, x: f32, y: f32, state: tauri::State<AppState>) -> Result<(), String> {
+    with_note_mut(&state, &note_id, |note| {
+        let (asset, width, height) = storage::prepare_image_asset(&image_data)
+            .map_err(|e| e.to_string())?;
+        let page_index = note.current_page;
+        note.editable_layer(page_index)?;
+        storage::write_image_asset(&note.id, &asset, &image_data)
+            .map_err(|e| e.to_string())?;
+        
+        let max_width = note.pages.get(page_index).map_or(800.0, |page| page.width() * 0.8);
+        let image = note::ImageElement::fitted(asset, x, y, width, height, max_width);
+        note.add_element_to(page_index, note::Element::Image(image))
+    })
+}
+
+// 复制页面上的内容，返回放到系统剪贴板的SVG文本（内嵌片段数据）和栅格化的PNG，
+// 前端把两者作为text/plain和image/png一起写入剪贴板
+#[tauri::command]
+fn copy_items(note_id: String, page_index: usize, items: Vec<note::ItemRef>, state: tauri::State<AppState>) -> Result<clipboard::ClipboardContent, String> {
+    let fragment = with_note(&state, &note_id, |note| {
+        clipboard::Fragment::from_items(note, page_index, &items)
+            .ok_or_else(|| "Nothing to copy".to_string())
+    })?;
+    let text = fragment.to_clipboard_text().map_err(|e| e.to_string())?;
+    
+    // 这里没有窗口，用一个不显示的egui上下文排版文字
+    let ctx = eframe::egui::Context::default();
+    let mut image = None;
+    let _ = ctx.run(Default::default(), |ctx| image = Some(ui::render_fragment(ctx, &fragment)));
+    let png = match image {
+        Some(image) => clipboard::encode_png(&image).map_err(|e| e.to_string())?,
+        None => Vec::new(),
+    };
+    Ok(clipboard::ClipboardContent { text, png })
+}
+
+// 粘贴剪贴板文本中的片段，(x, y)为粘贴内容的中心；返回粘贴出的内容
+#[tauri::command]
+fn paste_fragment(note_id: String, page_index: usize, clipboard_text: String, x: f32, y: f32, state: tauri::State<AppState>) -> Result<Vec<note::ItemRef>, String> {
+    let fragment = clipboard::Fragment::from_clipboard_text(&clipboard_text)
+        .ok_or_else(|| "Clipboard does not contain SpeedyNote content".to_string())?;
+    with_note_mut(&state, &note_id, |note| {
+        clipboard::paste(note, page_index, &fragment, Some(geometry::Vec2::new(x, y)))
+    })
+}
@@ -0,0 +1,197 @@
+// 便签/文本框使用的简化Markdown解析，egui渲染与PDF导出共用
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum BlockKind {
+    Paragraph,
+    Heading(u8),
+    Bullet,
+    Numbered(u32),
+    Quote,
+    Code,
+}
+
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Span {
+    pub text: String,
+    pub bold: bool,
+    pub italic: bool,
+    pub code: bool,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Block {
+    pub kind: BlockKind,
+    pub spans: Vec<Span>,
+}
+
+impl BlockKind {
+    // 相对于文本框字号的缩放比例
+    pub fn font_scale(&self) -> f32 {
+        match self {
+            BlockKind::Heading(1) => 1.6,
+            BlockKind::Heading(2) => 1.35,
+            BlockKind::Heading(_) => 1.15,
+            _ => 1.0,
+        }
+    }
+
+    // 列表等块在行首显示的前缀
+    pub fn prefix(&self) -> Option<String> {
+        match self {
+            BlockKind::Bullet => Some("• ".to_string()),
+            BlockKind::Numbered(n) => Some(format!("{}. ", n)),
+            BlockKind::Quote => Some("│ ".to_string()),
+            _ => None,
+        }
+    }
+}
+
+pub fn parse(source: &str) -> Vec<Block> {
+    let mut blocks = Vec::new();
+    let mut in_code = false;
+
+    for line in source.lines() {
+        let trimmed = line.trim_start();
+
+        if trimmed.starts_with("```") {
+            in_code = !in_code;
+            continue;
+        }
+
+        if in_code {
+            blocks.push(Block {
+                kind: BlockKind::Code,
+                spans: vec![Span { text: line.to_string(), code: true, ..Span::default() }],
+            });
+            continue;
+        }
+
+        let (kind, text) = block_kind(trimmed);
+        blocks.push(Block {
+            kind,
+            spans: parse_inline(text),
+        });
+    }
+
+    blocks
+}
+
+fn block_kind(line: &str) -> (BlockKind, &str) {
+    let hashes = line.chars().take_while(|&c| c == '#').count();
+    if (1..=6).contains(&hashes) && line[hashes..].starts_with(' ') {
+        return (BlockKind::Heading(hashes as u8), line[hashes..].trim_start());
+    }
+
+    for marker in ["- ", "* ", "+ "] {
+        if let Some(rest) = line.strip_prefix(marker) {
+            return (BlockKind::Bullet, rest);
+        }
+    }
+
+    if let Some(rest) = line.strip_prefix("> ") {
+        return (BlockKind::Quote, rest);
+    }
+
+    let digits = line.chars().take_while(char::is_ascii_digit).count();
+    if digits > 0 && line[digits..].starts_with(". ") {
+        if let Ok(number) = line[..digits].parse() {
+            return (BlockKind::Numbered(number), &line[digits + 2..]);
+        }
+    }
+
+    (BlockKind::Paragraph, line)
+}
+
+// 解析行内的**粗体**、*斜体*和`代码`，没有闭合的标记按普通字符处理
+pub fn parse_inline(text: &str) -> Vec<Span> {
+    let mut spans = Vec::new();
+    let mut current = Span::default();
+    let mut rest = text;
+
+    while let Some(c) = rest.chars().next() {
+        let marker = if current.code {
+            if c == '`' { Some("`") } else { None }
+        } else if rest.starts_with("**") {
+            Some("**")
+        } else if c == '*' || c == '_' || c == '`' {
+            Some(&rest[..1])
+        } else {
+            None
+        };
+
+        if let Some(marker) = marker {
+            let after = &rest[marker.len()..];
+            let closing = match marker {
+                "**" => current.bold,
+                "`" => current.code,
+                _ => current.italic,
+            };
+
+            if closing || after.contains(marker) {
+                if !current.text.is_empty() {
+                    spans.push(current.clone());
+                    current.text.clear();
+                }
+                match marker {
+                    "**" => current.bold = !current.bold,
+                    "`" => current.code = !current.code,
+                    _ => current.italic = !current.italic,
+                }
+                rest = after;
+                continue;
+            }
+        }
+
+        if c == '\\' && !current.code {
+            if let Some(escaped) = rest[1..].chars().next() {
+                current.text.push(escaped);
+                rest = &rest[1 + escaped.len_utf8()..];
+                continue;
+            }
+        }
+
+        current.text.push(c);
+        rest = &rest[c.len_utf8()..];
+    }
+
+    if !current.text.is_empty() {
+        spans.push(current);
+    }
+    spans
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_block_kinds() {
+        let blocks = parse("# 标题\n- 要点\n3. 第三\n> 引用\n普通文字");
+        let kinds: Vec<BlockKind> = blocks.iter().map(|b| b.kind).collect();
+
+        assert_eq!(kinds, vec![
+            BlockKind::Heading(1),
+            BlockKind::Bullet,
+            BlockKind::Numbered(3),
+            BlockKind::Quote,
+            BlockKind::Paragraph,
+        ]);
+        assert_eq!(blocks[0].spans[0].text, "标题");
+    }
+
+    #[test]
+    fn test_inline_styles() {
+        let spans = parse_inline("a **b** *c* `d*e`");
+
+        assert_eq!(spans.len(), 6);
+        assert!(spans[1].bold && spans[1].text == "b");
+        assert!(spans[3].italic && spans[3].text == "c");
+        assert!(spans[5].code && spans[5].text == "d*e");
+    }
+
+    #[test]
+    fn test_unclosed_marker_is_literal() {
+        let spans = parse_inline("2 * 3 = 6");
+        assert_eq!(spans, vec![Span { text: "2 * 3 = 6".to_string(), ..Span::default() }]);
+    }
+}
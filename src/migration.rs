@@ -0,0 +1,206 @@
+use crate::note::{Note, DEFAULT_DPI, FORMAT_VERSION, MM_PER_INCH};
+use serde_json::{json, Value};
+
+// 解析.spn文件内容，旧版本的数据先逐级迁移到当前格式
+pub fn parse_note(json_data: &str) -> Result<Note, Box<dyn std::error::Error>> {
+    let value: Value = serde_json::from_str(json_data)?;
+    let mut note: Note = serde_json::from_value(migrate(value)?)?;
+    for page in &mut note.pages {
+        page.reindex();
+    }
+    Ok(note)
+}
+
+pub fn migrate(mut note: Value) -> Result<Value, Box<dyn std::error::Error>> {
+    // 没有format_version字段的文件属于最初的版本1
+    let version = note.get("format_version")
+        .and_then(Value::as_u64)
+        .unwrap_or(1);
+    
+    if version > FORMAT_VERSION as u64 {
+        return Err(format!("Unsupported .spn format version: {}", version).into());
+    }
+    
+    if version < 2 {
+        migrate_v1_to_v2(&mut note);
+    }
+    if version < 3 {
+        migrate_v2_to_v3(&mut note);
+    }
+    if version < 4 {
+        migrate_v3_to_v4(&mut note);
+    }
+    if version < 5 {
+        migrate_v4_to_v5(&mut note);
+    }
+    
+    note["format_version"] = json!(FORMAT_VERSION);
+    Ok(note)
+}
+
+// 版本1 → 2：把Stroke上独立的pressure数组合并进每个Point
+fn migrate_v1_to_v2(note: &mut Value) {
+    let pages = match note.get_mut("pages").and_then(Value::as_array_mut) {
+        Some(pages) => pages,
+        None => return,
+    };
+    
+    for page in pages {
+        let strokes = match page.get_mut("strokes").and_then(Value::as_array_mut) {
+            Some(strokes) => strokes,
+            None => continue,
+        };
+        
+        for stroke in strokes {
+            let pressure: Vec<f64> = stroke.as_object_mut()
+                .and_then(|stroke| stroke.remove("pressure"))
+                .and_then(|pressure| serde_json::from_value(pressure).ok())
+                .unwrap_or_default();
+            
+            let points = match stroke.get_mut("points").and_then(Value::as_array_mut) {
+                Some(points) => points,
+                None => continue,
+            };
+            
+            // 旧版本的压力数组经常比点少，缺失的部分沿用最后一个值
+            for (i, point) in points.iter_mut().enumerate() {
+                let value = pressure.get(i)
+                    .or_else(|| pressure.last())
+                    .copied()
+                    .unwrap_or(1.0);
+                point["pressure"] = json!(value);
+            }
+        }
+    }
+}
+
+// 版本2 → 3：页面上平铺的strokes放入默认图层
+fn migrate_v2_to_v3(note: &mut Value) {
+    let pages = match note.get_mut("pages").and_then(Value::as_array_mut) {
+        Some(pages) => pages,
+        None => return,
+    };
+    
+    for page in pages {
+        let page = match page.as_object_mut() {
+            Some(page) => page,
+            None => continue,
+        };
+        
+        let strokes = page.remove("strokes").unwrap_or_else(|| json!([]));
+        page.insert("layers".to_string(), json!([{
+            "name": "图层 1",
+            "visible": true,
+            "locked": false,
+            "opacity": 1.0,
+            "strokes": strokes,
+        }]));
+        page.insert("active_layer".to_string(), json!(0));
+    }
+}
+
+// 版本3 → 4：以像素保存的页面宽高换算成物理尺寸，按96dpi解释
+fn migrate_v3_to_v4(note: &mut Value) {
+    let pages = match note.get_mut("pages").and_then(Value::as_array_mut) {
+        Some(pages) => pages,
+        None => return,
+    };
+    
+    let dpi = DEFAULT_DPI as f64;
+    let to_mm = |pixels: f64| pixels / dpi * MM_PER_INCH as f64;
+    for page in pages {
+        let page = match page.as_object_mut() {
+            Some(page) => page,
+            None => continue,
+        };
+        
+        let width = page.remove("width").and_then(|w| w.as_f64()).unwrap_or(800.0);
+        let height = page.remove("height").and_then(|h| h.as_f64()).unwrap_or(1000.0);
+        page.insert("size".to_string(), json!({
+            "width_mm": to_mm(width),
+            "height_mm": to_mm(height),
+        }));
+        page.insert("dpi".to_string(), json!(dpi));
+    }
+}
+
+// 版本4 → 5：给每个页面分配稳定的id，供大纲引用
+fn migrate_v4_to_v5(note: &mut Value) {
+    let pages = match note.get_mut("pages").and_then(Value::as_array_mut) {
+        Some(pages) => pages,
+        None => return,
+    };
+    
+    for page in pages {
+        if let Some(page) = page.as_object_mut() {
+            page.insert("id".to_string(), json!(uuid::Uuid::new_v4().to_string()));
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    
+    const V1_NOTE: &str = r##"{
+        "id": "old-note",
+        "title": "旧笔记",
+        "pages": [{
+            "strokes": [{
+                "points": [
+                    {"x": 0.0, "y": 0.0, "timestamp": 1},
+                    {"x": 5.0, "y": 5.0, "timestamp": 2},
+                    {"x": 9.0, "y": 9.0, "timestamp": 3}
+                ],
+                "color": "#000000",
+                "thickness": 2.0,
+                "pressure": [0.5]
+            }],
+            "background": "Blank",
+            "width": 800.0,
+            "height": 1000.0
+        }],
+        "created_at": 0,
+        "updated_at": 0,
+        "current_page": 0
+    }"##;
+    
+    #[test]
+    fn test_migrate_v1_pressure_into_points() {
+        let note = parse_note(V1_NOTE).unwrap();
+        
+        assert_eq!(note.format_version, FORMAT_VERSION);
+        let points = &note.pages[0].layers[0].strokes[0].points;
+        assert_eq!(points.len(), 3);
+        assert!(points.iter().all(|p| p.pressure == 0.5 && p.tilt.is_none()));
+    }
+    
+    #[test]
+    fn test_migrate_pixel_size_keeps_canvas_size() {
+        let note = parse_note(V1_NOTE).unwrap();
+        let page = &note.pages[0];
+        
+        assert_eq!(page.dpi, DEFAULT_DPI);
+        assert!((page.width() - 800.0).abs() < 1e-3);
+        assert!((page.height() - 1000.0).abs() < 1e-3);
+    }
+    
+    #[test]
+    fn test_current_version_round_trip() {
+        let note = parse_note(V1_NOTE).unwrap();
+        let saved = serde_json::to_string(&note).unwrap();
+        let reloaded = parse_note(&saved).unwrap();
+        
+        assert_eq!(reloaded.pages[0].layers.len(), 1);
+        assert_eq!(reloaded.pages[0].layers[0].strokes[0].points.len(), 3);
+        // 迁移时分配的页面id在保存后保持不变
+        assert!(!note.pages[0].id.is_empty());
+        assert_eq!(reloaded.pages[0].id, note.pages[0].id);
+    }
+    
+    #[test]
+    fn test_rejects_newer_version() {
+        let value = json!({ "format_version": FORMAT_VERSION + 1 });
+        assert!(migrate(value).is_err());
+    }
+}
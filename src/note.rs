@@ -1,112 +1,2815 @@
+use crate::geometry::{self, Rect, Transform, Vec2};
+use crate::history::{Command, History};
+use crate::outline::{self, FittedOutline};
+use crate::spatial::SpatialIndex;
 use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::ops::Range;
+use std::sync::{Arc, OnceLock};
 use std::time::{SystemTime, UNIX_EPOCH};
 
+// 当前的.spn文件格式版本，旧版本文件在加载时迁移
+pub const FORMAT_VERSION: u32 = 5;
+
+// RGBA颜色。文件中保存为#RRGGBB（不透明时）或#RRGGBBAA字符串；
+// 解析时接受#RGB、#RRGGBB、#RRGGBBAA、rgb()/rgba()和CSS颜色名
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(from = "String", into = "String")]
+pub struct Color {
+    pub r: u8,
+    pub g: u8,
+    pub b: u8,
+    pub a: u8,
+}
+
+impl Color {
+    pub const BLACK: Color = Color::rgb(0, 0, 0);
+    pub const WHITE: Color = Color::rgb(255, 255, 255);
+    
+    pub const fn rgb(r: u8, g: u8, b: u8) -> Self {
+        Self { r, g, b, a: 255 }
+    }
+    
+    pub const fn rgba(r: u8, g: u8, b: u8, a: u8) -> Self {
+        Self { r, g, b, a }
+    }
+    
+    pub fn parse(text: &str) -> Option<Color> {
+        let text = text.trim();
+        if let Some(hex) = text.strip_prefix('#') {
+            return parse_hex(hex);
+        }
+        
+        let lower = text.to_ascii_lowercase();
+        if let Some(args) = lower.strip_prefix("rgba(").or_else(|| lower.strip_prefix("rgb(")) {
+            return parse_rgb_function(args.strip_suffix(')')?);
+        }
+        if lower == "transparent" {
+            return Some(Color::rgba(0, 0, 0, 0));
+        }
+        NAMED_COLORS.binary_search_by(|(name, _)| name.cmp(&lower.as_str()))
+            .ok()
+            .map(|index| {
+                let [_, r, g, b] = NAMED_COLORS[index].1.to_be_bytes();
+                Color::rgb(r, g, b)
+            })
+    }
+    
+    // 不透明度，0.0到1.0
+    pub fn alpha(self) -> f32 {
+        self.a as f32 / 255.0
+    }
+    
+    // 0.0到1.0的RGB分量，供PDF使用
+    pub fn to_rgb_f32(self) -> (f32, f32, f32) {
+        (self.r as f32 / 255.0, self.g as f32 / 255.0, self.b as f32 / 255.0)
+    }
+    
+    // 不含透明度的#RRGGBB，透明度需要另外输出的场合（如SVG的fill-opacity）使用
+    pub fn to_rgb_hex(self) -> String {
+        format!("#{:02X}{:02X}{:02X}", self.r, self.g, self.b)
+    }
+    
+    pub fn to_hex(self) -> String {
+        if self.a == 255 {
+            self.to_rgb_hex()
+        } else {
+            format!("{}{:02X}", self.to_rgb_hex(), self.a)
+        }
+    }
+}
+
+impl std::fmt::Display for Color {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(&self.to_hex())
+    }
+}
+
+impl std::str::FromStr for Color {
+    type Err = String;
+    
+    fn from_str(text: &str) -> Result<Self, Self::Err> {
+        Color::parse(text).ok_or_else(|| format!("Invalid color: {}", text))
+    }
+}
+
+// 旧文件中的颜色是任意字符串，无法解析时按黑色加载而不是整个文件打不开
+impl From<String> for Color {
+    fn from(text: String) -> Self {
+        Color::parse(&text).unwrap_or_else(|| {
+            log::warn!("无法解析颜色 {:?}，按黑色处理", text);
+            Color::BLACK
+        })
+    }
+}
+
+impl From<Color> for String {
+    fn from(color: Color) -> Self {
+        color.to_hex()
+    }
+}
+
+fn parse_hex(hex: &str) -> Option<Color> {
+    if !hex.bytes().all(|b| b.is_ascii_hexdigit()) {
+        return None;
+    }
+    let digit = |i: usize| u8::from_str_radix(&hex[i..i + 1], 16).ok().map(|d| d * 17);
+    let byte = |i: usize| u8::from_str_radix(&hex[i..i + 2], 16).ok();
+    
+    match hex.len() {
+        3 => Some(Color::rgb(digit(0)?, digit(1)?, digit(2)?)),
+        6 => Some(Color::rgb(byte(0)?, byte(2)?, byte(4)?)),
+        8 => Some(Color::rgba(byte(0)?, byte(2)?, byte(4)?, byte(6)?)),
+        _ => None,
+    }
+}
+
+// rgb()/rgba()的参数，支持逗号分隔和"r g b / a"两种写法，分量可以是数字或百分比
+fn parse_rgb_function(args: &str) -> Option<Color> {
+    let (channels, alpha) = match args.split_once('/') {
+        Some((channels, alpha)) => (channels, Some(alpha)),
+        None => (args, None),
+    };
+    let mut parts: Vec<&str> = channels.split(|c: char| c == ',' || c.is_whitespace())
+        .filter(|part| !part.is_empty())
+        .collect();
+    let alpha = match (alpha, parts.len()) {
+        (Some(alpha), 3) => Some(alpha.trim()),
+        (None, 4) => parts.pop(),
+        (None, 3) => None,
+        _ => return None,
+    };
+    
+    let channel = |part: &str| -> Option<u8> {
+        let value = match part.strip_suffix('%') {
+            Some(percent) => percent.parse::<f32>().ok()? / 100.0 * 255.0,
+            None => part.parse::<f32>().ok()?,
+        };
+        Some(value.round().clamp(0.0, 255.0) as u8)
+    };
+    let a = match alpha {
+        Some(alpha) => {
+            let value = match alpha.strip_suffix('%') {
+                Some(percent) => percent.parse::<f32>().ok()? / 100.0,
+                None => alpha.parse::<f32>().ok()?,
+            };
+            (value.clamp(0.0, 1.0) * 255.0).round() as u8
+        }
+        None => 255,
+    };
+    Some(Color::rgba(channel(parts[0])?, channel(parts[1])?, channel(parts[2])?, a))
+}
+
+// CSS颜色名（按字母排序，用于二分查找）
+const NAMED_COLORS: [(&str, u32); 148] = [
+    ("aliceblue", 0xF0F8FF), ("antiquewhite", 0xFAEBD7), ("aqua", 0x00FFFF), ("aquamarine", 0x7FFFD4),
+    ("azure", 0xF0FFFF), ("beige", 0xF5F5DC), ("bisque", 0xFFE4C4), ("black", 0x000000),
+    ("blanchedalmond", 0xFFEBCD), ("blue", 0x0000FF), ("blueviolet", 0x8A2BE2), ("brown", 0xA52A2A),
+    ("burlywood", 0xDEB887), ("cadetblue", 0x5F9EA0), ("chartreuse", 0x7FFF00), ("chocolate", 0xD2691E),
+    ("coral", 0xFF7F50), ("cornflowerblue", 0x6495ED), ("cornsilk", 0xFFF8DC), ("crimson", 0xDC143C),
+    ("cyan", 0x00FFFF), ("darkblue", 0x00008B), ("darkcyan", 0x008B8B), ("darkgoldenrod", 0xB8860B),
+    ("darkgray", 0xA9A9A9), ("darkgreen", 0x006400), ("darkgrey", 0xA9A9A9), ("darkkhaki", 0xBDB76B),
+    ("darkmagenta", 0x8B008B), ("darkolivegreen", 0x556B2F), ("darkorange", 0xFF8C00), ("darkorchid", 0x9932CC),
+    ("darkred", 0x8B0000), ("darksalmon", 0xE9967A), ("darkseagreen", 0x8FBC8F), ("darkslateblue", 0x483D8B),
+    ("darkslategray", 0x2F4F4F), ("darkslategrey", 0x2F4F4F), ("darkturquoise", 0x00CED1), ("darkviolet", 0x9400D3),
+    ("deeppink", 0xFF1493), ("deepskyblue", 0x00BFFF), ("dimgray", 0x696969), ("dimgrey", 0x696969),
+    ("dodgerblue", 0x1E90FF), ("firebrick", 0xB22222), ("floralwhite", 0xFFFAF0), ("forestgreen", 0x228B22),
+    ("fuchsia", 0xFF00FF), ("gainsboro", 0xDCDCDC), ("ghostwhite", 0xF8F8FF), ("gold", 0xFFD700),
+    ("goldenrod", 0xDAA520), ("gray", 0x808080), ("green", 0x008000), ("greenyellow", 0xADFF2F),
+    ("grey", 0x808080), ("honeydew", 0xF0FFF0), ("hotpink", 0xFF69B4), ("indianred", 0xCD5C5C),
+    ("indigo", 0x4B0082), ("ivory", 0xFFFFF0), ("khaki", 0xF0E68C), ("lavender", 0xE6E6FA),
+    ("lavenderblush", 0xFFF0F5), ("lawngreen", 0x7CFC00), ("lemonchiffon", 0xFFFACD), ("lightblue", 0xADD8E6),
+    ("lightcoral", 0xF08080), ("lightcyan", 0xE0FFFF), ("lightgoldenrodyellow", 0xFAFAD2), ("lightgray", 0xD3D3D3),
+    ("lightgreen", 0x90EE90), ("lightgrey", 0xD3D3D3), ("lightpink", 0xFFB6C1), ("lightsalmon", 0xFFA07A),
+    ("lightseagreen", 0x20B2AA), ("lightskyblue", 0x87CEFA), ("lightslategray", 0x778899), ("lightslategrey", 0x778899),
+    ("lightsteelblue", 0xB0C4DE), ("lightyellow", 0xFFFFE0), ("lime", 0x00FF00), ("limegreen", 0x32CD32),
+    ("linen", 0xFAF0E6), ("magenta", 0xFF00FF), ("maroon", 0x800000), ("mediumaquamarine", 0x66CDAA),
+    ("mediumblue", 0x0000CD), ("mediumorchid", 0xBA55D3), ("mediumpurple", 0x9370DB), ("mediumseagreen", 0x3CB371),
+    ("mediumslateblue", 0x7B68EE), ("mediumspringgreen", 0x00FA9A), ("mediumturquoise", 0x48D1CC), ("mediumvioletred", 0xC71585),
+    ("midnightblue", 0x191970), ("mintcream", 0xF5FFFA), ("mistyrose", 0xFFE4E1), ("moccasin", 0xFFE4B5),
+    ("navajowhite", 0xFFDEAD), ("navy", 0x000080), ("oldlace", 0xFDF5E6), ("olive", 0x808000),
+    ("olivedrab", 0x6B8E23), ("orange", 0xFFA500), ("orangered", 0xFF4500), ("orchid", 0xDA70D6),
+    ("palegoldenrod", 0xEEE8AA), ("palegreen", 0x98FB98), ("paleturquoise", 0xAFEEEE), ("palevioletred", 0xDB7093),
+    ("papayawhip", 0xFFEFD5), ("peachpuff", 0xFFDAB9), ("peru", 0xCD853F), ("pink", 0xFFC0CB),
+    ("plum", 0xDDA0DD), ("powderblue", 0xB0E0E6), ("purple", 0x800080), ("rebeccapurple", 0x663399),
+    ("red", 0xFF0000), ("rosybrown", 0xBC8F8F), ("royalblue", 0x4169E1), ("saddlebrown", 0x8B4513),
+    ("salmon", 0xFA8072), ("sandybrown", 0xF4A460), ("seagreen", 0x2E8B57), ("seashell", 0xFFF5EE),
+    ("sienna", 0xA0522D), ("silver", 0xC0C0C0), ("skyblue", 0x87CEEB), ("slateblue", 0x6A5ACD),
+    ("slategray", 0x708090), ("slategrey", 0x708090), ("snow", 0xFFFAFA), ("springgreen", 0x00FF7F),
+    ("steelblue", 0x4682B4), ("tan", 0xD2B48C), ("teal", 0x008080), ("thistle", 0xD8BFD8),
+    ("tomato", 0xFF6347), ("turquoise", 0x40E0D0), ("violet", 0xEE82EE), ("wheat", 0xF5DEB3),
+    ("white", 0xFFFFFF), ("whitesmoke", 0xF5F5F5), ("yellow", 0xFFFF00), ("yellowgreen", 0x9ACD32),
+];
+
+// 书写工具，决定笔画的宽度、笔尖形状、透明度和绘制顺序
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+pub enum StrokeTool {
+    #[default]
+    Pen,
+    // 半透明，画在同一图层的笔迹下面
+    Highlighter,
+    // 平头，不随压力变化
+    Marker,
+    // 带颗粒纹理
+    Pencil,
+}
+
+impl StrokeTool {
+    pub const ALL: [StrokeTool; 4] = [StrokeTool::Pen, StrokeTool::Highlighter, StrokeTool::Marker, StrokeTool::Pencil];
+    
+    pub fn name(&self) -> &'static str {
+        match self {
+            StrokeTool::Pen => "钢笔",
+            StrokeTool::Highlighter => "荧光笔",
+            StrokeTool::Marker => "马克笔",
+            StrokeTool::Pencil => "铅笔",
+        }
+    }
+    
+    // 工具自身的不透明度，绘制时再乘以图层不透明度
+    pub fn opacity(&self) -> f32 {
+        match self {
+            StrokeTool::Highlighter => 0.35,
+            StrokeTool::Pencil => 0.6,
+            StrokeTool::Pen | StrokeTool::Marker => 1.0,
+        }
+    }
+    
+    // 笔尖宽度相对于设定粗细的倍数
+    pub fn width_scale(&self) -> f32 {
+        match self {
+            StrokeTool::Highlighter => 4.0,
+            StrokeTool::Marker => 2.0,
+            StrokeTool::Pen | StrokeTool::Pencil => 1.0,
+        }
+    }
+    
+    pub fn is_pressure_sensitive(&self) -> bool {
+        matches!(self, StrokeTool::Pen | StrokeTool::Pencil)
+    }
+    
+    pub fn has_flat_tip(&self) -> bool {
+        matches!(self, StrokeTool::Highlighter | StrokeTool::Marker)
+    }
+    
+    pub fn is_underlay(&self) -> bool {
+        *self == StrokeTool::Highlighter
+    }
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Stroke {
     pub points: Vec<Point>,
-    pub color: String,
+    pub color: Color,
     pub thickness: f32,
-    pub pressure: Vec<f32>,
+    #[serde(default)]
+    pub tool: StrokeTool,
+    #[serde(default)]
+    pub link: Option<LinkTarget>,
+    // 拟合后的轮廓，不保存；采样点或粗细变化后需要清空
+    #[serde(skip)]
+    pub fitted: OnceLock<Arc<FittedOutline>>,
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
 pub struct Point {
     pub x: f32,
     pub y: f32,
+    pub pressure: f32,
     pub timestamp: u64,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub tilt: Option<Tilt>,
+}
+
+// 笔身倾斜角（弧度），分别为沿X、Y轴方向的倾斜
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+pub struct Tilt {
+    pub x: f32,
+    pub y: f32,
+}
+
+impl Point {
+    pub fn position(&self) -> Vec2 {
+        Vec2::new(self.x, self.y)
+    }
+    
+    // 在两个采样点之间按比例插值
+    pub fn lerp(&self, other: &Point, t: f32) -> Point {
+        let position = self.position().lerp(other.position(), t);
+        let tilt = match (self.tilt, other.tilt) {
+            (Some(a), Some(b)) => Some(Tilt {
+                x: a.x + (b.x - a.x) * t,
+                y: a.y + (b.y - a.y) * t,
+            }),
+            (tilt, _) => tilt,
+        };
+        
+        Point {
+            x: position.x,
+            y: position.y,
+            pressure: self.pressure + (other.pressure - self.pressure) * t,
+            timestamp: self.timestamp
+                + (other.timestamp.saturating_sub(self.timestamp) as f32 * t) as u64,
+            tilt,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum EraserMode {
+    // 整笔擦除：接触到的笔画整条删除
+    Stroke,
+    // 精确擦除：在擦过的位置切断笔画，保留两侧
+    Precise,
+}
+
+impl Stroke {
+    fn positions(&self) -> Vec<Vec2> {
+        self.points.iter().map(Point::position).collect()
+    }
+    
+    pub fn bounds(&self) -> Option<Rect> {
+        Rect::from_points(self.points.iter().map(Point::position))
+            .map(|bounds| bounds.expand(self.width() / 2.0))
+    }
+    
+    // 压力最大时的笔尖宽度
+    pub fn width(&self) -> f32 {
+        self.thickness * self.tool.width_scale()
+    }
+    
+    pub fn transform(&mut self, transform: &Transform) {
+        for point in &mut self.points {
+            let position = transform.apply(point.position());
+            point.x = position.x;
+            point.y = position.y;
+        }
+        self.thickness *= transform.scale_factor();
+        self.invalidate_outline();
+    }
+    
+    // 拟合后的轮廓，第一次使用时计算，之后绘制和导出都直接使用
+    pub fn outline(&self) -> &FittedOutline {
+        self.fitted.get_or_init(|| Arc::new(outline::fit_outline(self)))
+    }
+    
+    pub fn invalidate_outline(&mut self) {
+        self.fitted = OnceLock::new();
+    }
+    
+    // 橡皮擦路径是否碰到笔画（考虑笔画自身的粗细）
+    pub fn touches_path(&self, eraser_path: &[Vec2], radius: f32) -> bool {
+        if self.points.is_empty() {
+            return false;
+        }
+        geometry::polyline_distance(&self.positions(), eraser_path) <= radius + self.width() / 2.0
+    }
+    
+    // 用橡皮擦路径切割笔画，返回剩余的片段；未碰到时返回None
+    pub fn split_by_eraser(&self, eraser_path: &[Vec2], radius: f32) -> Option<Vec<Stroke>> {
+        if !self.touches_path(eraser_path, radius) {
+            return None;
+        }
+        
+        let reach = radius + self.width() / 2.0;
+        let mut pieces = Vec::new();
+        let mut points: Vec<Point> = Vec::new();
+        
+        let mut flush = |points: &mut Vec<Point>| {
+            if points.len() >= 2 {
+                pieces.push(Stroke {
+                    points: std::mem::take(points),
+                    color: self.color,
+                    thickness: self.thickness,
+                    tool: self.tool,
+                    link: self.link.clone(),
+                    fitted: OnceLock::new(),
+                });
+            } else {
+                points.clear();
+            }
+        };
+        
+        // 原始采样点保持不变，只在线段与擦除范围的交点处插入切口
+        if let Some(first) = self.points.first() {
+            if geometry::point_polyline_distance(first.position(), eraser_path) > reach {
+                points.push(*first);
+            }
+        }
+        for pair in self.points.windows(2) {
+            let (start, end) = (&pair[0], &pair[1]);
+            let erased = geometry::segment_polyline_ranges(start.position(), end.position(), eraser_path, reach);
+            for &(from, to) in &erased {
+                if from > 0.0 {
+                    points.push(start.lerp(end, from));
+                }
+                flush(&mut points);
+                if to < 1.0 {
+                    points.push(start.lerp(end, to));
+                }
+            }
+            if erased.last().is_none_or(|&(_, to)| to < 1.0) {
+                points.push(*end);
+            }
+        }
+        flush(&mut points);
+        
+        Some(pieces)
+    }
+}
+
+// 擦除图层中的笔画，内容有变化时返回新的笔画列表；只检查橡皮擦路径附近的笔画
+fn erase_strokes(layer: &Layer, eraser_path: &[Vec2], radius: f32, mode: EraserMode) -> Option<Vec<Stroke>> {
+    let area = Rect::from_points(eraser_path.iter().copied())?.expand(radius);
+    let mut candidates = layer.strokes_in(&area).into_iter().peekable();
+    candidates.peek()?;
+    
+    let mut changed = false;
+    let mut remaining = Vec::with_capacity(layer.strokes.len());
+    
+    for (index, stroke) in layer.strokes.iter().enumerate() {
+        if candidates.next_if_eq(&index).is_none() {
+            remaining.push(stroke.clone());
+            continue;
+        }
+        
+        match mode {
+            EraserMode::Stroke => {
+                if stroke.touches_path(eraser_path, radius) {
+                    changed = true;
+                } else {
+                    remaining.push(stroke.clone());
+                }
+            }
+            EraserMode::Precise => match stroke.split_by_eraser(eraser_path, radius) {
+                Some(pieces) => {
+                    changed = true;
+                    remaining.extend(pieces);
+                }
+                None => remaining.push(stroke.clone()),
+            },
+        }
+    }
+    
+    if changed {
+        Some(remaining)
+    } else {
+        None
+    }
 }
 
+// 页面上除笔画以外的内容
 #[derive(Debug, Clone, Serialize, Deserialize)]
-pub struct Page {
-    pub strokes: Vec<Stroke>,
-    pub background: Background,
+pub enum Element {
+    Text(TextBox),
+    Image(ImageElement),
+    Shape(ShapeElement),
+}
+
+// 文本框内容与边框之间的留白
+pub const TEXT_BOX_PADDING: f32 = 6.0;
+
+// 带位置的文本框/便签，内容为Markdown
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct TextBox {
+    pub id: String,
+    pub x: f32,
+    pub y: f32,
     pub width: f32,
     pub height: f32,
+    pub content: String,
+    pub font_size: f32,
+    pub color: Color,
+    // 便签的底色，普通文本框为None
+    #[serde(default)]
+    pub background: Option<Color>,
+    #[serde(default)]
+    pub link: Option<LinkTarget>,
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
-pub enum Background {
-    Blank,
-    Lined { spacing: f32 },
-    Grid { spacing: f32 },
-    Pdf { file_path: String },
+impl TextBox {
+    pub fn new(x: f32, y: f32, font_size: f32, color: Color) -> Self {
+        Self {
+            id: uuid::Uuid::new_v4().to_string(),
+            x,
+            y,
+            width: 240.0,
+            height: font_size * 2.0,
+            content: String::new(),
+            font_size,
+            color,
+            background: None,
+            link: None,
+        }
+    }
 }
 
+// 嵌入的图片，图片文件保存在笔记的资源目录中，这里只记录资源名
 #[derive(Debug, Clone, Serialize, Deserialize)]
-pub struct Note {
+pub struct ImageElement {
     pub id: String,
-    pub title: String,
-    pub pages: Vec<Page>,
-    pub created_at: u64,
-    pub updated_at: u64,
-    pub current_page: usize,
+    pub asset: String,
+    // 未旋转时的左上角
+    pub x: f32,
+    pub y: f32,
+    pub width: f32,
+    pub height: f32,
+    // 绕中心顺时针旋转的角度（弧度）
+    #[serde(default)]
+    pub rotation: f32,
 }
 
-impl Note {
-    pub fn new(title: String) -> Self {
-        let now = SystemTime::now()
-            .duration_since(UNIX_EPOCH)
-            .unwrap()
-            .as_secs();
-        
-        let default_page = Page {
-            strokes: Vec::new(),
-            background: Background::Blank,
-            width: 800.0,
-            height: 1000.0,
-        };
+impl ImageElement {
+    // 按原始尺寸放置图片，超过max_width时等比缩小
+    pub fn fitted(asset: String, x: f32, y: f32, natural_width: u32, natural_height: u32, max_width: f32) -> Self {
+        let natural_width = natural_width.max(1) as f32;
+        let natural_height = natural_height.max(1) as f32;
+        let scale = (max_width / natural_width).min(1.0);
         
         Self {
             id: uuid::Uuid::new_v4().to_string(),
-            title,
-            pages: vec![default_page],
-            created_at: now,
-            updated_at: now,
-            current_page: 0,
+            asset,
+            x,
+            y,
+            width: natural_width * scale,
+            height: natural_height * scale,
+            rotation: 0.0,
         }
     }
     
-    pub fn add_stroke(&mut self, stroke: Stroke) {
-        if let Some(page) = self.pages.get_mut(self.current_page) {
-            page.strokes.push(stroke);
-            self.updated_at = SystemTime::now()
-                .duration_since(UNIX_EPOCH)
-                .unwrap()
-                .as_secs();
+    pub fn center(&self) -> Vec2 {
+        Vec2::new(self.x + self.width / 2.0, self.y + self.height / 2.0)
+    }
+}
+
+// 矢量图形
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub enum ShapeKind {
+    Line { start: Vec2, end: Vec2 },
+    Arrow { start: Vec2, end: Vec2 },
+    Rectangle { min: Vec2, max: Vec2 },
+    // rotation为长轴相对X轴顺时针旋转的角度（弧度）
+    Ellipse { center: Vec2, radius_x: f32, radius_y: f32, rotation: f32 },
+    // 闭合多边形
+    Polygon { points: Vec<Vec2> },
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ShapeElement {
+    pub id: String,
+    pub kind: ShapeKind,
+    pub color: Color,
+    pub thickness: f32,
+    #[serde(default)]
+    pub fill: Option<Color>,
+}
+
+impl ShapeElement {
+    pub fn new(kind: ShapeKind, color: Color, thickness: f32) -> Self {
+        Self {
+            id: uuid::Uuid::new_v4().to_string(),
+            kind,
+            color,
+            thickness,
+            fill: None,
         }
     }
-    
-    pub fn add_page(&mut self, background: Background) {
-        let new_page = Page {
-            strokes: Vec::new(),
-            background,
-            width: 800.0,
-            height: 1000.0,
-        };
-        self.pages.push(new_page);
-        self.current_page = self.pages.len() - 1;
+}
+
+// 椭圆近似为折线时的分段数
+const ELLIPSE_SEGMENTS: usize = 64;
+
+impl ShapeKind {
+    pub fn transform(&mut self, transform: &Transform) {
+        match self {
+            ShapeKind::Line { start, end } | ShapeKind::Arrow { start, end } => {
+                *start = transform.apply(*start);
+                *end = transform.apply(*end);
+            }
+            ShapeKind::Rectangle { min, max } => match transform {
+                // 旋转后不再与坐标轴对齐，改为四边形
+                Transform::Rotate { .. } => {
+                    let points = Rect { min: *min, max: *max }.corners()
+                        .iter()
+                        .map(|corner| transform.apply(*corner))
+                        .collect();
+                    *self = ShapeKind::Polygon { points };
+                }
+                _ => {
+                    let (a, b) = (transform.apply(*min), transform.apply(*max));
+                    *min = Vec2::new(a.x.min(b.x), a.y.min(b.y));
+                    *max = Vec2::new(a.x.max(b.x), a.y.max(b.y));
+                }
+            },
+            ShapeKind::Ellipse { center, radius_x, radius_y, rotation } => {
+                *center = transform.apply(*center);
+                match *transform {
+                    Transform::Translate(_) => {}
+                    Transform::Scale { x, y, .. } => {
+                        // 分别缩放两条半轴；椭圆有旋转时非等比缩放是近似结果
+                        let (sin, cos) = rotation.sin_cos();
+                        let axis_x = Vec2::new(cos * x, sin * y);
+                        let axis_y = Vec2::new(-sin * x, cos * y);
+                        *radius_x *= axis_x.length();
+                        *radius_y *= axis_y.length();
+                        *rotation = axis_x.y.atan2(axis_x.x);
+                    }
+                    Transform::Rotate { angle, .. } => *rotation += angle,
+                }
+            }
+            ShapeKind::Polygon { points } => {
+                for point in points {
+                    *point = transform.apply(*point);
+                }
+            }
+        }
     }
     
-    pub fn remove_page(&mut self, page_index: usize) -> Result<(), String> {
-        if self.pages.len() <= 1 {
-            return Err("Cannot remove the last page".to_string());
+    // 图形的描边路径；闭合图形返回true
+    pub fn path(&self) -> (Vec<Vec2>, bool) {
+        match self {
+            ShapeKind::Line { start, end } | ShapeKind::Arrow { start, end } => (vec![*start, *end], false),
+            ShapeKind::Rectangle { min, max } => (
+                vec![*min, Vec2::new(max.x, min.y), *max, Vec2::new(min.x, max.y)],
+                true,
+            ),
+            ShapeKind::Ellipse { center, radius_x, radius_y, rotation } => {
+                let (sin, cos) = rotation.sin_cos();
+                let points = (0..ELLIPSE_SEGMENTS)
+                    .map(|i| {
+                        let t = std::f32::consts::TAU * i as f32 / ELLIPSE_SEGMENTS as f32;
+                        let (x, y) = (radius_x * t.cos(), radius_y * t.sin());
+                        Vec2::new(center.x + x * cos - y * sin, center.y + x * sin + y * cos)
+                    })
+                    .collect();
+                (points, true)
+            }
+            ShapeKind::Polygon { points } => (points.clone(), true),
         }
+    }
+    
+    // 箭头头部的两条边：(左翼端点, 箭头尖, 右翼端点)
+    pub fn arrow_head(start: Vec2, end: Vec2, thickness: f32) -> [Vec2; 3] {
+        let direction = end - start;
+        let length = direction.length().max(f32::EPSILON);
+        let back = direction * (-1.0 / length);
+        let head_length = (thickness * 4.0).max(12.0).min(length * 0.5);
+        let (sin, cos) = (std::f32::consts::PI / 7.0).sin_cos();
         
-        if page_index >= self.pages.len() {
-            return Err("Page index out of bounds".to_string());
+        let rotate = |v: Vec2, sin: f32| Vec2::new(v.x * cos - v.y * sin, v.x * sin + v.y * cos);
+        [
+            end + rotate(back, sin) * head_length,
+            end,
+            end + rotate(back, -sin) * head_length,
+        ]
+    }
+}
+
+impl Element {
+    pub fn transform(&mut self, transform: &Transform) {
+        match self {
+            Element::Text(text) => {
+                // 文本框不旋转，只移动位置；缩放时字号跟随高度
+                let center = Vec2::new(text.x + text.width / 2.0, text.y + text.height / 2.0);
+                if let Transform::Scale { x, y, .. } = *transform {
+                    text.width *= x.abs();
+                    text.height *= y.abs();
+                    text.font_size *= y.abs();
+                }
+                let center = transform.apply(center);
+                text.x = center.x - text.width / 2.0;
+                text.y = center.y - text.height / 2.0;
+            }
+            Element::Image(image) => {
+                let center = transform.apply(image.center());
+                match *transform {
+                    Transform::Scale { x, y, .. } => {
+                        image.width *= x.abs();
+                        image.height *= y.abs();
+                    }
+                    Transform::Rotate { angle, .. } => image.rotation += angle,
+                    Transform::Translate(_) => {}
+                }
+                image.x = center.x - image.width / 2.0;
+                image.y = center.y - image.height / 2.0;
+            }
+            Element::Shape(shape) => {
+                shape.kind.transform(transform);
+                shape.thickness *= transform.scale_factor();
+            }
         }
-        
-        self.pages.remove(page_index);
-        if self.current_page >= page_index && self.current_page > 0 {
-            self.current_page -= 1;
+    }
+    
+    // 目前只有文本框可以带链接，图片和图形不受影响
+    pub fn set_link(&mut self, link: Option<LinkTarget>) {
+        if let Element::Text(text) = self {
+            text.link = link;
         }
-        
-        Ok(())
     }
     
-    pub fn set_background(&mut self, background: Background) {
-        if let Some(page) = self.pages.get_mut(self.current_page) {
-            page.background = background;
+    // 图片没有颜色，不受影响
+    pub fn set_color(&mut self, color: Color) {
+        match self {
+            Element::Text(text) => text.color = color,
+            Element::Shape(shape) => shape.color = color,
+            Element::Image(_) => {}
+        }
+    }
+    
+    // 只有图形有线宽
+    pub fn set_thickness(&mut self, thickness: f32) {
+        if let Element::Shape(shape) = self {
+            shape.thickness = thickness;
+        }
+    }
+    
+    // 粘贴或复制出的元素需要新的id
+    pub fn regenerate_id(&mut self) {
+        let id = uuid::Uuid::new_v4().to_string();
+        match self {
+            Element::Text(text) => text.id = id,
+            Element::Image(image) => image.id = id,
+            Element::Shape(shape) => shape.id = id,
+        }
+    }
+    
+    pub fn id(&self) -> &str {
+        match self {
+            Element::Text(text) => &text.id,
+            Element::Image(image) => &image.id,
+            Element::Shape(shape) => &shape.id,
+        }
+    }
+    
+    // 轴对齐包围盒，旋转的图片取旋转后的外接矩形
+    pub fn bounds(&self) -> Rect {
+        match self {
+            Element::Text(text) => Rect::from_min_size(Vec2::new(text.x, text.y), text.width, text.height),
+            Element::Image(image) => {
+                let (sin, cos) = image.rotation.sin_cos();
+                let half_width = (image.width * cos.abs() + image.height * sin.abs()) / 2.0;
+                let half_height = (image.width * sin.abs() + image.height * cos.abs()) / 2.0;
+                let center = image.center();
+                Rect {
+                    min: Vec2::new(center.x - half_width, center.y - half_height),
+                    max: Vec2::new(center.x + half_width, center.y + half_height),
+                }
+            }
+            Element::Shape(shape) => {
+                let (mut points, _) = shape.kind.path();
+                if let ShapeKind::Arrow { start, end } = shape.kind {
+                    points.extend(ShapeKind::arrow_head(start, end, shape.thickness));
+                }
+                
+                let half = shape.thickness / 2.0;
+                let mut bounds = Rect {
+                    min: Vec2::new(f32::INFINITY, f32::INFINITY),
+                    max: Vec2::new(f32::NEG_INFINITY, f32::NEG_INFINITY),
+                };
+                for p in points {
+                    bounds.min = Vec2::new(bounds.min.x.min(p.x - half), bounds.min.y.min(p.y - half));
+                    bounds.max = Vec2::new(bounds.max.x.max(p.x + half), bounds.max.y.max(p.y + half));
+                }
+                bounds
+            }
+        }
+    }
+}
+
+// 页面上一项内容的位置（图层索引, 笔画或元素索引），用于选择
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum ItemRef {
+    Stroke { layer: usize, index: usize },
+    Element { layer: usize, index: usize },
+}
+
+// 链接的目标：同一笔记中的页面、其他笔记（可以指定页面）或外部网址。
+// 页面用id引用，页面移动后链接仍然有效
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub enum LinkTarget {
+    Page { page_id: String },
+    Note { note_id: String, page_id: Option<String> },
+    Url { url: String },
+}
+
+// 只允许网页和邮件地址，避免通过链接打开本地文件或程序
+const LINK_URL_SCHEMES: [&str; 3] = ["http://", "https://", "mailto:"];
+
+impl LinkTarget {
+    pub fn url(url: &str) -> Result<LinkTarget, String> {
+        let target = LinkTarget::Url { url: url.trim().to_string() };
+        target.validate()?;
+        Ok(target)
+    }
+    
+    pub fn validate(&self) -> Result<(), String> {
+        match self {
+            LinkTarget::Url { url } => {
+                let lower = url.to_ascii_lowercase();
+                let valid = LINK_URL_SCHEMES.iter()
+                    .any(|scheme| lower.starts_with(scheme) && lower.len() > scheme.len());
+                if valid { Ok(()) } else { Err(format!("Unsupported link URL: {}", url)) }
+            }
+            _ => Ok(()),
+        }
+    }
+    
+    // 内容带到其他笔记时，本笔记的页面链接改为指向原笔记的该页
+    pub fn qualified(&self, note_id: &str) -> LinkTarget {
+        match self {
+            LinkTarget::Page { page_id } => LinkTarget::Note {
+                note_id: note_id.to_string(),
+                page_id: Some(page_id.clone()),
+            },
+            other => other.clone(),
+        }
+    }
+}
+
+// 页面上的一块链接区域（画布坐标），独立于笔画和元素
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct LinkRegion {
+    pub id: String,
+    pub rect: Rect,
+    pub target: LinkTarget,
+}
+
+// 指向某个笔记的链接：来源笔记和页面，以及指向的页面（指向整个笔记时为None）
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct Backlink {
+    pub note_id: String,
+    pub note_title: String,
+    pub page_index: usize,
+    pub target_page_id: Option<String>,
+}
+
+// notes中所有指向note的链接，包括note自己的页面之间的链接
+pub fn backlinks<'a>(note: &Note, notes: impl IntoIterator<Item = &'a Note>) -> Vec<Backlink> {
+    let mut backlinks = Vec::new();
+    for source in notes {
+        for (page_index, page) in source.pages.iter().enumerate() {
+            for target in page.link_targets() {
+                let target_page_id = match target {
+                    LinkTarget::Note { note_id, page_id } if *note_id == note.id => page_id.clone(),
+                    LinkTarget::Page { page_id } if source.id == note.id => Some(page_id.clone()),
+                    _ => continue,
+                };
+                backlinks.push(Backlink {
+                    note_id: source.id.clone(),
+                    note_title: source.title.clone(),
+                    page_index,
+                    target_page_id,
+                });
+            }
+        }
+    }
+    backlinks
+}
+
+// 已保存的笔记中的反向链接与打开的笔记中的合并；打开的笔记可能有未保存的修改，以打开的版本为准
+pub fn merge_backlinks(note: &Note, stored: &[Backlink], open: &[&Note]) -> Vec<Backlink> {
+    let mut merged: Vec<Backlink> = stored.iter()
+        .filter(|link| !open.iter().any(|source| source.id == link.note_id))
+        .cloned()
+        .collect();
+    merged.extend(backlinks(note, open.iter().copied()));
+    merged
+}
+
+// 点击链接时判定命中的距离（画布坐标）
+const LINK_HIT_RADIUS: f32 = 4.0;
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Layer {
+    pub name: String,
+    pub visible: bool,
+    pub locked: bool,
+    pub opacity: f32,
+    // 修改笔画要经过下面的方法，以保持空间索引同步
+    pub strokes: Vec<Stroke>,
+    #[serde(default)]
+    pub elements: Vec<Element>,
+    // 笔画包围盒的网格索引，不保存到文件，加载后重建
+    #[serde(skip)]
+    index: SpatialIndex,
+}
+
+impl Layer {
+    pub fn new(name: String) -> Self {
+        Self {
+            name,
+            visible: true,
+            locked: false,
+            opacity: 1.0,
+            strokes: Vec::new(),
+            elements: Vec::new(),
+            index: SpatialIndex::new(),
+        }
+    }
+    
+    // 隐藏或锁定的图层不接受绘制和擦除
+    pub fn is_editable(&self) -> bool {
+        self.visible && !self.locked
+    }
+    
+    pub fn insert_stroke(&mut self, index: usize, stroke: Stroke) {
+        let index = index.min(self.strokes.len());
+        self.index.insert(index, stroke.bounds());
+        self.strokes.insert(index, stroke);
+    }
+    
+    pub fn push_stroke(&mut self, stroke: Stroke) {
+        self.insert_stroke(self.strokes.len(), stroke);
+    }
+    
+    pub fn remove_stroke(&mut self, index: usize) -> Option<Stroke> {
+        if index >= self.strokes.len() {
+            return None;
         }
+        self.index.remove(index);
+        Some(self.strokes.remove(index))
     }
-}
\ No newline at end of file
+    
+    pub fn set_strokes(&mut self, strokes: Vec<Stroke>) {
+        self.strokes = strokes;
+        self.reindex();
+    }
+    
+    // 就地修改一条笔画并更新它的包围盒
+    pub fn edit_stroke(&mut self, index: usize, edit: impl FnOnce(&mut Stroke)) {
+        if let Some(stroke) = self.strokes.get_mut(index) {
+            edit(stroke);
+            let bounds = stroke.bounds();
+            self.index.update(index, bounds);
+        }
+    }
+    
+    pub fn reindex(&mut self) {
+        self.index = SpatialIndex::from_bounds(self.strokes.iter().map(Stroke::bounds));
+    }
+    
+    // 反序列化或直接改过strokes后索引可能过期，按数量判断并退回逐条计算
+    fn is_indexed(&self) -> bool {
+        self.index.len() == self.strokes.len()
+    }
+    
+    pub fn stroke_bounds(&self, index: usize) -> Option<Rect> {
+        if self.is_indexed() {
+            self.index.bounds(index)
+        } else {
+            self.strokes.get(index)?.bounds()
+        }
+    }
+    
+    // 笔画的绘制顺序：荧光笔在下，其余保持添加顺序
+    pub fn draw_order(&self, mut indices: Vec<usize>) -> Vec<usize> {
+        indices.sort_by_key(|index| !self.strokes[*index].tool.is_underlay());
+        indices
+    }
+    
+    // 包围盒与area相交的笔画索引，按升序
+    pub fn strokes_in(&self, area: &Rect) -> Vec<usize> {
+        if self.is_indexed() {
+            return self.index.query(area);
+        }
+        self.strokes.iter()
+            .enumerate()
+            .filter(|(_, stroke)| stroke.bounds().is_some_and(|bounds| bounds.intersects(area)))
+            .map(|(index, _)| index)
+            .collect()
+    }
+}
+
+pub const MM_PER_INCH: f32 = 25.4;
+pub const POINTS_PER_INCH: f32 = 72.0;
+// 画布坐标每英寸的单位数，与屏幕上100%缩放时的像素对应
+pub const DEFAULT_DPI: f32 = 96.0;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Orientation {
+    Portrait,
+    Landscape,
+}
+
+// 纸张规格，自定义尺寸可以用毫米或英寸给出
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub enum PageFormat {
+    A4,
+    A5,
+    Letter,
+    CustomMm { width: f32, height: f32 },
+    CustomInches { width: f32, height: f32 },
+}
+
+impl PageFormat {
+    pub const PRESETS: [PageFormat; 3] = [PageFormat::A4, PageFormat::A5, PageFormat::Letter];
+    
+    pub fn name(&self) -> &'static str {
+        match self {
+            PageFormat::A4 => "A4",
+            PageFormat::A5 => "A5",
+            PageFormat::Letter => "Letter",
+            PageFormat::CustomMm { .. } | PageFormat::CustomInches { .. } => "自定义",
+        }
+    }
+    
+    pub fn is_custom(&self) -> bool {
+        matches!(self, PageFormat::CustomMm { .. } | PageFormat::CustomInches { .. })
+    }
+    
+    pub fn size(&self, orientation: Orientation) -> PageSize {
+        let size = match *self {
+            PageFormat::A4 => PageSize::new(210.0, 297.0),
+            PageFormat::A5 => PageSize::new(148.0, 210.0),
+            PageFormat::Letter => PageSize::new(8.5 * MM_PER_INCH, 11.0 * MM_PER_INCH),
+            PageFormat::CustomMm { width, height } => PageSize::new(width, height),
+            PageFormat::CustomInches { width, height } => PageSize::new(width * MM_PER_INCH, height * MM_PER_INCH),
+        };
+        size.oriented(orientation)
+    }
+    
+    // 与尺寸相符的预设规格（不区分方向），都不符合时为毫米表示的自定义尺寸
+    pub fn detect(size: PageSize) -> PageFormat {
+        let portrait = size.oriented(Orientation::Portrait);
+        Self::PRESETS.iter()
+            .copied()
+            .find(|format| {
+                let preset = format.size(Orientation::Portrait);
+                (preset.width_mm - portrait.width_mm).abs() < 0.5
+                    && (preset.height_mm - portrait.height_mm).abs() < 0.5
+            })
+            .unwrap_or(PageFormat::CustomMm { width: portrait.width_mm, height: portrait.height_mm })
+    }
+}
+
+// 页面的物理尺寸（毫米），已经包含方向
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct PageSize {
+    pub width_mm: f32,
+    pub height_mm: f32,
+}
+
+impl PageSize {
+    pub fn new(width_mm: f32, height_mm: f32) -> Self {
+        Self { width_mm, height_mm }
+    }
+    
+    pub fn orientation(self) -> Orientation {
+        if self.width_mm > self.height_mm {
+            Orientation::Landscape
+        } else {
+            Orientation::Portrait
+        }
+    }
+    
+    pub fn oriented(self, orientation: Orientation) -> PageSize {
+        if self.orientation() == orientation {
+            self
+        } else {
+            PageSize::new(self.height_mm, self.width_mm)
+        }
+    }
+    
+    // 按dpi换算成画布坐标
+    pub fn to_canvas(self, dpi: f32) -> (f32, f32) {
+        (self.width_mm / MM_PER_INCH * dpi, self.height_mm / MM_PER_INCH * dpi)
+    }
+    
+    // PDF使用的点（1/72英寸）
+    pub fn to_points(self) -> (f32, f32) {
+        self.to_canvas(POINTS_PER_INCH)
+    }
+    
+    pub fn from_points(width_pt: f32, height_pt: f32) -> Self {
+        Self::new(width_pt / POINTS_PER_INCH * MM_PER_INCH, height_pt / POINTS_PER_INCH * MM_PER_INCH)
+    }
+}
+
+impl Default for PageSize {
+    fn default() -> Self {
+        PageFormat::A4.size(Orientation::Portrait)
+    }
+}
+
+// 无限画布导出时内容四周留出的边距（画布坐标）
+pub const INFINITE_CANVAS_MARGIN: f32 = 40.0;
+
+// 固定尺寸的纸张，或随内容扩展、可以向任意方向平移的无限画布
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+pub enum PageKind {
+    #[default]
+    Fixed,
+    Infinite,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Page {
+    // 页面移动、删除后仍然不变，供大纲等引用
+    pub id: String,
+    // 图层按从下到上的顺序排列
+    pub layers: Vec<Layer>,
+    pub active_layer: usize,
+    pub background: Background,
+    // 物理尺寸，以及画布坐标每英寸的单位数
+    pub size: PageSize,
+    pub dpi: f32,
+    // 无限画布的纸张尺寸只用作初始区域和分页导出时每页的大小
+    #[serde(default)]
+    pub kind: PageKind,
+    #[serde(default)]
+    pub links: Vec<LinkRegion>,
+}
+
+impl Page {
+    pub fn new(background: Background, size: PageSize, dpi: f32) -> Self {
+        Self {
+            id: uuid::Uuid::new_v4().to_string(),
+            layers: vec![Layer::new("图层 1".to_string())],
+            active_layer: 0,
+            background,
+            size,
+            dpi,
+            kind: PageKind::Fixed,
+            links: Vec::new(),
+        }
+    }
+    
+    // 加载后重建各图层的空间索引
+    pub fn reindex(&mut self) {
+        for layer in &mut self.layers {
+            layer.reindex();
+        }
+    }
+    
+    pub fn is_infinite(&self) -> bool {
+        self.kind == PageKind::Infinite
+    }
+    
+    // 画布坐标下的宽高，页面内容都使用画布坐标
+    pub fn width(&self) -> f32 {
+        self.size.to_canvas(self.dpi).0
+    }
+    
+    pub fn height(&self) -> f32 {
+        self.size.to_canvas(self.dpi).1
+    }
+    
+    pub fn active_layer(&self) -> Option<&Layer> {
+        self.layers.get(self.active_layer)
+    }
+    
+    pub fn visible_layers(&self) -> impl Iterator<Item = &Layer> {
+        self.layers.iter().filter(|layer| layer.visible)
+    }
+    
+    // 所有图层中的笔画，按绘制顺序
+    pub fn strokes(&self) -> impl Iterator<Item = &Stroke> {
+        self.layers.iter().flat_map(|layer| layer.strokes.iter())
+    }
+    
+    pub fn elements(&self) -> impl Iterator<Item = &Element> {
+        self.layers.iter().flat_map(|layer| layer.elements.iter())
+    }
+    
+    // 按id查找元素所在的(图层索引, 元素索引)
+    pub fn find_element(&self, element_id: &str) -> Option<(usize, usize)> {
+        self.layers.iter().enumerate().find_map(|(layer_index, layer)| {
+            layer.elements.iter()
+                .position(|element| element.id() == element_id)
+                .map(|element_index| (layer_index, element_index))
+        })
+    }
+    
+    // 可见图层中位于该点的最上层元素
+    pub fn element_at(&self, position: Vec2) -> Option<&Element> {
+        self.visible_layers()
+            .flat_map(|layer| layer.elements.iter())
+            .filter(|element| element.bounds().contains(position))
+            .last()
+    }
+    
+    // 可编辑图层中完全落在多边形（套索或选框）内的笔画和元素
+    pub fn items_in_polygon(&self, polygon: &[Vec2]) -> Vec<ItemRef> {
+        let mut items = Vec::new();
+        for (layer_index, layer) in self.layers.iter().enumerate() {
+            if !layer.is_editable() {
+                continue;
+            }
+            
+            // 完全被圈住的笔画一定与圈选范围相交，先用索引筛掉远处的笔画
+            let candidates = Rect::from_points(polygon.iter().copied())
+                .map(|area| layer.strokes_in(&area))
+                .unwrap_or_default();
+            for index in candidates {
+                let stroke = &layer.strokes[index];
+                if !stroke.points.is_empty()
+                    && stroke.points.iter().all(|p| geometry::point_in_polygon(p.position(), polygon))
+                {
+                    items.push(ItemRef::Stroke { layer: layer_index, index });
+                }
+            }
+            for (index, element) in layer.elements.iter().enumerate() {
+                if element.bounds().corners().iter().all(|p| geometry::point_in_polygon(*p, polygon)) {
+                    items.push(ItemRef::Element { layer: layer_index, index });
+                }
+            }
+        }
+        items
+    }
+    
+    pub fn item_bounds(&self, item: ItemRef) -> Option<Rect> {
+        match item {
+            ItemRef::Stroke { layer, index } => self.layers.get(layer)?.stroke_bounds(index),
+            ItemRef::Element { layer, index } => Some(self.layers.get(layer)?.elements.get(index)?.bounds()),
+        }
+    }
+    
+    // 可见图层上所有内容的范围，没有内容时为None
+    pub fn content_bounds(&self) -> Option<Rect> {
+        self.visible_layers()
+            .flat_map(|layer| {
+                (0..layer.strokes.len()).filter_map(|index| layer.stroke_bounds(index))
+                    .chain(layer.elements.iter().map(Element::bounds))
+            })
+            .reduce(|a, b| a.union(&b))
+    }
+    
+    // 纸张在画布坐标中的范围
+    pub fn sheet_bounds(&self) -> Rect {
+        Rect::from_min_size(Vec2::new(0.0, 0.0), self.width(), self.height())
+    }
+    
+    // 导出的区域：固定页面为整张纸，无限画布为内容范围加边距，空白时为初始纸张
+    pub fn export_bounds(&self) -> Rect {
+        match self.kind {
+            PageKind::Fixed => self.sheet_bounds(),
+            PageKind::Infinite => self.content_bounds()
+                .map_or_else(|| self.sheet_bounds(), |bounds| bounds.expand(INFINITE_CANVAS_MARGIN)),
+        }
+    }
+    
+    // 把导出区域按纸张大小切成多页，从左上开始逐行排列
+    pub fn export_tiles(&self) -> Vec<Rect> {
+        let bounds = self.export_bounds();
+        let (tile_width, tile_height) = (self.width(), self.height());
+        let columns = (bounds.width() / tile_width).ceil().max(1.0) as usize;
+        let rows = (bounds.height() / tile_height).ceil().max(1.0) as usize;
+        
+        (0..rows)
+            .flat_map(|row| (0..columns).map(move |column| (row, column)))
+            .map(|(row, column)| {
+                let min = Vec2::new(
+                    bounds.min.x + column as f32 * tile_width,
+                    bounds.min.y + row as f32 * tile_height,
+                );
+                Rect::from_min_size(min, tile_width, tile_height)
+            })
+            .collect()
+    }
+    
+    pub fn items_bounds(&self, items: &[ItemRef]) -> Option<Rect> {
+        items.iter()
+            .filter_map(|item| self.item_bounds(*item))
+            .reduce(|a, b| a.union(&b))
+    }
+    
+    // 对每一项选中内容分别调用笔画或元素的编辑函数，失效的引用直接跳过
+    fn edit_items<S, E>(&mut self, items: &[ItemRef], mut edit_stroke: S, mut edit_element: E)
+    where
+        S: FnMut(&mut Stroke),
+        E: FnMut(&mut Element),
+    {
+        for item in items {
+            match *item {
+                ItemRef::Stroke { layer, index } => {
+                    if let Some(layer) = self.layers.get_mut(layer) {
+                        layer.edit_stroke(index, &mut edit_stroke);
+                    }
+                }
+                ItemRef::Element { layer, index } => {
+                    if let Some(element) = self.layers.get_mut(layer).and_then(|l| l.elements.get_mut(index)) {
+                        edit_element(element);
+                    }
+                }
+            }
+        }
+    }
+    
+    pub fn transform_items(&mut self, items: &[ItemRef], transform: &Transform) {
+        self.edit_items(items, |stroke| stroke.transform(transform), |element| element.transform(transform));
+    }
+    
+    // 给选中的笔画和文本框设置或清除链接
+    pub fn set_items_link(&mut self, items: &[ItemRef], link: Option<LinkTarget>) {
+        self.edit_items(items, |stroke| stroke.link = link.clone(), |element| element.set_link(link.clone()));
+    }
+    
+    // 页面上所有的链接，包括隐藏图层中的内容
+    pub fn link_targets(&self) -> Vec<&LinkTarget> {
+        let strokes = self.strokes().filter_map(|stroke| stroke.link.as_ref());
+        let texts = self.elements().filter_map(|element| match element {
+            Element::Text(text) => text.link.as_ref(),
+            _ => None,
+        });
+        strokes.chain(texts).chain(self.links.iter().map(|link| &link.target)).collect()
+    }
+    
+    // 可见内容上的链接及其范围，用于绘制提示和导出PDF链接注释
+    pub fn link_areas(&self) -> Vec<(Rect, &LinkTarget)> {
+        let mut areas = Vec::new();
+        for layer in self.visible_layers() {
+            for (index, stroke) in layer.strokes.iter().enumerate() {
+                if let (Some(link), Some(bounds)) = (&stroke.link, layer.stroke_bounds(index)) {
+                    areas.push((bounds, link));
+                }
+            }
+            for element in &layer.elements {
+                if let Element::Text(TextBox { link: Some(link), .. }) = element {
+                    areas.push((element.bounds(), link));
+                }
+            }
+        }
+        areas.extend(self.links.iter().map(|link| (link.rect, &link.target)));
+        areas
+    }
+    
+    // 某一点上的链接，上层的内容优先：先找文本框和笔画，最后是链接区域
+    pub fn link_at(&self, point: Vec2) -> Option<&LinkTarget> {
+        for layer in self.layers.iter().rev().filter(|layer| layer.visible) {
+            let text = layer.elements.iter().rev().find_map(|element| match element {
+                Element::Text(text @ TextBox { link: Some(link), .. })
+                    if Rect::from_min_size(Vec2::new(text.x, text.y), text.width, text.height).contains(point) => Some(link),
+                _ => None,
+            });
+            if text.is_some() {
+                return text;
+            }
+            
+            let area = Rect { min: point, max: point }.expand(LINK_HIT_RADIUS);
+            let stroke = layer.strokes_in(&area).into_iter().rev().find_map(|index| {
+                let stroke = &layer.strokes[index];
+                stroke.link.as_ref().filter(|_| stroke.touches_path(&[point], LINK_HIT_RADIUS))
+            });
+            if stroke.is_some() {
+                return stroke;
+            }
+        }
+        self.links.iter().rev().find(|link| link.rect.contains(point)).map(|link| &link.target)
+    }
+    
+    pub fn set_items_color(&mut self, items: &[ItemRef], color: Color) {
+        self.edit_items(items, |stroke| stroke.color = color, |element| element.set_color(color));
+    }
+    
+    pub fn set_items_thickness(&mut self, items: &[ItemRef], thickness: f32) {
+        self.edit_items(
+            items,
+            |stroke| {
+                stroke.thickness = thickness;
+                stroke.invalidate_outline();
+            },
+            |element| element.set_thickness(thickness),
+        );
+    }
+    
+    pub fn remove_items(&mut self, items: &[ItemRef]) {
+        // 从后往前删除，避免前面的删除改变后面的索引
+        let mut sorted = items.to_vec();
+        sorted.sort_by_key(|item| match *item {
+            ItemRef::Stroke { layer, index } => (layer, 0, index),
+            ItemRef::Element { layer, index } => (layer, 1, index),
+        });
+        sorted.dedup();
+        
+        for item in sorted.into_iter().rev() {
+            match item {
+                ItemRef::Stroke { layer, index } => {
+                    if let Some(layer) = self.layers.get_mut(layer) {
+                        layer.remove_stroke(index);
+                    }
+                }
+                ItemRef::Element { layer, index } => {
+                    if let Some(layer) = self.layers.get_mut(layer) {
+                        if index < layer.elements.len() {
+                            layer.elements.remove(index);
+                        }
+                    }
+                }
+            }
+        }
+    }
+}
+
+// 纸张模板的颜色和线宽（画布坐标），屏幕和PDF导出使用同样的设置
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct TemplateStyle {
+    pub line_color: Color,
+    pub line_width: f32,
+    pub paper_color: Color,
+}
+
+impl Default for TemplateStyle {
+    fn default() -> Self {
+        Self {
+            line_color: Color::rgb(0xC8, 0xC8, 0xC8),
+            line_width: 1.0,
+            paper_color: Color::WHITE,
+        }
+    }
+}
+
+// 左侧页边线的默认颜色
+pub const MARGIN_LINE_COLOR: Color = Color::rgb(0xE5, 0x73, 0x73);
+
+// 间距均为画布坐标
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub enum Background {
+    Blank,
+    Lined {
+        spacing: f32,
+        #[serde(default)]
+        style: TemplateStyle,
+    },
+    Grid {
+        spacing: f32,
+        #[serde(default)]
+        style: TemplateStyle,
+    },
+    // 导入的PDF中的一页：page_index从0开始，rotation为页面的/Rotate（0、90、180、270）
+    Pdf {
+        file_path: String,
+        #[serde(default)]
+        page_index: usize,
+        #[serde(default)]
+        rotation: u16,
+    },
+    // 点阵，点位于网格的交点
+    Dotted {
+        spacing: f32,
+        #[serde(default)]
+        style: TemplateStyle,
+    },
+    // 横线加左侧的页边竖线，margin为页边线到纸张左边的距离
+    LinedMargin {
+        spacing: f32,
+        margin: f32,
+        margin_color: Color,
+        #[serde(default)]
+        style: TemplateStyle,
+    },
+    // 康奈尔笔记：左侧线索栏、右侧笔记区和底部总结区
+    Cornell {
+        spacing: f32,
+        #[serde(default)]
+        style: TemplateStyle,
+    },
+    // 五线谱，spacing为谱线间距，staff_gap为相邻两行谱之间的空白
+    MusicStaff {
+        spacing: f32,
+        staff_gap: f32,
+        #[serde(default)]
+        style: TemplateStyle,
+    },
+    // 等距网格：竖线和±30°斜线组成的三角形网格，spacing为三角形的边长
+    Isometric {
+        spacing: f32,
+        #[serde(default)]
+        style: TemplateStyle,
+    },
+}
+
+impl Background {
+    // 可以选择的纸张模板，使用默认的间距和样式
+    pub fn templates() -> Vec<Background> {
+        let style = TemplateStyle::default();
+        vec![
+            Background::Blank,
+            Background::Lined { spacing: 28.0, style },
+            Background::LinedMargin { spacing: 28.0, margin: 96.0, margin_color: MARGIN_LINE_COLOR, style },
+            Background::Grid { spacing: 20.0, style },
+            Background::Dotted { spacing: 20.0, style },
+            Background::Cornell { spacing: 28.0, style },
+            Background::MusicStaff { spacing: 8.0, staff_gap: 48.0, style },
+            Background::Isometric { spacing: 24.0, style },
+        ]
+    }
+    
+    pub fn name(&self) -> &'static str {
+        match self {
+            Background::Blank => "空白",
+            Background::Lined { .. } => "横线",
+            Background::Grid { .. } => "方格",
+            Background::Pdf { .. } => "PDF",
+            Background::Dotted { .. } => "点阵",
+            Background::LinedMargin { .. } => "横线（带页边线）",
+            Background::Cornell { .. } => "康奈尔笔记",
+            Background::MusicStaff { .. } => "五线谱",
+            Background::Isometric { .. } => "等距网格",
+        }
+    }
+    
+    // 空白和PDF背景没有模板样式
+    pub fn style(&self) -> Option<&TemplateStyle> {
+        match self {
+            Background::Blank | Background::Pdf { .. } => None,
+            Background::Lined { style, .. }
+            | Background::Grid { style, .. }
+            | Background::Dotted { style, .. }
+            | Background::LinedMargin { style, .. }
+            | Background::Cornell { style, .. }
+            | Background::MusicStaff { style, .. }
+            | Background::Isometric { style, .. } => Some(style),
+        }
+    }
+    
+    pub fn style_mut(&mut self) -> Option<&mut TemplateStyle> {
+        match self {
+            Background::Blank | Background::Pdf { .. } => None,
+            Background::Lined { style, .. }
+            | Background::Grid { style, .. }
+            | Background::Dotted { style, .. }
+            | Background::LinedMargin { style, .. }
+            | Background::Cornell { style, .. }
+            | Background::MusicStaff { style, .. }
+            | Background::Isometric { style, .. } => Some(style),
+        }
+    }
+    
+    pub fn validate(&self) -> Result<(), String> {
+        let (spacing, extra) = match self {
+            Background::Blank | Background::Pdf { .. } => return Ok(()),
+            Background::LinedMargin { spacing, margin, .. } => (*spacing, *margin),
+            Background::MusicStaff { spacing, staff_gap, .. } => (*spacing, *staff_gap),
+            Background::Lined { spacing, .. }
+            | Background::Grid { spacing, .. }
+            | Background::Dotted { spacing, .. }
+            | Background::Cornell { spacing, .. }
+            | Background::Isometric { spacing, .. } => (*spacing, 0.0),
+        };
+        if spacing <= 0.0 || extra < 0.0 {
+            return Err("Template spacing must be positive".to_string());
+        }
+        if self.style().is_some_and(|style| style.line_width <= 0.0) {
+            return Err("Template line width must be positive".to_string());
+        }
+        Ok(())
+    }
+}
+
+// 笔记的元数据，用于按课程、项目等整理大量笔记
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct NoteMetadata {
+    pub tags: Vec<String>,
+    pub author: String,
+    pub description: String,
+    // 列表中显示的颜色标记
+    pub label: Option<Color>,
+    // 用户自定义的键值属性，按键排序保存
+    pub properties: BTreeMap<String, String>,
+}
+
+impl NoteMetadata {
+    // 标签去掉首尾空白，丢弃空标签，忽略大小写去重并保留第一次出现的写法；属性键同样去掉空白
+    pub fn normalize(&mut self) {
+        let mut tags: Vec<String> = Vec::new();
+        for tag in self.tags.drain(..) {
+            let tag = tag.trim();
+            if !tag.is_empty() && !tags.iter().any(|t| t.eq_ignore_ascii_case(tag)) {
+                tags.push(tag.to_string());
+            }
+        }
+        self.tags = tags;
+        
+        self.author = self.author.trim().to_string();
+        self.properties = std::mem::take(&mut self.properties)
+            .into_iter()
+            .map(|(key, value)| (key.trim().to_string(), value))
+            .filter(|(key, _)| !key.is_empty())
+            .collect();
+    }
+    
+    // 标签比较忽略大小写
+    pub fn has_tag(&self, tag: &str) -> bool {
+        let tag = tag.trim();
+        self.tags.iter().any(|t| t.eq_ignore_ascii_case(tag))
+    }
+}
+
+// 一组笔记中出现过的所有标签，忽略大小写去重后按字母排序，用于侧边栏的标签筛选
+pub fn collect_tags<'a>(metadata: impl IntoIterator<Item = &'a NoteMetadata>) -> Vec<String> {
+    let mut tags: Vec<String> = Vec::new();
+    for tag in metadata.into_iter().flat_map(|metadata| &metadata.tags) {
+        if !tags.iter().any(|t| t.eq_ignore_ascii_case(tag)) {
+            tags.push(tag.clone());
+        }
+    }
+    tags.sort_by_key(|tag| tag.to_lowercase());
+    tags
+}
+
+// 大纲（目录）中的一项，指向某一页，可以嵌套子项。顶层的项也就是页面书签。
+// 条目通过页面id引用页面，页面被删除后条目保留，导出和导航时跳过
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct OutlineEntry {
+    pub title: String,
+    pub page_id: String,
+    #[serde(default)]
+    pub children: Vec<OutlineEntry>,
+}
+
+// 大纲中的位置用从顶层开始的索引序列表示，例如[1, 0]是顶层第二项的第一个子项
+impl OutlineEntry {
+    pub fn new(title: String, page_id: String) -> Self {
+        Self { title, page_id, children: Vec::new() }
+    }
+    
+    pub fn get<'a>(entries: &'a [OutlineEntry], path: &[usize]) -> Option<&'a OutlineEntry> {
+        let (first, rest) = path.split_first()?;
+        let entry = entries.get(*first)?;
+        if rest.is_empty() { Some(entry) } else { Self::get(&entry.children, rest) }
+    }
+    
+    pub fn get_mut<'a>(entries: &'a mut [OutlineEntry], path: &[usize]) -> Option<&'a mut OutlineEntry> {
+        let (first, rest) = path.split_first()?;
+        let entry = entries.get_mut(*first)?;
+        if rest.is_empty() { Some(entry) } else { Self::get_mut(&mut entry.children, rest) }
+    }
+    
+    // path所在的同级列表
+    fn siblings_mut<'a>(entries: &'a mut Vec<OutlineEntry>, path: &[usize]) -> Option<&'a mut Vec<OutlineEntry>> {
+        match path.split_last()? {
+            (_, []) => Some(entries),
+            (_, parent) => Self::get_mut(entries, parent).map(|parent| &mut parent.children),
+        }
+    }
+    
+    // 删除一项及其子项
+    pub fn remove(entries: &mut Vec<OutlineEntry>, path: &[usize]) -> Option<OutlineEntry> {
+        let siblings = Self::siblings_mut(entries, path)?;
+        let index = *path.last()?;
+        (index < siblings.len()).then(|| siblings.remove(index))
+    }
+    
+    // 与相邻的同级项交换位置，返回移动后的位置
+    pub fn move_by(entries: &mut Vec<OutlineEntry>, path: &[usize], up: bool) -> Option<Vec<usize>> {
+        let siblings = Self::siblings_mut(entries, path)?;
+        let index = *path.last()?;
+        let target = if up { index.checked_sub(1)? } else { index + 1 };
+        if target >= siblings.len() || index >= siblings.len() {
+            return None;
+        }
+        siblings.swap(index, target);
+        
+        let mut moved = path.to_vec();
+        *moved.last_mut()? = target;
+        Some(moved)
+    }
+    
+    // 降一级：成为前一个同级项的最后一个子项，返回新的位置
+    pub fn indent(entries: &mut Vec<OutlineEntry>, path: &[usize]) -> Option<Vec<usize>> {
+        let index = *path.last()?;
+        let previous = index.checked_sub(1)?;
+        let entry = Self::remove(entries, path)?;
+        
+        let mut parent = path.to_vec();
+        *parent.last_mut()? = previous;
+        let children = &mut Self::get_mut(entries, &parent)?.children;
+        children.push(entry);
+        parent.push(children.len() - 1);
+        Some(parent)
+    }
+    
+    // 升一级：移到父项之后，返回新的位置
+    pub fn outdent(entries: &mut Vec<OutlineEntry>, path: &[usize]) -> Option<Vec<usize>> {
+        if path.len() < 2 {
+            return None;
+        }
+        let entry = Self::remove(entries, path)?;
+        
+        let mut target = path[..path.len() - 1].to_vec();
+        *target.last_mut()? += 1;
+        Self::siblings_mut(entries, &target)?.insert(*target.last()?, entry);
+        Some(target)
+    }
+}
+
+fn outline_refers_to(entries: &[OutlineEntry], page_id: &str) -> bool {
+    entries.iter().any(|entry| entry.page_id == page_id || outline_refers_to(&entry.children, page_id))
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Note {
+    pub id: String,
+    pub title: String,
+    pub pages: Vec<Page>,
+    pub created_at: u64,
+    pub updated_at: u64,
+    pub current_page: usize,
+    pub format_version: u32,
+    #[serde(default)]
+    pub metadata: NoteMetadata,
+    #[serde(default)]
+    pub outline: Vec<OutlineEntry>,
+    #[serde(skip)]
+    pub history: History,
+    // 内容每次修改后递增，界面据此判断缓存的绘制结果是否过期
+    #[serde(skip)]
+    pub revision: u64,
+}
+
+impl Note {
+    pub fn new(title: String) -> Self {
+        let now = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap()
+            .as_secs();
+        
+        let default_page = Page::new(Background::Blank, PageSize::default(), DEFAULT_DPI);
+        
+        Self {
+            id: uuid::Uuid::new_v4().to_string(),
+            title,
+            pages: vec![default_page],
+            created_at: now,
+            updated_at: now,
+            current_page: 0,
+            format_version: FORMAT_VERSION,
+            metadata: NoteMetadata::default(),
+            outline: Vec::new(),
+            history: History::default(),
+            revision: 0,
+        }
+    }
+    
+    // 元数据不是页面内容，修改不记入撤销历史
+    pub fn set_metadata(&mut self, mut metadata: NoteMetadata) {
+        metadata.normalize();
+        self.metadata = metadata;
+        self.touch();
+    }
+    
+    // 执行一条命令并记入撤销历史，所有修改都应经过这里
+    pub fn execute(&mut self, command: Command) {
+        command.apply(self);
+        self.touch();
+        self.history.push(command);
+    }
+    
+    pub fn undo(&mut self) -> bool {
+        match self.history.pop_undo() {
+            Some(command) => {
+                command.revert(self);
+                self.touch();
+                self.history.push_redo(command);
+                true
+            }
+            None => false,
+        }
+    }
+    
+    pub fn redo(&mut self) -> bool {
+        match self.history.pop_redo() {
+            Some(command) => {
+                command.apply(self);
+                self.touch();
+                self.history.push_undo(command);
+                true
+            }
+            None => false,
+        }
+    }
+    
+    pub fn can_undo(&self) -> bool {
+        self.history.can_undo()
+    }
+    
+    pub fn can_redo(&self) -> bool {
+        self.history.can_redo()
+    }
+    
+    fn touch(&mut self) {
+        self.revision += 1;
+        self.updated_at = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap()
+            .as_secs();
+    }
+    
+    // 笔画加入当前页面的活动图层；图层不可编辑时忽略。提交时拟合一次轮廓
+    pub fn add_stroke(&mut self, stroke: Stroke) {
+        stroke.outline();
+        if let Some(page) = self.pages.get(self.current_page) {
+            let layer_index = page.active_layer;
+            let stroke_index = match page.layers.get(layer_index) {
+                Some(layer) if layer.is_editable() => layer.strokes.len(),
+                _ => return,
+            };
+            self.execute(Command::AddStroke {
+                page_index: self.current_page,
+                layer_index,
+                stroke_index,
+                stroke,
+            });
+        }
+    }
+    
+    // 元素加入当前页面的活动图层；图层不可编辑时忽略
+    pub fn add_element(&mut self, element: Element) {
+        let _ = self.add_element_to(self.current_page, element);
+    }
+    
+    // 新元素会加入的图层（指定页面的活动图层），图层不可编辑时返回错误
+    pub fn editable_layer(&self, page_index: usize) -> Result<usize, String> {
+        let page = self.pages.get(page_index)
+            .ok_or_else(|| "Page index out of bounds".to_string())?;
+        match page.layers.get(page.active_layer) {
+            Some(layer) if layer.is_editable() => Ok(page.active_layer),
+            _ => Err("Layer is locked".to_string()),
+        }
+    }
+    
+    // 元素加入指定页面的活动图层，图层不可编辑时返回错误
+    pub fn add_element_to(&mut self, page_index: usize, element: Element) -> Result<(), String> {
+        let layer_index = self.editable_layer(page_index)?;
+        let element_index = self.pages[page_index].layers[layer_index].elements.len();
+        self.execute(Command::AddElement {
+            page_index,
+            layer_index,
+            element_index,
+            element,
+        });
+        Ok(())
+    }
+    
+    // 修改指定元素，所在图层不可编辑时返回错误
+    pub fn edit_element<F>(&mut self, page_index: usize, element_id: &str, edit: F) -> Result<(), String>
+    where
+        F: FnOnce(&mut Element),
+    {
+        let page = self.pages.get(page_index)
+            .ok_or_else(|| "Page index out of bounds".to_string())?;
+        let (layer_index, element_index) = page.find_element(element_id)
+            .ok_or_else(|| "Element not found".to_string())?;
+        if !page.layers[layer_index].is_editable() {
+            return Err("Layer is locked".to_string());
+        }
+        
+        self.edit_page(page_index, |page| edit(&mut page.layers[layer_index].elements[element_index]))
+    }
+    
+    pub fn remove_element(&mut self, page_index: usize, element_id: &str) -> Result<(), String> {
+        let page = self.pages.get(page_index)
+            .ok_or_else(|| "Page index out of bounds".to_string())?;
+        let (layer_index, element_index) = page.find_element(element_id)
+            .ok_or_else(|| "Element not found".to_string())?;
+        if !page.layers[layer_index].is_editable() {
+            return Err("Layer is locked".to_string());
+        }
+        
+        self.edit_page(page_index, |page| {
+            page.layers[layer_index].elements.remove(element_index);
+        })
+    }
+    
+    // 在末尾添加页面并跳转到新页面
+    pub fn add_page(&mut self, background: Background) {
+        let _ = self.insert_page(self.pages.len(), background);
+    }
+    
+    // 在指定位置插入页面并跳转到新页面，纸张尺寸与当前页相同
+    pub fn insert_page(&mut self, page_index: usize, background: Background) -> Result<(), String> {
+        if page_index > self.pages.len() {
+            return Err("Page index out of bounds".to_string());
+        }
+        
+        let (size, dpi) = self.pages.get(self.current_page)
+            .map_or((PageSize::default(), DEFAULT_DPI), |page| (page.size, page.dpi));
+        self.execute(Command::InsertPage {
+            page_index,
+            page: Page::new(background, size, dpi),
+            previous_page: self.current_page,
+        });
+        Ok(())
+    }
+    
+    // 在固定页面和无限画布之间切换，内容不变
+    pub fn set_page_kind(&mut self, page_index: usize, kind: PageKind) -> Result<(), String> {
+        self.edit_page(page_index, |page| page.kind = kind)
+    }
+    
+    // 修改纸张尺寸，页面内容的画布坐标保持不变
+    pub fn set_page_size(&mut self, page_index: usize, size: PageSize) -> Result<(), String> {
+        if size.width_mm <= 0.0 || size.height_mm <= 0.0 {
+            return Err("Page size must be positive".to_string());
+        }
+        self.edit_page(page_index, |page| page.size = size)
+    }
+    
+    // 在原页面之后插入一份副本并跳转过去，副本及其中的元素使用新的id
+    pub fn duplicate_page(&mut self, page_index: usize) -> Result<(), String> {
+        let mut page = self.pages.get(page_index)
+            .ok_or_else(|| "Page index out of bounds".to_string())?
+            .clone();
+        page.id = uuid::Uuid::new_v4().to_string();
+        for layer in &mut page.layers {
+            for element in &mut layer.elements {
+                element.regenerate_id();
+            }
+        }
+        
+        self.execute(Command::InsertPage {
+            page_index: page_index + 1,
+            page,
+            previous_page: self.current_page,
+        });
+        Ok(())
+    }
+    
+    pub fn move_page(&mut self, from: usize, to: usize) -> Result<(), String> {
+        self.move_pages(from..from + 1, to)
+    }
+    
+    // 把一段页面移动到to，to是移动后第一页所在的位置
+    pub fn move_pages(&mut self, range: Range<usize>, to: usize) -> Result<(), String> {
+        if range.is_empty() || range.end > self.pages.len() {
+            return Err("Page range out of bounds".to_string());
+        }
+        if to + range.len() > self.pages.len() {
+            return Err("Target page index out of bounds".to_string());
+        }
+        if to == range.start {
+            return Ok(());
+        }
+        
+        self.execute(Command::MovePages {
+            from: range.start,
+            count: range.len(),
+            to,
+        });
+        Ok(())
+    }
+    
+    pub fn remove_pages(&mut self, range: Range<usize>) -> Result<(), String> {
+        if range.is_empty() || range.end > self.pages.len() {
+            return Err("Page range out of bounds".to_string());
+        }
+        if range.len() >= self.pages.len() {
+            return Err("Cannot remove the last page".to_string());
+        }
+        
+        self.execute(Command::RemovePages {
+            page_index: range.start,
+            pages: self.pages[range].to_vec(),
+            previous_page: self.current_page,
+        });
+        Ok(())
+    }
+    
+    pub fn remove_page(&mut self, page_index: usize) -> Result<(), String> {
+        if self.pages.len() <= 1 {
+            return Err("Cannot remove the last page".to_string());
+        }
+        
+        if page_index >= self.pages.len() {
+            return Err("Page index out of bounds".to_string());
+        }
+        
+        self.execute(Command::RemovePage {
+            page_index,
+            page: self.pages[page_index].clone(),
+            previous_page: self.current_page,
+        });
+        
+        Ok(())
+    }
+    
+    pub fn page_index_of(&self, page_id: &str) -> Option<usize> {
+        self.pages.iter().position(|page| page.id == page_id)
+    }
+    
+    // 大纲中是否有指向该页的条目
+    pub fn is_bookmarked(&self, page_index: usize) -> bool {
+        self.pages.get(page_index).is_some_and(|page| outline_refers_to(&self.outline, &page.id))
+    }
+    
+    // 给页面加书签，即在大纲顶层末尾添加一项；没有标题时使用页码
+    pub fn bookmark_page(&mut self, page_index: usize, title: Option<String>) -> Result<(), String> {
+        let page_id = self.pages.get(page_index)
+            .ok_or_else(|| "Page index out of bounds".to_string())?
+            .id
+            .clone();
+        let title = title.unwrap_or_else(|| format!("第 {} 页", page_index + 1));
+        self.edit_outline(|outline| outline.push(OutlineEntry::new(title, page_id)));
+        Ok(())
+    }
+    
+    // 整体替换大纲，条目只能指向笔记中的页面。
+    // 已有的条目可能指向被删除的页面，允许保留它们，以便撤销删除后恢复
+    pub fn set_outline(&mut self, outline: Vec<OutlineEntry>) -> Result<(), String> {
+        fn find_invalid<'a>(note: &Note, entries: &'a [OutlineEntry]) -> Option<&'a OutlineEntry> {
+            entries.iter().find_map(|entry| {
+                let valid = note.page_index_of(&entry.page_id).is_some()
+                    || outline_refers_to(&note.outline, &entry.page_id);
+                if valid { find_invalid(note, &entry.children) } else { Some(entry) }
+            })
+        }
+        if let Some(entry) = find_invalid(self, &outline) {
+            return Err(format!("Outline entry refers to a missing page: {}", entry.title));
+        }
+        
+        self.edit_outline(|current| *current = outline);
+        Ok(())
+    }
+    
+    // 在大纲的副本上修改，有变化时作为一条可撤销的命令执行
+    pub fn edit_outline<F>(&mut self, edit: F)
+    where
+        F: FnOnce(&mut Vec<OutlineEntry>),
+    {
+        let mut outline = self.outline.clone();
+        edit(&mut outline);
+        if outline != self.outline {
+            self.execute(Command::SetOutline {
+                old: self.outline.clone(),
+                new: outline,
+            });
+        }
+    }
+    
+    pub fn set_background(&mut self, background: Background) {
+        if let Some(page) = self.pages.get(self.current_page) {
+            self.execute(Command::SetBackground {
+                page_index: self.current_page,
+                old: page.background.clone(),
+                new: background,
+            });
+        }
+    }
+    
+    // 更换指定页面的纸张模板或调整模板样式
+    pub fn set_page_background(&mut self, page_index: usize, background: Background) -> Result<(), String> {
+        background.validate()?;
+        let page = self.pages.get(page_index).ok_or_else(|| "Page index out of bounds".to_string())?;
+        if page.background != background {
+            self.execute(Command::SetBackground {
+                page_index,
+                old: page.background.clone(),
+                new: background,
+            });
+        }
+        Ok(())
+    }
+    
+    // 用橡皮擦路径擦除当前页面可编辑图层中的笔画，返回是否有内容被擦除
+    pub fn erase(&mut self, eraser_path: &[Point], radius: f32, mode: EraserMode) -> bool {
+        let page_index = self.current_page;
+        let page = match self.pages.get(page_index) {
+            Some(page) => page,
+            None => return false,
+        };
+        
+        let path: Vec<Vec2> = eraser_path.iter().map(Point::position).collect();
+        let erased: Vec<(usize, Vec<Stroke>)> = page.layers.iter()
+            .enumerate()
+            .filter(|(_, layer)| layer.is_editable())
+            .filter_map(|(index, layer)| {
+                erase_strokes(layer, &path, radius, mode).map(|strokes| (index, strokes))
+            })
+            .collect();
+        
+        if erased.is_empty() {
+            return false;
+        }
+        
+        let _ = self.edit_page(page_index, |page| {
+            for (index, strokes) in erased {
+                page.layers[index].set_strokes(strokes);
+            }
+        });
+        true
+    }
+    
+    pub fn add_layer(&mut self, page_index: usize, name: String) -> Result<(), String> {
+        self.edit_page(page_index, |page| {
+            page.layers.push(Layer::new(name));
+            page.active_layer = page.layers.len() - 1;
+        })
+    }
+    
+    pub fn remove_layer(&mut self, page_index: usize, layer_index: usize) -> Result<(), String> {
+        let page = self.pages.get(page_index)
+            .ok_or_else(|| "Page index out of bounds".to_string())?;
+        if page.layers.len() <= 1 {
+            return Err("Cannot remove the last layer".to_string());
+        }
+        if layer_index >= page.layers.len() {
+            return Err("Layer index out of bounds".to_string());
+        }
+        
+        self.edit_page(page_index, |page| {
+            page.layers.remove(layer_index);
+            if page.active_layer >= layer_index && page.active_layer > 0 {
+                page.active_layer -= 1;
+            }
+        })
+    }
+    
+    // 调整图层顺序，active_layer跟随原来的图层
+    pub fn move_layer(&mut self, page_index: usize, from: usize, to: usize) -> Result<(), String> {
+        self.edit_layer_page(page_index, from, |page| {
+            let to = to.min(page.layers.len() - 1);
+            let active = page.active_layer;
+            let layer = page.layers.remove(from);
+            page.layers.insert(to, layer);
+            
+            page.active_layer = if active == from {
+                to
+            } else if from < active && active <= to {
+                active - 1
+            } else if to <= active && active < from {
+                active + 1
+            } else {
+                active
+            };
+        })
+    }
+    
+    // 切换活动图层属于视图状态，不进入撤销历史
+    pub fn set_active_layer(&mut self, page_index: usize, layer_index: usize) -> Result<(), String> {
+        let page = self.pages.get_mut(page_index)
+            .ok_or_else(|| "Page index out of bounds".to_string())?;
+        if layer_index >= page.layers.len() {
+            return Err("Layer index out of bounds".to_string());
+        }
+        page.active_layer = layer_index;
+        Ok(())
+    }
+    
+    pub fn rename_layer(&mut self, page_index: usize, layer_index: usize, name: String) -> Result<(), String> {
+        self.edit_layer_page(page_index, layer_index, |page| page.layers[layer_index].name = name)
+    }
+    
+    pub fn set_layer_visible(&mut self, page_index: usize, layer_index: usize, visible: bool) -> Result<(), String> {
+        self.edit_layer_page(page_index, layer_index, |page| page.layers[layer_index].visible = visible)
+    }
+    
+    pub fn set_layer_locked(&mut self, page_index: usize, layer_index: usize, locked: bool) -> Result<(), String> {
+        self.edit_layer_page(page_index, layer_index, |page| page.layers[layer_index].locked = locked)
+    }
+    
+    pub fn set_layer_opacity(&mut self, page_index: usize, layer_index: usize, opacity: f32) -> Result<(), String> {
+        let opacity = opacity.clamp(0.0, 1.0);
+        self.edit_layer_page(page_index, layer_index, |page| page.layers[layer_index].opacity = opacity)
+    }
+    
+    // 校验图层索引后再修改页面
+    fn edit_layer_page<F>(&mut self, page_index: usize, layer_index: usize, edit: F) -> Result<(), String>
+    where
+        F: FnOnce(&mut Page),
+    {
+        let page = self.pages.get(page_index)
+            .ok_or_else(|| "Page index out of bounds".to_string())?;
+        if layer_index >= page.layers.len() {
+            return Err("Layer index out of bounds".to_string());
+        }
+        self.edit_page(page_index, edit)
+    }
+    
+    // 选中内容的编辑，每次操作作为一条整页替换记录，可以撤销
+    pub fn transform_items(&mut self, page_index: usize, items: &[ItemRef], transform: &Transform) -> Result<(), String> {
+        self.edit_page(page_index, |page| page.transform_items(items, transform))
+    }
+    
+    // link为None时清除选中内容上的链接
+    pub fn set_items_link(&mut self, page_index: usize, items: &[ItemRef], link: Option<LinkTarget>) -> Result<(), String> {
+        if let Some(link) = &link {
+            self.validate_link(link)?;
+        }
+        self.edit_page(page_index, |page| page.set_items_link(items, link))
+    }
+    
+    // 在页面上添加链接区域，返回区域的id
+    pub fn add_link_region(&mut self, page_index: usize, rect: Rect, target: LinkTarget) -> Result<String, String> {
+        if rect.width() <= 0.0 || rect.height() <= 0.0 {
+            return Err("Link region must not be empty".to_string());
+        }
+        self.validate_link(&target)?;
+        
+        let id = uuid::Uuid::new_v4().to_string();
+        let link = LinkRegion { id: id.clone(), rect, target };
+        self.edit_page(page_index, |page| page.links.push(link))?;
+        Ok(id)
+    }
+    
+    pub fn remove_link_regions(&mut self, page_index: usize, link_ids: &[String]) -> Result<(), String> {
+        self.edit_page(page_index, |page| page.links.retain(|link| !link_ids.contains(&link.id)))
+    }
+    
+    // 本笔记中的页面链接必须指向存在的页面；其他笔记在链接时可能还没有加载，不做检查
+    fn validate_link(&self, link: &LinkTarget) -> Result<(), String> {
+        link.validate()?;
+        if let LinkTarget::Page { page_id } = link {
+            if self.page_index_of(page_id).is_none() {
+                return Err("Link target page not found".to_string());
+            }
+        }
+        Ok(())
+    }
+    
+    pub fn set_items_color(&mut self, page_index: usize, items: &[ItemRef], color: Color) -> Result<(), String> {
+        self.edit_page(page_index, |page| page.set_items_color(items, color))
+    }
+    
+    pub fn set_items_thickness(&mut self, page_index: usize, items: &[ItemRef], thickness: f32) -> Result<(), String> {
+        self.edit_page(page_index, |page| page.set_items_thickness(items, thickness))
+    }
+    
+    pub fn remove_items(&mut self, page_index: usize, items: &[ItemRef]) -> Result<(), String> {
+        self.edit_page(page_index, |page| page.remove_items(items))
+    }
+    
+    // 以整页快照的方式对页面做任意修改，并记入撤销历史
+    pub fn edit_page<F>(&mut self, page_index: usize, edit: F) -> Result<(), String>
+    where
+        F: FnOnce(&mut Page),
+    {
+        let before = self.pages.get(page_index)
+            .ok_or_else(|| "Page index out of bounds".to_string())?
+            .clone();
+        let mut after = before.clone();
+        edit(&mut after);
+        
+        self.execute(Command::ReplacePage {
+            page_index,
+            before,
+            after,
+        });
+        
+        Ok(())
+    }
+    
+    // 接收在别处（前端）修改过的整份笔记。页面结构不变时，内容变了的页面和大纲记为命令，可以撤销；
+    // 页面增删或换了顺序时旧命令的页码不再可靠，只能清空撤销历史
+    pub fn apply_edited(&mut self, mut edited: Note) {
+        for page in &mut edited.pages {
+            page.reindex();
+        }
+        
+        let same_pages = self.pages.len() == edited.pages.len()
+            && self.pages.iter().zip(&edited.pages).all(|(a, b)| a.id == b.id);
+        if same_pages {
+            for (page_index, after) in edited.pages.into_iter().enumerate() {
+                let before = &self.pages[page_index];
+                if serde_json::to_value(before).ok() != serde_json::to_value(&after).ok() {
+                    let before = before.clone();
+                    self.execute(Command::ReplacePage { page_index, before, after });
+                }
+            }
+            if edited.outline != self.outline {
+                let old = std::mem::take(&mut self.outline);
+                self.execute(Command::SetOutline { old, new: edited.outline });
+            }
+        } else {
+            self.pages = edited.pages;
+            self.outline = edited.outline;
+            self.history.clear();
+            self.touch();
+        }
+        
+        self.title = edited.title;
+        self.metadata = edited.metadata;
+        self.current_page = edited.current_page.min(self.pages.len().saturating_sub(1));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    
+    fn horizontal_stroke() -> Stroke {
+        Stroke {
+            points: (0..=10)
+                .map(|i| Point {
+                    x: i as f32 * 10.0,
+                    y: 50.0,
+                    pressure: i as f32 / 10.0,
+                    timestamp: i as u64,
+                    tilt: None,
+                })
+                .collect(),
+            color: Color::BLACK,
+            thickness: 2.0,
+            tool: StrokeTool::Pen,
+            link: None,
+            fitted: Default::default(),
+        }
+    }
+    
+    fn vertical_path(x: f32) -> Vec<Vec2> {
+        vec![Vec2::new(x, 0.0), Vec2::new(x, 100.0)]
+    }
+    
+    #[test]
+    fn test_touches_path() {
+        let stroke = horizontal_stroke();
+        
+        assert!(stroke.touches_path(&vertical_path(50.0), 1.0));
+        assert!(stroke.touches_path(&[Vec2::new(30.0, 54.0)], 3.5));
+        assert!(!stroke.touches_path(&[Vec2::new(30.0, 60.0)], 3.5));
+        assert!(!stroke.touches_path(&vertical_path(150.0), 5.0));
+    }
+    
+    #[test]
+    fn test_split_keeps_both_sides() {
+        let stroke = horizontal_stroke();
+        let pieces = stroke.split_by_eraser(&vertical_path(50.0), 5.0).unwrap();
+        
+        assert_eq!(pieces.len(), 2);
+        
+        let left_end = pieces[0].points.last().unwrap();
+        let right_start = pieces[1].points.first().unwrap();
+        // 切口正好落在擦除范围（半径 + 半个笔画宽度）的边界上
+        let reach = 5.0 + stroke.width() / 2.0;
+        assert!((left_end.x - (50.0 - reach)).abs() < 1e-3);
+        assert!((right_start.x - (50.0 + reach)).abs() < 1e-3);
+        // 原有的采样点保持不变，只多出两个切口
+        assert_eq!(pieces[0].points.len() + pieces[1].points.len(), stroke.points.len() - 1 + 2);
+        assert!(pieces[0].points[..5].iter().zip(&stroke.points).all(|(a, b)| a.x == b.x));
+        
+        assert_eq!(pieces[0].points[0].pressure, 0.0);
+        assert_eq!(pieces[1].points.last().unwrap().pressure, 1.0);
+    }
+    
+    #[test]
+    fn test_split_interpolates_pressure() {
+        let stroke = horizontal_stroke();
+        let pieces = stroke.split_by_eraser(&vertical_path(50.0), 5.0).unwrap();
+        
+        for piece in &pieces {
+            for point in &piece.points {
+                assert!((point.pressure - point.x / 100.0).abs() < 1e-4);
+            }
+        }
+    }
+    
+    #[test]
+    fn test_split_untouched_stroke() {
+        let stroke = horizontal_stroke();
+        assert!(stroke.split_by_eraser(&vertical_path(200.0), 5.0).is_none());
+    }
+    
+    #[test]
+    fn test_erase_modes_are_undoable() {
+        let mut note = Note::new("test".to_string());
+        note.add_stroke(horizontal_stroke());
+        
+        let path = vec![
+            Point { x: 50.0, y: 0.0, pressure: 1.0, timestamp: 0, tilt: None },
+            Point { x: 50.0, y: 100.0, pressure: 1.0, timestamp: 1, tilt: None },
+        ];
+        
+        assert!(note.erase(&path, 5.0, EraserMode::Precise));
+        assert_eq!(note.pages[0].layers[0].strokes.len(), 2);
+        
+        assert!(note.erase(&path[..1], 60.0, EraserMode::Stroke));
+        assert!(note.pages[0].layers[0].strokes.is_empty());
+        
+        note.undo();
+        note.undo();
+        assert_eq!(note.pages[0].layers[0].strokes.len(), 1);
+        assert_eq!(note.pages[0].layers[0].strokes[0].points.len(), 11);
+    }
+    
+    #[test]
+    fn test_locked_layer_is_not_erased() {
+        let mut note = Note::new("test".to_string());
+        note.add_stroke(horizontal_stroke());
+        note.set_layer_locked(0, 0, true).unwrap();
+        
+        let path = vec![Point { x: 50.0, y: 50.0, pressure: 1.0, timestamp: 0, tilt: None }];
+        assert!(!note.erase(&path, 5.0, EraserMode::Stroke));
+        
+        note.add_stroke(horizontal_stroke());
+        assert_eq!(note.pages[0].layers[0].strokes.len(), 1);
+    }
+    
+    #[test]
+    fn test_lasso_selection_transform_is_undoable() {
+        let mut note = Note::new("test".to_string());
+        note.add_stroke(horizontal_stroke());
+        note.add_element(Element::Shape(ShapeElement::new(
+            ShapeKind::Line { start: Vec2::new(0.0, 200.0), end: Vec2::new(50.0, 200.0) },
+            Color::BLACK,
+            2.0,
+        )));
+        
+        let lasso = [Vec2::new(-10.0, 40.0), Vec2::new(110.0, 40.0), Vec2::new(110.0, 60.0), Vec2::new(-10.0, 60.0)];
+        let items = note.pages[0].items_in_polygon(&lasso);
+        assert_eq!(items, vec![ItemRef::Stroke { layer: 0, index: 0 }]);
+        
+        note.transform_items(0, &items, &Transform::Translate(Vec2::new(5.0, 10.0))).unwrap();
+        note.set_items_color(0, &items, Color::rgb(255, 0, 0)).unwrap();
+        let stroke = &note.pages[0].layers[0].strokes[0];
+        assert_eq!((stroke.points[0].x, stroke.points[0].y), (5.0, 60.0));
+        assert_eq!(stroke.color, Color::rgb(255, 0, 0));
+        
+        note.undo();
+        note.undo();
+        let stroke = &note.pages[0].layers[0].strokes[0];
+        assert_eq!((stroke.points[0].x, stroke.points[0].y), (0.0, 50.0));
+        assert_eq!(stroke.color, Color::BLACK);
+        
+        // 锁定图层的内容不能被选中
+        note.set_layer_locked(0, 0, true).unwrap();
+        assert!(note.pages[0].items_in_polygon(&lasso).is_empty());
+    }
+    
+    // 返回笔记和各页的id，用id区分页面
+    fn numbered_note(count: usize) -> (Note, Vec<String>) {
+        let mut note = Note::new("test".to_string());
+        for _ in 1..count {
+            note.add_page(Background::Blank);
+        }
+        let ids = note.pages.iter().map(|page| page.id.clone()).collect();
+        (note, ids)
+    }
+    
+    // 各页在原来顺序中的位置，新插入或复制出的页面记为"新"
+    fn page_order(note: &Note, ids: &[String]) -> Vec<String> {
+        note.pages.iter()
+            .map(|page| ids.iter().position(|id| *id == page.id).map_or("新".to_string(), |i| i.to_string()))
+            .collect()
+    }
+    
+    #[test]
+    fn test_move_pages_keeps_current_page() {
+        let (mut note, ids) = numbered_note(5);
+        note.current_page = 1;
+        
+        note.move_pages(0..2, 3).unwrap();
+        assert_eq!(page_order(&note, &ids), vec!["2", "3", "4", "0", "1"]);
+        assert_eq!(note.current_page, 4);
+        
+        note.current_page = 0;
+        note.move_page(0, 4).unwrap();
+        assert_eq!(page_order(&note, &ids), vec!["3", "4", "0", "1", "2"]);
+        assert_eq!(note.current_page, 4);
+        
+        note.undo();
+        note.undo();
+        assert_eq!(page_order(&note, &ids), vec!["0", "1", "2", "3", "4"]);
+        assert!(note.move_pages(3..5, 4).is_err());
+    }
+    
+    #[test]
+    fn test_insert_duplicate_and_remove_pages() {
+        let (mut note, ids) = numbered_note(3);
+        
+        note.insert_page(1, Background::Blank).unwrap();
+        assert_eq!(note.current_page, 1);
+        note.duplicate_page(2).unwrap();
+        assert_eq!(page_order(&note, &ids), vec!["0", "新", "1", "新", "2"]);
+        assert_eq!(note.current_page, 3);
+        // 复制出的页面有新的id，尺寸和背景与原页相同
+        assert_eq!(note.pages[3].size, note.pages[2].size);
+        assert_eq!(note.pages[3].background, note.pages[2].background);
+        
+        note.remove_pages(1..4).unwrap();
+        assert_eq!(page_order(&note, &ids), vec!["0", "2"]);
+        assert_eq!(note.current_page, 0);
+        assert!(note.remove_pages(0..2).is_err());
+        
+        note.undo();
+        assert_eq!(page_order(&note, &ids), vec!["0", "新", "1", "新", "2"]);
+        assert_eq!(note.current_page, 3);
+    }
+    
+    #[test]
+    fn test_outline_follows_pages() {
+        let (mut note, _) = numbered_note(4);
+        note.bookmark_page(0, Some("第一章".to_string())).unwrap();
+        note.bookmark_page(2, None).unwrap();
+        note.bookmark_page(3, None).unwrap();
+        assert!(note.is_bookmarked(2) && !note.is_bookmarked(1));
+        
+        // [第一章, 第 3 页, 第 4 页] → 第 3 页成为第一章的子项，第 4 页成为第 3 页的子项
+        note.edit_outline(|outline| {
+            assert_eq!(OutlineEntry::indent(outline, &[1]), Some(vec![0, 0]));
+            assert_eq!(OutlineEntry::indent(outline, &[1]), Some(vec![0, 1]));
+            assert_eq!(OutlineEntry::indent(outline, &[0, 1]), Some(vec![0, 0, 0]));
+        });
+        assert_eq!(note.outline.len(), 1);
+        assert_eq!(OutlineEntry::get(&note.outline, &[0, 0, 0]).unwrap().title, "第 4 页");
+        
+        // 条目跟随页面移动；删除页面后条目保留，撤销后恢复指向
+        let page_id = OutlineEntry::get(&note.outline, &[0, 0]).unwrap().page_id.clone();
+        note.move_page(2, 0).unwrap();
+        assert_eq!(note.page_index_of(&page_id), Some(0));
+        note.remove_page(0).unwrap();
+        assert_eq!(note.page_index_of(&page_id), None);
+        assert!(note.set_outline(note.outline.clone()).is_ok());
+        note.undo();
+        assert_eq!(note.page_index_of(&page_id), Some(0));
+        
+        let outdented = {
+            let mut outline = note.outline.clone();
+            OutlineEntry::outdent(&mut outline, &[0, 0, 0]).unwrap();
+            outline
+        };
+        note.set_outline(outdented).unwrap();
+        assert_eq!(note.outline[0].children.len(), 2);
+        note.undo();
+        assert_eq!(note.outline[0].children.len(), 1);
+        
+        let invalid = vec![OutlineEntry::new("?".to_string(), "missing".to_string())];
+        assert!(note.set_outline(invalid).is_err());
+    }
+    
+    #[test]
+    fn test_apply_edited_then_undo() {
+        let (mut note, ids) = numbered_note(2);
+        note.current_page = 0;
+        note.add_stroke(horizontal_stroke());
+        
+        // 前端在第2页加了一笔：这一步可以撤销，之前的操作仍然可以继续撤销
+        let mut edited = note.clone();
+        edited.pages[1].layers[0].strokes.push(horizontal_stroke());
+        edited.title = "改名".to_string();
+        note.apply_edited(edited);
+        assert_eq!(note.title, "改名");
+        assert_eq!(note.pages[1].layers[0].strokes.len(), 1);
+        assert!(note.undo());
+        assert!(note.pages[1].layers[0].strokes.is_empty());
+        assert_eq!(note.pages[0].layers[0].strokes.len(), 1);
+        assert!(note.undo());
+        assert!(note.pages[0].layers[0].strokes.is_empty());
+        assert!(note.redo() && note.redo());
+        assert_eq!(note.pages[1].layers[0].strokes.len(), 1);
+        
+        // 页面结构变了时丢弃旧的历史，撤销不会放回过期的页面
+        let mut edited = note.clone();
+        edited.pages.swap(0, 1);
+        note.apply_edited(edited);
+        assert_eq!(page_order(&note, &ids), vec!["1", "0"]);
+        assert!(!note.undo());
+        assert_eq!(note.pages[1].layers[0].strokes.len(), 1);
+    }
+    
+    #[test]
+    fn test_links_and_backlinks() {
+        let (mut note, _) = numbered_note(3);
+        note.current_page = 0;
+        note.add_stroke(horizontal_stroke());
+        let third = LinkTarget::Page { page_id: note.pages[2].id.clone() };
+        let stroke = [ItemRef::Stroke { layer: 0, index: 0 }];
+        note.set_items_link(0, &stroke, Some(third.clone())).unwrap();
+        assert_eq!(note.pages[0].link_at(Vec2::new(50.0, 51.0)), Some(&third));
+        assert_eq!(note.pages[0].link_at(Vec2::new(50.0, 80.0)), None);
+        
+        // 链接区域位于内容下方，笔画上的链接优先
+        let url = LinkTarget::url("https://example.com").unwrap();
+        let region = Rect { min: Vec2::new(0.0, 0.0), max: Vec2::new(200.0, 100.0) };
+        let region_id = note.add_link_region(0, region, url.clone()).unwrap();
+        assert_eq!(note.pages[0].link_at(Vec2::new(50.0, 51.0)), Some(&third));
+        assert_eq!(note.pages[0].link_at(Vec2::new(50.0, 80.0)), Some(&url));
+        assert_eq!(note.pages[0].link_areas().len(), 2);
+        
+        assert!(LinkTarget::url("file:///etc/passwd").is_err());
+        assert!(note.add_link_region(0, Rect { min: region.min, max: region.min }, url.clone()).is_err());
+        let missing = LinkTarget::Page { page_id: "missing".to_string() };
+        assert!(note.set_items_link(0, &stroke, Some(missing)).is_err());
+        
+        let mut other = Note::new("other".to_string());
+        let target = LinkTarget::Note { note_id: note.id.clone(), page_id: None };
+        other.add_link_region(0, region, target).unwrap();
+        let backlinks = backlinks(&note, [&note, &other]);
+        assert_eq!(backlinks.len(), 2);
+        assert_eq!(backlinks[0].target_page_id.as_deref(), Some(note.pages[2].id.as_str()));
+        assert_eq!((backlinks[1].note_title.as_str(), backlinks[1].target_page_id.as_ref()), ("other", None));
+        
+        // 保存的版本中还有链接，打开的版本已经删除时以打开的为准
+        let mut edited = other.clone();
+        edited.pages[0].links.clear();
+        assert_eq!(merge_backlinks(&note, &backlinks, &[&note]).len(), 2);
+        assert_eq!(merge_backlinks(&note, &backlinks, &[&note, &edited]).len(), 1);
+        
+        note.remove_link_regions(0, &[region_id]).unwrap();
+        assert!(note.pages[0].links.is_empty());
+        note.undo();
+        assert_eq!(note.pages[0].links.len(), 1);
+    }
+    
+    #[test]
+    fn test_background_templates() {
+        // 旧文件中的横线和网格背景没有样式，使用默认样式
+        let lined: Background = serde_json::from_str(r#"{"Lined":{"spacing":30.0}}"#).unwrap();
+        assert_eq!(lined.style(), Some(&TemplateStyle::default()));
+        let pdf: Background = serde_json::from_str(r#"{"Pdf":{"file_path":"slides.pdf"}}"#).unwrap();
+        assert_eq!(pdf, Background::Pdf { file_path: "slides.pdf".to_string(), page_index: 0, rotation: 0 });
+        
+        let mut note = Note::new("test".to_string());
+        let mut staff = Background::templates().into_iter()
+            .find(|template| matches!(template, Background::MusicStaff { .. }))
+            .unwrap();
+        staff.style_mut().unwrap().paper_color = Color::rgb(0xFA, 0xF3, 0xE0);
+        note.set_page_background(0, staff.clone()).unwrap();
+        assert_eq!(note.pages[0].background, staff);
+        
+        let invalid = Background::Dotted { spacing: 0.0, style: TemplateStyle::default() };
+        assert!(note.set_page_background(0, invalid).is_err());
+        note.undo();
+        assert_eq!(note.pages[0].background, Background::Blank);
+    }
+    
+    #[test]
+    fn test_page_size_orientation_and_scale() {
+        let size = PageFormat::A4.size(Orientation::Landscape);
+        assert_eq!((size.width_mm, size.height_mm), (297.0, 210.0));
+        assert_eq!(PageFormat::detect(size), PageFormat::A4);
+        
+        let (width_pt, height_pt) = PageFormat::Letter.size(Orientation::Portrait).to_points();
+        assert!((width_pt - 612.0).abs() < 1e-3 && (height_pt - 792.0).abs() < 1e-3);
+        let letter = PageSize::from_points(612.0, 792.0);
+        assert_eq!(PageFormat::detect(letter), PageFormat::Letter);
+        let custom = PageFormat::CustomInches { width: 4.0, height: 6.0 }.size(Orientation::Portrait);
+        assert_eq!(custom.to_canvas(DEFAULT_DPI), (384.0, 576.0));
+        
+        let mut note = Note::new("test".to_string());
+        note.set_page_size(0, size).unwrap();
+        note.insert_page(1, Background::Blank).unwrap();
+        assert_eq!(note.pages[1].size, size);
+        assert!(note.pages[1].width() > note.pages[1].height());
+        
+        note.undo();
+        note.undo();
+        assert_eq!(note.pages[0].size.orientation(), Orientation::Portrait);
+        assert!(note.set_page_size(0, PageSize::new(0.0, 100.0)).is_err());
+    }
+    
+    #[test]
+    fn test_infinite_page_bounds_and_tiles() {
+        let mut note = Note::new("test".to_string());
+        note.set_page_size(0, PageSize::new(MM_PER_INCH, MM_PER_INCH)).unwrap();
+        note.set_page_kind(0, PageKind::Infinite).unwrap();
+        assert_eq!(note.pages[0].export_tiles().len(), 1);
+        
+        note.add_stroke(Stroke {
+            points: vec![
+                Point { x: -100.0, y: -20.0, pressure: 1.0, timestamp: 0, tilt: None },
+                Point { x: 150.0, y: 10.0, pressure: 1.0, timestamp: 1, tilt: None },
+            ],
+            color: Color::BLACK,
+            thickness: 4.0,
+            tool: StrokeTool::Pen,
+            link: None,
+            fitted: Default::default(),
+        });
+        let page = &note.pages[0];
+        
+        let bounds = page.export_bounds();
+        assert_eq!(bounds.min, Vec2::new(-142.0, -62.0));
+        assert_eq!(bounds.max, Vec2::new(192.0, 52.0));
+        // 96×96的纸张横向需要4页、纵向2页
+        let tiles = page.export_tiles();
+        assert_eq!(tiles.len(), 8);
+        assert_eq!(tiles[0].min, bounds.min);
+        assert_eq!(tiles[4].min, Vec2::new(-142.0, 34.0));
+        
+        note.undo();
+        note.undo();
+        assert_eq!(note.pages[0].export_bounds(), note.pages[0].sheet_bounds());
+    }
+    
+    #[test]
+    fn test_stroke_index_follows_edits() {
+        let mut note = Note::new("test".to_string());
+        for i in 0..3 {
+            let x = i as f32 * 300.0;
+            note.add_stroke(Stroke {
+                points: vec![
+                    Point { x, y: 0.0, pressure: 1.0, timestamp: 0, tilt: None },
+                    Point { x: x + 10.0, y: 10.0, pressure: 1.0, timestamp: 1, tilt: None },
+                ],
+                color: Color::BLACK,
+                thickness: 2.0,
+                tool: StrokeTool::Pen,
+                link: None,
+                fitted: Default::default(),
+            });
+        }
+        let near = Rect { min: Vec2::new(295.0, 0.0), max: Vec2::new(320.0, 5.0) };
+        assert_eq!(note.pages[0].layers[0].strokes_in(&near), vec![1]);
+        
+        let first = [ItemRef::Stroke { layer: 0, index: 0 }];
+        note.transform_items(0, &first, &Transform::Translate(Vec2::new(300.0, 0.0))).unwrap();
+        assert_eq!(note.pages[0].layers[0].strokes_in(&near), vec![0, 1]);
+        note.remove_items(0, &[ItemRef::Stroke { layer: 0, index: 1 }]).unwrap();
+        assert_eq!(note.pages[0].layers[0].strokes_in(&near), vec![0]);
+        
+        // 反序列化得到的图层没有索引，结果应与重建索引后相同
+        let mut loaded: Page = serde_json::from_str(&serde_json::to_string(&note.pages[0]).unwrap()).unwrap();
+        let far = Rect { min: Vec2::new(590.0, 0.0), max: Vec2::new(700.0, 5.0) };
+        assert_eq!(loaded.layers[0].strokes_in(&far), vec![1]);
+        loaded.reindex();
+        assert_eq!(loaded.layers[0].strokes_in(&far), vec![1]);
+    }
+    
+    #[test]
+    fn test_color_parsing() {
+        let red = Color::rgb(255, 0, 0);
+        for text in ["#F00", "#ff0000", "#FF0000FF", "rgb(255, 0, 0)", "rgb(100% 0% 0%)", "Red"] {
+            assert_eq!(Color::parse(text), Some(red), "{}", text);
+        }
+        assert_eq!(Color::parse("rgba(0, 0, 255, 0.5)"), Some(Color::rgba(0, 0, 255, 128)));
+        assert_eq!(Color::parse("rgb(0 0 255 / 50%)"), Some(Color::rgba(0, 0, 255, 128)));
+        assert_eq!(Color::parse("#11223344"), Some(Color::rgba(0x11, 0x22, 0x33, 0x44)));
+        assert_eq!(Color::parse("rebeccapurple"), Some(Color::rgb(0x66, 0x33, 0x99)));
+        for text in ["#12", "#GGGGGG", "rgb(1, 2)", "blurple"] {
+            assert_eq!(Color::parse(text), None, "{}", text);
+        }
+        
+        // 不透明颜色按旧格式保存，无法解析的旧字符串按黑色加载
+        assert_eq!(serde_json::to_string(&red).unwrap(), "\"#FF0000\"");
+        assert_eq!(serde_json::to_string(&Color::rgba(0, 0, 0, 0)).unwrap(), "\"#00000000\"");
+        let loaded: Vec<Color> = serde_json::from_str(r##"["#ff0000", "green", "not a color"]"##).unwrap();
+        assert_eq!(loaded, vec![red, Color::rgb(0, 128, 0), Color::BLACK]);
+    }
+    
+    #[test]
+    fn test_metadata_tags_and_defaults() {
+        let mut note = Note::new("线性代数".to_string());
+        let mut metadata = NoteMetadata {
+            tags: vec![" Math ".to_string(), "math".to_string(), "".to_string(), "2024秋".to_string()],
+            label: Color::parse("teal"),
+            ..Default::default()
+        };
+        metadata.properties.insert(" course ".to_string(), "MATH 221".to_string());
+        note.set_metadata(metadata);
+        
+        assert_eq!(note.metadata.tags, vec!["Math", "2024秋"]);
+        assert!(note.metadata.has_tag("MATH"));
+        assert_eq!(note.metadata.properties.get("course").map(String::as_str), Some("MATH 221"));
+        
+        let mut other = Note::new("物理".to_string());
+        other.metadata.tags = vec!["physics".to_string(), "MATH".to_string()];
+        assert_eq!(collect_tags([&note.metadata, &other.metadata]), vec!["2024秋", "Math", "physics"]);
+        
+        // 没有元数据的旧文件按空元数据加载
+        let mut json = serde_json::to_value(&note).unwrap();
+        json.as_object_mut().unwrap().remove("metadata");
+        let loaded: Note = serde_json::from_value(json).unwrap();
+        assert_eq!(loaded.metadata, NoteMetadata::default());
+    }
+    
+    #[test]
+    fn test_move_layer_keeps_active_layer() {
+        let mut note = Note::new("test".to_string());
+        note.add_layer(0, "题目".to_string()).unwrap();
+        note.add_layer(0, "答案".to_string()).unwrap();
+        note.set_active_layer(0, 0).unwrap();
+        
+        note.move_layer(0, 0, 2).unwrap();
+        let page = &note.pages[0];
+        assert_eq!(page.layers[2].name, "图层 1");
+        assert_eq!(page.active_layer, 2);
+    }
+    
+    #[test]
+    fn test_fitted_image_and_rotated_bounds() {
+        // 超过最大宽度时等比缩小，小图保持原始尺寸
+        let large = ImageElement::fitted("a.png".to_string(), 10.0, 20.0, 1600, 800, 400.0);
+        assert_eq!((large.x, large.y, large.width, large.height), (10.0, 20.0, 400.0, 200.0));
+        let small = ImageElement::fitted("b.png".to_string(), 0.0, 0.0, 100, 50, 400.0);
+        assert_eq!((small.width, small.height), (100.0, 50.0));
+        assert_eq!(small.center(), Vec2::new(50.0, 25.0));
+        
+        // 旋转90°后外接矩形的宽高互换，中心不变
+        let mut image = small;
+        image.rotation = std::f32::consts::FRAC_PI_2;
+        let bounds = Element::Image(image.clone()).bounds();
+        assert!((bounds.width() - 50.0).abs() < 1e-3);
+        assert!((bounds.height() - 100.0).abs() < 1e-3);
+        assert!((bounds.min.x - 25.0).abs() < 1e-3 && (bounds.min.y + 25.0).abs() < 1e-3);
+        
+        image.rotation = std::f32::consts::FRAC_PI_4;
+        let bounds = Element::Image(image).bounds();
+        let diagonal = 150.0 * std::f32::consts::FRAC_1_SQRT_2;
+        assert!((bounds.width() - diagonal).abs() < 1e-3);
+        assert!((bounds.height() - diagonal).abs() < 1e-3);
+    }
+    
+    #[test]
+    fn test_locked_layer_rejects_elements() {
+        let mut note = Note::new("test".to_string());
+        let image = || Element::Image(ImageElement::fitted("a.png".to_string(), 0.0, 0.0, 10, 10, 100.0));
+        assert_eq!(note.editable_layer(0), Ok(0));
+        assert!(note.editable_layer(1).is_err());
+        
+        note.set_layer_locked(0, 0, true).unwrap();
+        assert!(note.editable_layer(0).is_err());
+        assert!(note.add_element_to(0, image()).is_err());
+        assert!(note.pages[0].layers[0].elements.is_empty());
+    }
+}
@@ -0,0 +1,394 @@
+use crate::geometry::Vec2;
+use crate::note::{Stroke, StrokeTool};
+use crate::smoothing;
+use std::f32::consts::PI;
+
+// 压力为0时笔画宽度相对于设定粗细的比例
+const MIN_WIDTH_RATIO: f32 = 0.25;
+// 每个半圆笔帽的分段数
+const CAP_SEGMENTS: usize = 8;
+// 铅笔纹理短划的长度和每个采样间隔内的数量
+const GRAIN_LENGTH: f32 = 1.5;
+const GRAIN_PER_SAMPLE: usize = 2;
+// 铅笔颗粒短划的线宽（画布坐标），屏幕和PDF共用
+pub const PENCIL_GRAIN_WIDTH: f32 = 0.6;
+
+// 笔画两端的形状：钢笔和铅笔为圆头，荧光笔和马克笔为平头（向外延伸半个笔宽）
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Cap {
+    Round,
+    Square,
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct StrokeSample {
+    pub position: Vec2,
+    pub pressure: f32,
+}
+
+// 笔画的填充轮廓：沿中心线两侧的边界以及两端的圆形笔帽
+#[derive(Debug, Clone, Default)]
+pub struct Outline {
+    pub left: Vec<Vec2>,
+    pub right: Vec<Vec2>,
+    start_center: Option<Vec2>,
+    end_center: Option<Vec2>,
+    start_cap: Vec<Vec2>,
+    end_cap: Vec<Vec2>,
+}
+
+// 轮廓的三角剖分结果，供egui网格使用
+#[derive(Debug, Clone, Default)]
+pub struct Tessellation {
+    pub vertices: Vec<Vec2>,
+    pub indices: Vec<u32>,
+}
+
+// 笔画拟合后的几何：导出用的矢量路径、屏幕用的三角网格和铅笔纹理
+#[derive(Debug, Clone, Default)]
+pub struct FittedOutline {
+    pub path: Vec<PathSegment>,
+    pub tessellation: Tessellation,
+    pub grain: Vec<(Vec2, Vec2)>,
+}
+
+// 矢量路径的绘制指令，PDF和SVG导出共用
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum PathSegment {
+    MoveTo(Vec2),
+    LineTo(Vec2),
+    CurveTo(Vec2, Vec2, Vec2),
+    Close,
+}
+
+pub fn width_for_pressure(thickness: f32, pressure: f32) -> f32 {
+    thickness * (MIN_WIDTH_RATIO + (1.0 - MIN_WIDTH_RATIO) * pressure.clamp(0.0, 1.0))
+}
+
+// 只在存储的采样点处计算的轮廓，两侧边界再拟合成曲线
+pub fn control_outline(stroke: &Stroke) -> Outline {
+    let samples: Vec<StrokeSample> = stroke.points.iter()
+        .map(|point| StrokeSample {
+            position: point.position(),
+            pressure: point.pressure,
+        })
+        .collect();
+    tool_outline(samples, stroke)
+}
+
+// 按工具调整宽度、压力和笔帽
+fn tool_outline(mut samples: Vec<StrokeSample>, stroke: &Stroke) -> Outline {
+    if !stroke.tool.is_pressure_sensitive() {
+        for sample in &mut samples {
+            sample.pressure = 1.0;
+        }
+    }
+    let cap = if stroke.tool.has_flat_tip() { Cap::Square } else { Cap::Round };
+    stroke_outline(&samples, stroke.width(), cap)
+}
+
+pub fn stroke_outline(samples: &[StrokeSample], thickness: f32, cap: Cap) -> Outline {
+    // 去掉重合的采样点，避免方向无法确定
+    let mut points: Vec<StrokeSample> = Vec::with_capacity(samples.len());
+    for sample in samples {
+        if points.last().is_none_or(|last| last.position.distance(sample.position) > 0.01) {
+            points.push(*sample);
+        }
+    }
+
+    if points.is_empty() {
+        return Outline::default();
+    }
+
+    let mut outline = Outline::default();
+    let mut directions = Vec::with_capacity(points.len());
+    let mut half_widths = Vec::with_capacity(points.len());
+
+    for i in 0..points.len() {
+        let prev = points[i.saturating_sub(1)].position;
+        let next = points[(i + 1).min(points.len() - 1)].position;
+        let direction = normalize(next - prev);
+        let normal = Vec2::new(-direction.y, direction.x);
+        let half_width = width_for_pressure(thickness, points[i].pressure) / 2.0;
+
+        outline.left.push(points[i].position + normal * half_width);
+        outline.right.push(points[i].position - normal * half_width);
+        directions.push(direction);
+        half_widths.push(half_width);
+    }
+
+    let last = points.len() - 1;
+    outline.end_center = Some(points[last].position);
+    let cap_points = |center: Vec2, direction: Vec2, radius: f32| match cap {
+        Cap::Round => cap_arc(center, direction, radius),
+        Cap::Square => square_cap(center, direction, radius),
+    };
+    outline.end_cap = cap_points(points[last].position, directions[last], half_widths[last]);
+    outline.start_center = Some(points[0].position);
+    outline.start_cap = cap_points(points[0].position, directions[0] * -1.0, half_widths[0]);
+
+    outline
+}
+
+// 拟合一次后缓存在笔画上，屏幕绘制和PDF/SVG导出使用同一份几何
+pub fn fit_outline(stroke: &Stroke) -> FittedOutline {
+    let outline = control_outline(stroke);
+    if outline.is_empty() {
+        return FittedOutline::default();
+    }
+
+    // 两侧边界拟合成贝塞尔曲线，每对相邻采样点之间左右各一段
+    let left = smoothing::fit_curve(&outline.left);
+    let right = smoothing::fit_curve(&outline.right);
+
+    // 矢量路径：左边界、终点笔帽、右边界（反向）、起点笔帽
+    let mut path = vec![PathSegment::MoveTo(outline.left[0])];
+    path.extend(left.iter().map(|c| PathSegment::CurveTo(c.control1, c.control2, c.end)));
+    path.extend(outline.end_cap.iter().chain(outline.right.last()).map(|p| PathSegment::LineTo(*p)));
+    path.extend(right.iter().rev().map(|c| PathSegment::CurveTo(c.control2, c.control1, c.start)));
+    path.extend(outline.start_cap.iter().map(|p| PathSegment::LineTo(*p)));
+    path.push(PathSegment::Close);
+
+    // 屏幕上把同一组曲线展开成折线，左右两段取相同的分段数，保持三角带一一对应
+    let mut flattened = Outline {
+        left: vec![outline.left[0]],
+        right: vec![outline.right[0]],
+        ..outline
+    };
+    for (l, r) in left.iter().zip(&right) {
+        let length = l.start.distance(l.end).max(r.start.distance(r.end));
+        let steps = (length / smoothing::FLATTEN_STEP).ceil().max(1.0) as usize;
+        for step in 1..=steps {
+            let t = step as f32 / steps as f32;
+            flattened.left.push(l.point_at(t));
+            flattened.right.push(r.point_at(t));
+        }
+    }
+
+    FittedOutline {
+        path,
+        tessellation: flattened.tessellate(),
+        grain: pencil_grain(stroke),
+    }
+}
+
+// 从中心线一侧绕过direction方向到另一侧的半圆，不含两个端点
+fn cap_arc(center: Vec2, direction: Vec2, radius: f32) -> Vec<Vec2> {
+    let normal = Vec2::new(-direction.y, direction.x);
+    (1..CAP_SEGMENTS)
+        .map(|k| {
+            let theta = PI * k as f32 / CAP_SEGMENTS as f32;
+            center
+                + normal * (theta.cos() * radius)
+                + direction * (theta.sin() * radius)
+        })
+        .collect()
+}
+
+// 平头笔帽：沿direction延伸半个笔宽的两个角
+fn square_cap(center: Vec2, direction: Vec2, radius: f32) -> Vec<Vec2> {
+    let normal = Vec2::new(-direction.y, direction.x);
+    let tip = center + direction * radius;
+    vec![tip + normal * radius, tip - normal * radius]
+}
+
+// 铅笔的颗粒纹理：在笔画范围内散布的短划，位置由确定的伪随机数决定，屏幕和PDF上完全一致
+pub fn pencil_grain(stroke: &Stroke) -> Vec<(Vec2, Vec2)> {
+    if stroke.tool != StrokeTool::Pencil {
+        return Vec::new();
+    }
+
+    let samples = smoothing::flatten_stroke(&stroke.points);
+    let seed = stroke.points.first().map_or(0, |point| point.timestamp as u32);
+    let mut grain = Vec::with_capacity(samples.len() * GRAIN_PER_SAMPLE);
+    for (i, pair) in samples.windows(2).enumerate() {
+        let direction = normalize(pair[1].position - pair[0].position);
+        let normal = Vec2::new(-direction.y, direction.x);
+        let half_width = width_for_pressure(stroke.width(), pair[0].pressure) / 2.0;
+        for k in 0..GRAIN_PER_SAMPLE {
+            let along = noise(seed, i, k * 2);
+            let across = noise(seed, i, k * 2 + 1) * 2.0 - 1.0;
+            let start = pair[0].position.lerp(pair[1].position, along)
+                + normal * (across * half_width);
+            grain.push((start, start + direction * GRAIN_LENGTH));
+        }
+    }
+    grain
+}
+
+// [0, 1]之间的整数哈希噪声
+fn noise(seed: u32, i: usize, k: usize) -> f32 {
+    let mut h = seed ^ (i as u32).wrapping_mul(0x9E37_79B9) ^ (k as u32).wrapping_mul(0x85EB_CA6B);
+    h ^= h >> 16;
+    h = h.wrapping_mul(0x7FEB_352D);
+    h ^= h >> 15;
+    h = h.wrapping_mul(0x846C_A68B);
+    h ^= h >> 16;
+    h as f32 / u32::MAX as f32
+}
+
+fn normalize(v: Vec2) -> Vec2 {
+    let length = v.length();
+    if length <= f32::EPSILON {
+        Vec2::new(1.0, 0.0)
+    } else {
+        v * (1.0 / length)
+    }
+}
+
+impl Outline {
+    pub fn is_empty(&self) -> bool {
+        self.left.is_empty()
+    }
+
+    // 闭合的轮廓多边形：左边界、终点笔帽、右边界（反向）、起点笔帽
+    pub fn polygon(&self) -> Vec<Vec2> {
+        let mut polygon = Vec::with_capacity(
+            self.left.len() + self.right.len() + self.start_cap.len() + self.end_cap.len()
+        );
+        polygon.extend(self.left.iter().copied());
+        polygon.extend(self.end_cap.iter().copied());
+        polygon.extend(self.right.iter().rev().copied());
+        polygon.extend(self.start_cap.iter().copied());
+        polygon
+    }
+
+    pub fn tessellate(&self) -> Tessellation {
+        let mut tessellation = Tessellation::default();
+        if self.is_empty() {
+            return tessellation;
+        }
+
+        // 两侧边界组成三角带
+        for i in 0..self.left.len() {
+            tessellation.vertices.push(self.left[i]);
+            tessellation.vertices.push(self.right[i]);
+            if i + 1 < self.left.len() {
+                let base = (i * 2) as u32;
+                tessellation.indices.extend_from_slice(&[base, base + 1, base + 2]);
+                tessellation.indices.extend_from_slice(&[base + 1, base + 3, base + 2]);
+            }
+        }
+
+        let last = self.left.len() - 1;
+        if let Some(center) = self.end_center {
+            add_fan(&mut tessellation, center, self.left[last], &self.end_cap, self.right[last]);
+        }
+        if let Some(center) = self.start_center {
+            add_fan(&mut tessellation, center, self.right[0], &self.start_cap, self.left[0]);
+        }
+
+        tessellation
+    }
+}
+
+fn add_fan(tessellation: &mut Tessellation, center: Vec2, from: Vec2, arc: &[Vec2], to: Vec2) {
+    let center_index = tessellation.vertices.len() as u32;
+    tessellation.vertices.push(center);
+    tessellation.vertices.push(from);
+    tessellation.vertices.extend_from_slice(arc);
+    tessellation.vertices.push(to);
+
+    let rim = arc.len() as u32 + 2;
+    for k in 0..rim - 1 {
+        let a = center_index + 1 + k;
+        tessellation.indices.extend_from_slice(&[center_index, a, a + 1]);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::note::{Color, Point};
+
+    fn sample(x: f32, pressure: f32) -> StrokeSample {
+        StrokeSample { position: Vec2::new(x, 0.0), pressure }
+    }
+
+    #[test]
+    fn test_width_follows_pressure() {
+        let outline = stroke_outline(&[sample(0.0, 0.0), sample(10.0, 0.5), sample(20.0, 1.0)], 8.0, Cap::Round);
+
+        let widths: Vec<f32> = outline.left.iter()
+            .zip(&outline.right)
+            .map(|(l, r)| l.distance(*r))
+            .collect();
+        assert_eq!(widths, vec![2.0, 5.0, 8.0]);
+    }
+
+    #[test]
+    fn test_single_point_is_round_dot() {
+        let outline = stroke_outline(&[sample(5.0, 1.0), sample(5.0, 1.0)], 4.0, Cap::Round);
+        let polygon = outline.polygon();
+
+        assert_eq!(polygon.len(), 2 + 2 * (CAP_SEGMENTS - 1));
+        for point in polygon {
+            assert!((point.distance(Vec2::new(5.0, 0.0)) - 2.0).abs() < 1e-4);
+        }
+    }
+
+    #[test]
+    fn test_marker_is_flat_and_ignores_pressure() {
+        let points = [(0.0, 0.2), (10.0, 1.0), (20.0, 0.5)]
+            .iter()
+            .map(|&(x, pressure)| Point { x, y: 0.0, pressure, timestamp: 0, tilt: None })
+            .collect();
+        let stroke = Stroke { points, color: Color::BLACK, thickness: 2.0, tool: StrokeTool::Marker, link: None, fitted: Default::default() };
+        let outline = control_outline(&stroke);
+
+        assert!(outline.left.iter().zip(&outline.right).all(|(l, r)| (l.distance(*r) - 4.0).abs() < 1e-4));
+        // 平头笔帽只有两个角，延伸到终点外半个笔宽
+        assert_eq!(outline.end_cap, vec![Vec2::new(22.0, 2.0), Vec2::new(22.0, -2.0)]);
+        assert!(pencil_grain(&stroke).is_empty());
+    }
+
+    #[test]
+    fn test_pencil_grain_is_deterministic_and_inside_stroke() {
+        let points = (0..5).map(|i| Point { x: i as f32 * 10.0, y: 0.0, pressure: 1.0, timestamp: 7, tilt: None }).collect();
+        let stroke = Stroke { points, color: Color::BLACK, thickness: 4.0, tool: StrokeTool::Pencil, link: None, fitted: Default::default() };
+        let grain = pencil_grain(&stroke);
+
+        assert!(!grain.is_empty());
+        assert_eq!(grain, pencil_grain(&stroke));
+        assert!(grain.iter().all(|(start, _)| start.y.abs() <= 2.0 + 1e-4));
+    }
+
+    #[test]
+    fn test_tessellation_indices_in_range() {
+        let outline = stroke_outline(&[sample(0.0, 1.0), sample(10.0, 1.0), sample(20.0, 0.2)], 4.0, Cap::Round);
+        let tessellation = outline.tessellate();
+
+        assert_eq!(tessellation.indices.len() % 3, 0);
+        assert!(tessellation.indices.iter().all(|&i| (i as usize) < tessellation.vertices.len()));
+    }
+
+    #[test]
+    fn test_fitted_outline_matches_path() {
+        let points = [(0.0, 0.0, 0.3), (10.0, 8.0, 1.0), (20.0, 0.0, 0.6), (30.0, 5.0, 0.8)]
+            .iter()
+            .map(|&(x, y, pressure)| Point { x, y, pressure, timestamp: 0, tilt: None })
+            .collect();
+        let stroke = Stroke { points, color: Color::BLACK, thickness: 4.0, tool: StrokeTool::Pen, link: None, fitted: Default::default() };
+        let fitted = fit_outline(&stroke);
+        let outline = control_outline(&stroke);
+
+        // 路径经过两侧边界的每个采样点，右边界反向
+        let ends: Vec<Vec2> = fitted.path.iter()
+            .filter_map(|segment| match segment {
+                PathSegment::CurveTo(_, _, end) => Some(*end),
+                _ => None,
+            })
+            .collect();
+        let mut expected: Vec<Vec2> = outline.left[1..].to_vec();
+        expected.extend(outline.right.iter().rev().skip(1));
+        assert_eq!(ends, expected);
+        assert_eq!(fitted.path.last(), Some(&PathSegment::Close));
+
+        // 屏幕网格的顶点也都落在同一组曲线上：采样点处的顶点与路径一致
+        let vertices = &fitted.tessellation.vertices;
+        for point in outline.left.iter().chain(&outline.right) {
+            assert!(vertices.iter().any(|v| v.distance(*point) < 1e-4));
+        }
+        assert!(fitted.tessellation.indices.iter().all(|&i| (i as usize) < vertices.len()));
+    }
+}
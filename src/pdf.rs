@@ -1,119 +1,706 @@
-use crate::note::{Note, Page, Background};
-use std::path::Path;
+use crate::markdown::{self, BlockKind};
+use crate::geometry::{Rect, Vec2};
+use crate::note::{Color, LinkTarget, Note, OutlineEntry, Page, PageKind, PageSize, Background, Stroke, Element, TextBox, ImageElement, ShapeElement, ShapeKind, TEXT_BOX_PADDING, POINTS_PER_INCH, DEFAULT_DPI};
+use crate::outline::{PathSegment, PENCIL_GRAIN_WIDTH};
+use crate::pdf_render;
+use crate::storage;
+use crate::template::{self, Mark};
+use serde::{Deserialize, Serialize};
+use std::path::{Path, PathBuf};
 
-pub fn export_to_pdf(note: &Note, file_path: &str) -> Result<(), Box<dyn std::error::Error>> {
+// 无限画布的导出方式：整个内容缩放到一页，或按纸张大小分成多页
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+pub enum InfiniteExport {
+    #[default]
+    SinglePage,
+    Tiled,
+}
+
+pub fn export_to_pdf(note: &Note, file_path: &str, infinite_export: InfiniteExport) -> Result<(), Box<dyn std::error::Error>> {
     // 使用pdf库创建PDF文档
     let mut doc = pdf::Document::new();
+    let cjk_font = embed_cjk_font(&mut doc);
     
-    for (page_index, page) in note.pages.iter().enumerate() {
-        let mut page_builder = doc.add_page(pdf::Pt(page.width as f64), pdf::Pt(page.height as f64));
-        
-        // 设置页面背景
-        match &page.background {
-            Background::Pdf { file_path: pdf_path } => {
-                // 如果是PDF背景，可以在这里处理
-                // 这里简化处理，实际需要解析PDF页面
-            }
-            _ => {
-                // 对于其他背景类型，创建简单的背景
-                let background_color = match &page.background {
-                    Background::Lined { .. } => pdf::Color::Rgb(0.95, 0.95, 0.95),
-                    Background::Grid { .. } => pdf::Color::Rgb(0.98, 0.98, 0.98),
-                    _ => pdf::Color::Rgb(1.0, 1.0, 1.0),
-                };
-                
-                page_builder = page_builder
-                    .set_fill_color(background_color)
-                    .rect(pdf::Pt(0.0), pdf::Pt(0.0), pdf::Pt(page.width as f64), pdf::Pt(page.height as f64))
-                    .fill();
+    // 固定页面导出整张纸；无限画布按导出方式得到一个或多个区域
+    let page_areas: Vec<Vec<Rect>> = note.pages.iter()
+        .map(|page| match (page.kind, infinite_export) {
+            (PageKind::Infinite, InfiniteExport::Tiled) => page.export_tiles(),
+            _ => vec![page.export_bounds()],
+        })
+        .collect();
+    // 每个笔记页面在PDF中的第一页，无限画布分页导出时一个页面对应多页；
+    // 先算好，页面链接可以指向后面的页面
+    let mut first_pdf_pages = Vec::with_capacity(note.pages.len());
+    let mut pdf_page_count = 0;
+    for areas in &page_areas {
+        first_pdf_pages.push(pdf_page_count);
+        pdf_page_count += areas.len();
+    }
+    
+    // 导入的PDF在整个导出过程中只打开一次
+    let mut resources = ExportResources { cjk_font, first_pdf_pages, pdf_sources: pdf_render::PdfSources::default() };
+    for (page_index, (page, areas)) in note.pages.iter().zip(page_areas).enumerate() {
+        for (area_index, area) in areas.into_iter().enumerate() {
+            let title = if page_index == 0 && area_index == 0 { Some(note.title.as_str()) } else { None };
+            draw_page_area(&mut doc, &mut resources, note, page, area, title);
+        }
+    }
+    
+    // 大纲写成PDF书签
+    add_outline_items(&mut doc, note, &note.outline, &resources.first_pdf_pages, None);
+    
+    // 保存PDF文件
+    doc.save(file_path)?;
+    Ok(())
+}
+
+// 指向已删除页面的条目不导出，它的子项提升到条目所在的层级
+fn add_outline_items(
+    doc: &mut pdf::Document,
+    note: &Note,
+    entries: &[OutlineEntry],
+    first_pdf_pages: &[usize],
+    parent: Option<pdf::OutlineItem>,
+) {
+    for entry in entries {
+        match note.page_index_of(&entry.page_id) {
+            Some(page_index) => {
+                let item = doc.add_outline_item(&entry.title, first_pdf_pages[page_index], parent);
+                add_outline_items(doc, note, &entry.children, first_pdf_pages, Some(item));
             }
+            None => add_outline_items(doc, note, &entry.children, first_pdf_pages, parent),
+        }
+    }
+}
+
+// 一次导出中各页共用的字体、页码对应关系和打开的源PDF
+struct ExportResources {
+    cjk_font: Option<pdf::Font>,
+    first_pdf_pages: Vec<usize>,
+    pdf_sources: pdf_render::PdfSources,
+}
+
+// 把页面上area范围内的内容画成PDF中的一页
+fn draw_page_area(doc: &mut pdf::Document, resources: &mut ExportResources, note: &Note, page: &Page, area: Rect, title: Option<&str>) {
+    let cjk_font = resources.cjk_font;
+    // 按物理尺寸建页，内容仍用画布坐标绘制，再按dpi缩放到点
+    let scale = POINTS_PER_INCH / page.dpi;
+    let (width, height) = (area.width(), area.height());
+    // PDF的Y轴从下往上，画布坐标以area.max.y为基线翻转，X方向平移到area的左边
+    let page_height = area.max.y;
+    let mut page_builder = doc.add_page(pdf::Pt((width * scale) as f64), pdf::Pt((height * scale) as f64))
+        .save_state()
+        .transform(pdf::Matrix::new(scale as f64, 0.0, 0.0, scale as f64, (-area.min.x * scale) as f64, 0.0));
+    
+    // 设置页面背景
+    match &page.background {
+        Background::Pdf { file_path: pdf_path, page_index, .. } => {
+            page_builder = draw_pdf_background(doc, &mut resources.pdf_sources, page_builder, page, pdf_path, *page_index, page_height);
+        }
+        background => {
+            // 纸张颜色铺满导出区域，模板的线条与屏幕上相同
+            let paper_color = background.style().map_or(Color::WHITE, |style| style.paper_color);
+            page_builder = page_builder
+                .set_fill_alpha(paper_color.alpha() as f64)
+                .set_fill_color(pdf_color(paper_color))
+                .rect(pdf::Pt(area.min.x as f64), pdf::Pt(0.0), pdf::Pt(width as f64), pdf::Pt(height as f64))
+                .fill()
+                .set_fill_alpha(1.0);
+            page_builder = draw_template(page_builder, background, page.sheet_bounds(), area, page_height);
+        }
+    }
+    
+    // 按图层顺序绘制可见图层的内容，隐藏的图层不导出。
+    // 每个图层画成一个透明度组，整组按图层不透明度合成，组内重叠的笔画不会叠加变深
+    for layer in page.visible_layers() {
+        page_builder = page_builder.begin_transparency_group(layer.opacity as f64);
+        
+        for element in &layer.elements {
+            page_builder = match element {
+                Element::Text(text) => draw_text_box(page_builder, text, page_height, cjk_font),
+                Element::Image(image) => draw_image(doc, page_builder, image, &note.id, page_height),
+                Element::Shape(shape) => draw_shape(page_builder, shape, page_height),
+            };
         }
         
-        // 绘制笔画
-        for stroke in &page.strokes {
-            if stroke.points.is_empty() {
-                continue;
+        // 荧光笔在下，与屏幕上的绘制顺序一致
+        for index in layer.draw_order((0..layer.strokes.len()).collect()) {
+            page_builder = draw_stroke(page_builder, &layer.strokes[index], page_height);
+        }
+        page_builder = page_builder.end_transparency_group();
+    }
+    
+    // 添加页面标题
+    if let Some(title) = title {
+        page_builder = page_builder
+            .set_fill_color(pdf::Color::Rgb(0.2, 0.2, 0.2))
+            .set_font(font_for(title, pdf::Font::Times_Roman, cjk_font), 24.0)
+            .begin_text()
+            .text_matrix(pdf::TextMatrix::new(1.0, 0.0, 0.0, 1.0, (area.min.x + 50.0) as f64, height as f64 - 50.0))
+            .show(title)
+            .end_text();
+    }
+    page_builder = page_builder.restore_state();
+    
+    // 链接注释使用页面坐标（点），不受上面的变换影响；只导出落在本区域内的部分
+    for (bounds, target) in page.link_areas() {
+        let action = match target {
+            LinkTarget::Page { page_id } => match note.page_index_of(page_id) {
+                Some(target_index) => pdf::LinkAction::GoTo(resources.first_pdf_pages[target_index]),
+                None => continue,
+            },
+            LinkTarget::Url { url } => pdf::LinkAction::Uri(url.clone()),
+            // 其他笔记不在导出的文件中，没有可以跳转的位置
+            LinkTarget::Note { .. } => continue,
+        };
+        let Some(bounds) = bounds.intersection(&area) else { continue };
+        page_builder = page_builder.link_annotation(
+            pdf::Pt(((bounds.min.x - area.min.x) * scale) as f64),
+            pdf::Pt(((area.max.y - bounds.max.y) * scale) as f64),
+            pdf::Pt((bounds.width() * scale) as f64),
+            pdf::Pt((bounds.height() * scale) as f64),
+            action,
+        );
+    }
+}
+
+// 填充与屏幕上相同的变宽轮廓，两侧边界用贝塞尔曲线输出，比逐点line_to更小也更平滑
+// 颜色和工具（如荧光笔）的不透明度相乘，画完后恢复为不透明
+fn draw_stroke(page_builder: pdf::PageBuilder, stroke: &Stroke, page_height: f32) -> pdf::PageBuilder {
+    let fitted = stroke.outline();
+    if fitted.path.is_empty() {
+        return page_builder;
+    }
+    
+    // 设置笔画颜色
+    let color = pdf_color(stroke.color);
+    let ink_opacity = stroke.color.alpha();
+    let to_pdf = |p: Vec2| (pdf::Pt(p.x as f64), pdf::Pt((page_height - p.y) as f64)); // PDF坐标系Y轴从下往上
+    
+    // 绘制路径
+    let mut path_builder = page_builder
+        .set_fill_alpha((ink_opacity * stroke.tool.opacity()) as f64)
+        .set_fill_color(color)
+        .path();
+    for segment in &fitted.path {
+        path_builder = match *segment {
+            PathSegment::MoveTo(p) => {
+                let (x, y) = to_pdf(p);
+                path_builder.move_to(x, y)
             }
+            PathSegment::LineTo(p) => {
+                let (x, y) = to_pdf(p);
+                path_builder.line_to(x, y)
+            }
+            PathSegment::CurveTo(c1, c2, p) => {
+                let (x1, y1) = to_pdf(c1);
+                let (x2, y2) = to_pdf(c2);
+                let (x3, y3) = to_pdf(p);
+                path_builder.curve_to(x1, y1, x2, y2, x3, y3)
+            }
+            PathSegment::Close => path_builder.close(),
+        };
+    }
+    let mut page_builder = path_builder.fill().set_fill_alpha(1.0);
+    
+    // 铅笔的颗粒用原色描在半透明的笔迹上
+    let grain = &fitted.grain;
+    if !grain.is_empty() {
+        let mut path_builder = page_builder
+            .set_stroke_alpha(ink_opacity as f64)
+            .set_stroke_color(color)
+            .set_line_width(pdf::Pt(PENCIL_GRAIN_WIDTH as f64))
+            .path();
+        for &(start, end) in grain {
+            let (x1, y1) = to_pdf(start);
+            let (x2, y2) = to_pdf(end);
+            path_builder = path_builder.move_to(x1, y1).line_to(x2, y2);
+        }
+        page_builder = path_builder.stroke().set_stroke_alpha(1.0);
+    }
+    page_builder
+}
+
+// 纸张模板的线条和点，与屏幕上使用同一份template::marks；
+// 颜色和粗细相同的连续线条（或点）合并成一条路径，整页横线只需一次描边
+fn draw_template(mut page_builder: pdf::PageBuilder, background: &Background, paper: Rect, area: Rect, page_height: f32) -> pdf::PageBuilder {
+    let to_pdf = |p: Vec2| (pdf::Pt(p.x as f64), pdf::Pt((page_height - p.y) as f64));
+    let marks = template::marks(background, paper, area);
+    
+    for group in template::paint_groups(&marks) {
+        page_builder = match group[0] {
+            Mark::Line { width, color, .. } => {
+                let mut path_builder = page_builder
+                    .set_stroke_alpha(color.alpha() as f64)
+                    .set_stroke_color(pdf_color(color))
+                    .set_line_width(pdf::Pt(width as f64))
+                    .path();
+                for mark in group {
+                    if let Mark::Line { start, end, .. } = *mark {
+                        let (x1, y1) = to_pdf(start);
+                        let (x2, y2) = to_pdf(end);
+                        path_builder = path_builder.move_to(x1, y1).line_to(x2, y2);
+                    }
+                }
+                path_builder.stroke()
+            }
+            Mark::Dot { color, .. } => {
+                let mut path_builder = page_builder
+                    .set_fill_alpha(color.alpha() as f64)
+                    .set_fill_color(pdf_color(color))
+                    .path();
+                for mark in group {
+                    if let Mark::Dot { center, radius, .. } = *mark {
+                        let point_at = |angle: f32| center + Vec2::new(angle.cos(), angle.sin()) * radius;
+                        let tangent_at = |angle: f32| Vec2::new(-angle.sin(), angle.cos()) * (radius * BEZIER_KAPPA);
+                        
+                        let (x, y) = to_pdf(point_at(0.0));
+                        path_builder = path_builder.move_to(x, y);
+                        for quarter in 0..4 {
+                            let t0 = std::f32::consts::FRAC_PI_2 * quarter as f32;
+                            let t1 = t0 + std::f32::consts::FRAC_PI_2;
+                            let (x1, y1) = to_pdf(point_at(t0) + tangent_at(t0));
+                            let (x2, y2) = to_pdf(point_at(t1) - tangent_at(t1));
+                            let (x3, y3) = to_pdf(point_at(t1));
+                            path_builder = path_builder.curve_to(x1, y1, x2, y2, x3, y3);
+                        }
+                        path_builder = path_builder.close();
+                    }
+                }
+                path_builder.fill()
+            }
+        };
+    }
+    page_builder.set_fill_alpha(1.0).set_stroke_alpha(1.0)
+}
+
+// 圆弧四分之一段用三次贝塞尔曲线近似时控制点的比例
+const BEZIER_KAPPA: f32 = 0.552_284_8;
+
+// 描边和填充颜色的透明度在单独保存的图形状态中设置，画完后恢复
+fn draw_shape(page_builder: pdf::PageBuilder, shape: &ShapeElement, page_height: f32) -> pdf::PageBuilder {
+    // 没有填充时填充色只用于箭头，与描边颜色相同
+    let fill_alpha = shape.fill.map_or(shape.color.alpha(), |fill| fill.alpha());
+    let page_builder = page_builder
+        .save_state()
+        .set_stroke_alpha(shape.color.alpha() as f64)
+        .set_fill_alpha(fill_alpha as f64);
+    shape_path(page_builder, shape, page_height).restore_state()
+}
+
+// 图形导出为矢量路径：矩形用rect，椭圆用四段贝塞尔曲线，其余用折线
+fn shape_path(page_builder: pdf::PageBuilder, shape: &ShapeElement, page_height: f32) -> pdf::PageBuilder {
+    let color = pdf_color(shape.color);
+    let fill = shape.fill.map(pdf_color);
+    let to_pdf = |p: Vec2| (pdf::Pt(p.x as f64), pdf::Pt((page_height - p.y) as f64));
+    
+    let mut page_builder = page_builder
+        .set_stroke_color(color)
+        .set_line_width(pdf::Pt(shape.thickness as f64));
+    if let Some(fill) = fill {
+        page_builder = page_builder.set_fill_color(fill);
+    }
+    
+    let path_builder = match &shape.kind {
+        ShapeKind::Rectangle { min, max } => {
+            let builder = page_builder.rect(
+                pdf::Pt(min.x as f64),
+                pdf::Pt((page_height - max.y) as f64),
+                pdf::Pt((max.x - min.x) as f64),
+                pdf::Pt((max.y - min.y) as f64),
+            );
+            return match fill {
+                Some(_) => builder.fill_and_stroke(),
+                None => builder.stroke(),
+            };
+        }
+        ShapeKind::Ellipse { center, radius_x, radius_y, rotation } => {
+            let (sin, cos) = rotation.sin_cos();
+            let rotate = |v: Vec2| Vec2::new(v.x * cos - v.y * sin, v.x * sin + v.y * cos);
+            let point_at = |t: f32| center + rotate(Vec2::new(radius_x * t.cos(), radius_y * t.sin()));
+            let tangent_at = |t: f32| rotate(Vec2::new(-radius_x * t.sin(), radius_y * t.cos())) * BEZIER_KAPPA;
             
-            // 设置笔画颜色
-            let color = parse_color(&stroke.color).unwrap_or(pdf::Color::Rgb(0.0, 0.0, 0.0));
-            page_builder = page_builder.set_stroke_color(color);
-            
-            // 设置线宽
-            page_builder = page_builder.set_line_width(stroke.thickness as f64);
+            let (x, y) = to_pdf(point_at(0.0));
+            let mut path_builder = page_builder.path().move_to(x, y);
+            for quarter in 0..4 {
+                let t0 = std::f32::consts::FRAC_PI_2 * quarter as f32;
+                let t1 = t0 + std::f32::consts::FRAC_PI_2;
+                let (x1, y1) = to_pdf(point_at(t0) + tangent_at(t0));
+                let (x2, y2) = to_pdf(point_at(t1) - tangent_at(t1));
+                let (x3, y3) = to_pdf(point_at(t1));
+                path_builder = path_builder.curve_to(x1, y1, x2, y2, x3, y3);
+            }
+            path_builder.close()
+        }
+        ShapeKind::Line { .. } | ShapeKind::Arrow { .. } | ShapeKind::Polygon { .. } => {
+            let (points, closed) = shape.kind.path();
+            if points.is_empty() {
+                return page_builder;
+            }
             
-            // 绘制路径
-            let mut path_builder = page_builder.path();
-            if let Some(first_point) = stroke.points.first() {
-                path_builder = path_builder.move_to(
-                    pdf::Pt(first_point.x as f64),
-                    pdf::Pt((page.height - first_point.y) as f64) // PDF坐标系Y轴从下往上
-                );
-                
-                for point in &stroke.points[1..] {
-                    path_builder = path_builder.line_to(
-                        pdf::Pt(point.x as f64),
-                        pdf::Pt((page.height - point.y) as f64)
-                    );
+            let (x, y) = to_pdf(points[0]);
+            let mut path_builder = page_builder.path().move_to(x, y);
+            for point in &points[1..] {
+                let (x, y) = to_pdf(*point);
+                path_builder = path_builder.line_to(x, y);
+            }
+            if !closed {
+                page_builder = path_builder.stroke();
+                if let ShapeKind::Arrow { start, end } = shape.kind {
+                    // 箭头用描边颜色实心填充
+                    let head = ShapeKind::arrow_head(start, end, shape.thickness);
+                    let (x, y) = to_pdf(head[0]);
+                    let mut head_builder = page_builder.set_fill_color(color).path().move_to(x, y);
+                    for point in &head[1..] {
+                        let (x, y) = to_pdf(*point);
+                        head_builder = head_builder.line_to(x, y);
+                    }
+                    page_builder = head_builder.close().fill();
                 }
+                return page_builder;
             }
-            
-            page_builder = path_builder.stroke();
+            path_builder.close()
+        }
+    };
+    
+    // 凹多边形和自相交的手绘多边形按奇偶规则填充
+    match fill {
+        Some(_) => path_builder.fill_and_stroke_even_odd(),
+        None => path_builder.stroke(),
+    }
+}
+
+// 把图片嵌入PDF并按位置、尺寸和旋转绘制；资源丢失时跳过
+fn draw_image(
+    doc: &mut pdf::Document,
+    page_builder: pdf::PageBuilder,
+    image: &ImageElement,
+    note_id: &str,
+    page_height: f32,
+) -> pdf::PageBuilder {
+    let rgba = match storage::load_image_asset(note_id, &image.asset) {
+        Ok(rgba) => rgba,
+        Err(e) => {
+            log::warn!("导出时无法加载图片 {}: {}", image.asset, e);
+            return page_builder;
+        }
+    };
+    let image_ref = doc.add_image(pdf::Image::from_rgba(rgba.width(), rgba.height(), rgba.into_raw()));
+    
+    // 单位正方形 → 缩放到图片尺寸 → 旋转 → 平移到中心；PDF的Y轴向上，顺时针旋转取负角
+    let center = image.center();
+    let (sin, cos) = (-image.rotation as f64).sin_cos();
+    let (width, height) = (image.width as f64, image.height as f64);
+    let (a, b, c, d) = (width * cos, width * sin, -height * sin, height * cos);
+    let e = center.x as f64 - (a + c) / 2.0;
+    let f = (page_height - center.y) as f64 - (b + d) / 2.0;
+    
+    page_builder
+        .save_state()
+        .transform(pdf::Matrix::new(a, b, c, d, e, f))
+        .draw_image(image_ref)
+        .restore_state()
+}
+
+// 导出时PDF背景页的渲染分辨率
+const PDF_BACKGROUND_DPI: f32 = 150.0;
+
+// 导入的PDF页面由pdfium渲染成图片铺满纸张，与屏幕上的背景一致；渲染失败时留白。
+// pdf库的写入接口不能把其他文档的页面作为Form XObject引用，所以背景有意以位图写入，
+// 原PDF中的文字在导出结果里不能选择
+fn draw_pdf_background(
+    doc: &mut pdf::Document,
+    pdf_sources: &mut pdf_render::PdfSources,
+    page_builder: pdf::PageBuilder,
+    page: &Page,
+    pdf_path: &str,
+    page_index: usize,
+    page_height: f32,
+) -> pdf::PageBuilder {
+    let sheet = page.sheet_bounds();
+    let pixels = |length: f32| (length / page.dpi * PDF_BACKGROUND_DPI).round().max(1.0) as i32;
+    let rgba = match pdf_sources.render_page(pdf_path, page_index, pixels(sheet.width()), pixels(sheet.height())) {
+        Ok(rgba) => rgba,
+        Err(e) => {
+            log::warn!("导出时无法渲染PDF背景 {} 第{}页: {}", pdf_path, page_index + 1, e);
+            return page_builder;
+        }
+    };
+    let image_ref = doc.add_image(pdf::Image::from_rgba(rgba.width(), rgba.height(), rgba.into_raw()));
+    
+    // 单位正方形缩放到纸张大小，PDF的Y轴向上
+    let (width, height) = (sheet.width() as f64, sheet.height() as f64);
+    page_builder
+        .save_state()
+        .transform(pdf::Matrix::new(width, 0.0, 0.0, height, sheet.min.x as f64, (page_height - sheet.max.y) as f64))
+        .draw_image(image_ref)
+        .restore_state()
+}
+
+// 文本行高相对字号的比例
+const LINE_HEIGHT: f32 = 1.3;
+
+// 随程序附带的中日韩字体，位于可执行文件旁；找不到时依次尝试系统字体
+const BUNDLED_CJK_FONT: &str = "fonts/NotoSansSC-Regular.ttf";
+const SYSTEM_CJK_FONTS: &[&str] = &[
+    "C:\\Windows\\Fonts\\simhei.ttf",
+    "/System/Library/Fonts/Supplemental/Arial Unicode.ttf",
+    "/usr/share/fonts/truetype/droid/DroidSansFallbackFull.ttf",
+    "/usr/share/fonts/truetype/arphic/uming.ttf",
+];
+
+// PDF标准字体只有WinAnsi（Latin-1）字形，中文等其他文字需要嵌入TrueType字体；
+// 字体按实际用到的字形子集嵌入
+fn embed_cjk_font(doc: &mut pdf::Document) -> Option<pdf::Font> {
+    let bundled = std::env::current_exe().ok()
+        .and_then(|exe| exe.parent().map(|dir| dir.join(BUNDLED_CJK_FONT)));
+    let data = bundled.into_iter()
+        .chain(SYSTEM_CJK_FONTS.iter().map(PathBuf::from))
+        .find_map(|path| std::fs::read(path).ok());
+    let data = match data {
+        Some(data) => data,
+        None => {
+            log::warn!("未找到中文字体，导出的PDF中中文无法显示");
+            return None;
+        }
+    };
+    match doc.embed_true_type_font(&data) {
+        Ok(font) => Some(font),
+        Err(e) => {
+            log::warn!("嵌入中文字体失败: {}", e);
+            None
+        }
+    }
+}
+
+// 含有标准字体之外字符的文字改用嵌入的字体
+fn font_for(text: &str, font: pdf::Font, cjk_font: Option<pdf::Font>) -> pdf::Font {
+    match cjk_font {
+        Some(cjk_font) if text.chars().any(|c| c as u32 > 0xFF) => cjk_font,
+        _ => font,
+    }
+}
+
+// 文本框导出为真实文字而不是图片，中文使用嵌入的字体
+// 与画布上一样按文本框宽度折行，只显示文本框范围内的文字；PDF中的字宽是估算的，超出的部分裁掉
+// 背景和文字颜色的透明度画完后恢复为不透明
+fn draw_text_box(mut page_builder: pdf::PageBuilder, text: &TextBox, page_height: f32, cjk_font: Option<pdf::Font>) -> pdf::PageBuilder {
+    if let Some(background) = text.background {
+        page_builder = page_builder
+            .set_fill_alpha(background.alpha() as f64)
+            .set_fill_color(pdf_color(background))
+            .rect(
+                pdf::Pt(text.x as f64),
+                pdf::Pt((page_height - text.y - text.height) as f64),
+                pdf::Pt(text.width as f64),
+                pdf::Pt(text.height as f64)
+            )
+            .fill();
+    }
+    
+    page_builder = page_builder
+        .save_state()
+        .rect(
+            pdf::Pt(text.x as f64),
+            pdf::Pt((page_height - text.y - text.height) as f64),
+            pdf::Pt(text.width as f64),
+            pdf::Pt(text.height as f64)
+        )
+        .clip()
+        .set_fill_alpha(text.color.alpha() as f64)
+        .set_fill_color(pdf_color(text.color));
+    
+    let left = text.x + TEXT_BOX_PADDING;
+    let max_width = text.width - TEXT_BOX_PADDING * 2.0;
+    let bottom = text.y + text.height;
+    let mut y = text.y + TEXT_BOX_PADDING;
+    
+    for block in markdown::parse(&text.content) {
+        let size = text.font_size * block.kind.font_scale();
+        let heading = matches!(block.kind, BlockKind::Heading(_));
+        
+        let mut runs = Vec::new();
+        if let Some(prefix) = block.kind.prefix() {
+            runs.push((pdf::Font::Times_Roman, prefix));
+        }
+        for span in &block.spans {
+            let code = span.code || block.kind == BlockKind::Code;
+            runs.push((pdf_font(span.bold || heading, span.italic, code), span.text.clone()));
+        }
+        
+        let lines = wrap_runs(runs, size, max_width, cjk_font);
+        if lines.is_empty() {
+            y += size * LINE_HEIGHT;
         }
         
-        // 添加页面标题
-        if page_index == 0 {
+        for line in lines {
+            // 后面的行完全在文本框之外，不再输出
+            if y >= bottom {
+                break;
+            }
+            y += size * LINE_HEIGHT;
             page_builder = page_builder
-                .set_fill_color(pdf::Color::Rgb(0.2, 0.2, 0.2))
-                .set_font(pdf::Font::Times_Roman, 24.0)
                 .begin_text()
-                .text_matrix(pdf::TextMatrix::new(1.0, 0.0, 0.0, 1.0, 50.0, page.height as f64 - 50.0))
-                .show(&note.title)
-                .end_text();
+                .text_matrix(pdf::TextMatrix::new(1.0, 0.0, 0.0, 1.0, left as f64, (page_height - y) as f64));
+            for (font, run) in line {
+                page_builder = page_builder.set_font(font, size as f64).show(&run);
+            }
+            page_builder = page_builder.end_text();
         }
     }
     
-    // 保存PDF文件
-    doc.save(file_path)?;
-    Ok(())
+    page_builder.restore_state()
+}
+
+fn pdf_font(bold: bool, italic: bool, code: bool) -> pdf::Font {
+    match (code, bold, italic) {
+        (true, _, _) => pdf::Font::Courier,
+        (false, true, true) => pdf::Font::Times_BoldItalic,
+        (false, true, false) => pdf::Font::Times_Bold,
+        (false, false, true) => pdf::Font::Times_Italic,
+        (false, false, false) => pdf::Font::Times_Roman,
+    }
 }
 
+// 估算文字宽度：西文约为半个字号，等宽字体0.6，中日韩等全角字符按一个字号
+fn estimate_text_width(text: &str, font: pdf::Font, size: f32) -> f32 {
+    let narrow = if font == pdf::Font::Courier { 0.6 } else { 0.5 };
+    text.chars()
+        .map(|c| if c.is_ascii() { size * narrow } else { size })
+        .sum()
+}
+
+// 把带字体的文字片段按宽度贪心折行；西文按单词折行，全角字符可以在任意字之间断开
+// 每个片段按所含字符选择标准字体或嵌入的字体
+fn wrap_runs(runs: Vec<(pdf::Font, String)>, size: f32, max_width: f32, cjk_font: Option<pdf::Font>) -> Vec<Vec<(pdf::Font, String)>> {
+    let mut lines: Vec<Vec<(pdf::Font, String)>> = Vec::new();
+    let mut line: Vec<(pdf::Font, String)> = Vec::new();
+    let mut line_width = 0.0;
+    
+    for (font, text) in runs {
+        let mut tokens: Vec<String> = Vec::new();
+        for word in text.split_inclusive(' ') {
+            let mut current = String::new();
+            for c in word.chars() {
+                if c.is_ascii() {
+                    current.push(c);
+                } else {
+                    if !current.is_empty() {
+                        tokens.push(std::mem::take(&mut current));
+                    }
+                    tokens.push(c.to_string());
+                }
+            }
+            if !current.is_empty() {
+                tokens.push(current);
+            }
+        }
+        
+        for token in tokens {
+            let font = font_for(&token, font, cjk_font);
+            let width = estimate_text_width(&token, font, size);
+            if line_width + width > max_width && !line.is_empty() {
+                lines.push(std::mem::take(&mut line));
+                line_width = 0.0;
+            }
+            
+            match line.last_mut() {
+                Some((last_font, last_text)) if *last_font == font => last_text.push_str(&token),
+                _ => line.push((font, token)),
+            }
+            line_width += width;
+        }
+    }
+    
+    if !line.is_empty() {
+        lines.push(line);
+    }
+    lines
+}
+
+// PDF的每一页成为笔记中的一页，尺寸取页面的MediaBox，旋转过的页面按显示方向交换宽高
+// 这里用pdf库读取，导入不需要pdfium动态库，背景渲染才用pdfium
 pub fn import_from_pdf(file_path: &str) -> Result<Note, Box<dyn std::error::Error>> {
     let path = Path::new(file_path);
     let file_name = path.file_stem()
         .and_then(|s| s.to_str())
         .unwrap_or("Imported PDF");
     
-    // 创建新的笔记
-    let mut note = Note::new(file_name.to_string());
+    let file = pdf::file::FileOptions::cached().open(file_path)?;
+    let mut pages = Vec::new();
+    for (page_index, page) in file.pages().enumerate() {
+        let page = page?;
+        let media_box = page.media_box()?;
+        let rotation = normalize_rotation(page.rotate);
+        let size = display_size(media_box.right - media_box.left, media_box.top - media_box.bottom, rotation);
+        
+        let background = Background::Pdf {
+            file_path: file_path.to_string(),
+            page_index,
+            rotation,
+        };
+        pages.push(Page::new(background, size, DEFAULT_DPI));
+    }
+    if pages.is_empty() {
+        return Err("PDF has no pages".into());
+    }
     
-    // 设置PDF背景
-    note.set_background(Background::Pdf {
-        file_path: file_path.to_string(),
-    });
+    // 直接替换新笔记的页面，导入得到的初始状态不出现在撤销历史中
+    let mut note = Note::new(file_name.to_string());
+    note.pages = pages;
+    note.current_page = 0;
     
     Ok(note)
 }
 
-fn parse_color(color_str: &str) -> Option<pdf::Color> {
-    if color_str.starts_with('#') && color_str.len() == 7 {
-        let r = u8::from_str_radix(&color_str[1..3], 16).ok()?;
-        let g = u8::from_str_radix(&color_str[3..5], 16).ok()?;
-        let b = u8::from_str_radix(&color_str[5..7], 16).ok()?;
-        
-        Some(pdf::Color::Rgb(
-            r as f32 / 255.0,
-            g as f32 / 255.0,
-            b as f32 / 255.0
-        ))
+// /Rotate应为90的倍数，可以为负数，统一到0、90、180、270。不是90的倍数时与pdfium一样向0取整，
+// 记录的方向与渲染出的背景一致
+fn normalize_rotation(rotate: i32) -> u16 {
+    ((rotate / 90).rem_euclid(4) * 90) as u16
+}
+
+// MediaBox的两个对角可以按任意顺序给出；旋转90或270度的页面按显示方向交换宽高
+fn display_size(width: f32, height: f32, rotation: u16) -> PageSize {
+    let (width, height) = (width.abs(), height.abs());
+    if rotation % 180 == 0 {
+        PageSize::from_points(width, height)
     } else {
-        // 处理命名颜色
-        match color_str.to_lowercase().as_str() {
-            "black" => Some(pdf::Color::Rgb(0.0, 0.0, 0.0)),
-            "red" => Some(pdf::Color::Rgb(1.0, 0.0, 0.0)),
-            "green" => Some(pdf::Color::Rgb(0.0, 1.0, 0.0)),
-            "blue" => Some(pdf::Color::Rgb(0.0, 0.0, 1.0)),
-            _ => None,
-        }
+        PageSize::from_points(height, width)
+    }
+}
+
+fn pdf_color(color: Color) -> pdf::Color {
+    let (r, g, b) = color.to_rgb_f32();
+    pdf::Color::Rgb(r, g, b)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    
+    #[test]
+    fn test_normalize_rotation() {
+        assert_eq!(normalize_rotation(0), 0);
+        assert_eq!(normalize_rotation(270), 270);
+        assert_eq!(normalize_rotation(450), 90);
+        // 负数按顺时针的等价角度
+        assert_eq!(normalize_rotation(-90), 270);
+        assert_eq!(normalize_rotation(-450), 270);
+        // 不是90的倍数时向0取整
+        assert_eq!(normalize_rotation(45), 0);
+        assert_eq!(normalize_rotation(135), 90);
+        assert_eq!(normalize_rotation(-135), 270);
     }
-}
\ No newline at end of file
+    
+    #[test]
+    fn test_display_size() {
+        let portrait = display_size(595.0, 842.0, 0);
+        assert_eq!(display_size(595.0, 842.0, 180), portrait);
+        // MediaBox的对角顺序相反时宽高仍为正
+        assert_eq!(display_size(-595.0, -842.0, 0), portrait);
+        
+        let landscape = display_size(595.0, 842.0, 90);
+        assert_eq!((landscape.width_mm, landscape.height_mm), (portrait.height_mm, portrait.width_mm));
+        assert_eq!(display_size(595.0, 842.0, 270), landscape);
+    }
+}
@@ -0,0 +1,366 @@
+// PDF背景的光栅化：pdfium在后台线程中按缩放档位渲染页面，界面线程只负责上传纹理，
+// 渲染大页面时书写不会卡顿。结果按(文件, 页码, 缩放档位)缓存。导出PDF时也用这里渲染背景页。
+// 导入时读取页面尺寸用的是纯Rust的pdf库，导入不依赖pdfium动态库：找不到pdfium时仍能导入和批注，
+// 只是背景显示为渲染失败
+use eframe::egui;
+use pdfium_render::prelude::*;
+use std::collections::hash_map::{Entry, HashMap};
+use std::sync::mpsc::{self, Receiver};
+use std::sync::{Arc, Condvar, Mutex, MutexGuard, PoisonError};
+use std::thread;
+
+// 缩放按档位向上取整，同一档位内的缩放变化不重新渲染
+const ZOOM_STEP: f32 = 0.5;
+const MAX_ZOOM: f32 = 4.0;
+// 最多保留的页面纹理数，超出时丢弃最久未使用的
+const MAX_CACHED_PAGES: usize = 12;
+
+// 整个进程共用一个pdfium实例：Pdfium释放时会关闭整个库，不能同时存在多个。
+// 加载失败时不记下结果，之后的请求（例如界面上的重试）会重新尝试
+static PDFIUM: Mutex<Option<&'static Pdfium>> = Mutex::new(None);
+
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+struct RenderKey {
+    file_path: String,
+    page_index: usize,
+    zoom_bucket: u32,
+}
+
+impl RenderKey {
+    fn same_page(&self, other: &RenderKey) -> bool {
+        self.file_path == other.file_path && self.page_index == other.page_index
+    }
+}
+
+// 渲染请求，width和height为目标像素尺寸
+struct RenderRequest {
+    key: RenderKey,
+    width: i32,
+    height: i32,
+}
+
+type RenderResult = (RenderKey, Result<egui::ColorImage, String>);
+
+// 等待渲染的请求。同一页只保留最新的请求，后进先出：最近请求的（通常是正在看的）页面先渲染
+#[derive(Default)]
+struct RenderQueue {
+    state: Mutex<QueueState>,
+    changed: Condvar,
+}
+
+#[derive(Default)]
+struct QueueState {
+    requests: Vec<RenderRequest>,
+    closed: bool,
+}
+
+impl RenderQueue {
+    fn lock(&self) -> MutexGuard<'_, QueueState> {
+        self.state.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+
+    // 加入请求，返回被替换掉的同一页其他档位的请求
+    fn push(&self, request: RenderRequest) -> Vec<RenderKey> {
+        let mut state = self.lock();
+        let mut replaced = Vec::new();
+        state.requests.retain(|pending| {
+            let same_page = pending.key.same_page(&request.key);
+            if same_page {
+                replaced.push(pending.key.clone());
+            }
+            !same_page
+        });
+        state.requests.push(request);
+        self.changed.notify_one();
+        replaced
+    }
+
+    // 撤回还没开始渲染的请求
+    fn remove(&self, key: &RenderKey) {
+        self.lock().requests.retain(|pending| pending.key != *key);
+    }
+
+    // 取出最新的请求，队列为空时等待；关闭后返回None
+    fn pop(&self) -> Option<RenderRequest> {
+        let mut state = self.lock();
+        loop {
+            if state.closed {
+                return None;
+            }
+            if let Some(request) = state.requests.pop() {
+                return Some(request);
+            }
+            state = self.changed.wait(state).unwrap_or_else(PoisonError::into_inner);
+        }
+    }
+
+    fn close(&self) {
+        self.lock().closed = true;
+        self.changed.notify_all();
+    }
+}
+
+enum CachedPage {
+    Pending,
+    Ready(egui::TextureHandle),
+    Failed(String),
+}
+
+struct CacheEntry {
+    page: CachedPage,
+    last_used: u64,
+}
+
+// 页面背景的状态
+pub enum PageTexture {
+    Ready(egui::TextureHandle),
+    // 正在渲染，同一页也没有其他档位的结果
+    Pending,
+    // 渲染失败及原因，调用retry后重新渲染
+    Failed(String),
+}
+
+struct Worker {
+    queue: Arc<RenderQueue>,
+    results: Receiver<RenderResult>,
+}
+
+// 后台线程在第一次请求时启动
+#[derive(Default)]
+pub struct PdfPageCache {
+    worker: Option<Worker>,
+    entries: HashMap<RenderKey, CacheEntry>,
+    frame: u64,
+}
+
+impl Drop for PdfPageCache {
+    // 后台线程渲染完手上的页面后退出
+    fn drop(&mut self) {
+        if let Some(worker) = &self.worker {
+            worker.queue.close();
+        }
+    }
+}
+
+impl PdfPageCache {
+    // 每帧调用一次：上传后台渲染好的页面，并淘汰多余的纹理
+    pub fn update(&mut self, ctx: &egui::Context) {
+        self.frame += 1;
+
+        if let Some(worker) = &self.worker {
+            for (key, result) in worker.results.try_iter() {
+                // 渲染期间已被淘汰的页面丢弃结果
+                let entry = match self.entries.get_mut(&key) {
+                    Some(entry) => entry,
+                    None => continue,
+                };
+                entry.page = match result {
+                    Ok(image) => {
+                        let name = format!("pdf/{}/{}/{}", key.file_path, key.page_index, key.zoom_bucket);
+                        CachedPage::Ready(ctx.load_texture(name, image, egui::TextureOptions::LINEAR))
+                    }
+                    Err(e) => {
+                        log::warn!("渲染PDF页面失败 {} 第{}页: {}", key.file_path, key.page_index + 1, e);
+                        CachedPage::Failed(e)
+                    }
+                };
+            }
+        }
+
+        while self.entries.len() > MAX_CACHED_PAGES {
+            // 本帧用到的页面不淘汰；还在等待的页面淘汰时一并撤回请求，不再渲染
+            let oldest = self.entries.iter()
+                .filter(|(_, entry)| entry.last_used < self.frame)
+                .min_by_key(|(_, entry)| entry.last_used)
+                .map(|(key, _)| key.clone());
+            match oldest {
+                Some(key) => {
+                    self.entries.remove(&key);
+                    if let Some(worker) = &self.worker {
+                        worker.queue.remove(&key);
+                    }
+                }
+                None => break,
+            }
+        }
+    }
+
+    // 页面的纹理。size为页面在屏幕上的大小（点），zoom为每点的像素数；
+    // 目标档位还没渲染好或渲染失败时先用同一页其他档位的结果
+    pub fn texture(&mut self, ctx: &egui::Context, file_path: &str, page_index: usize, size: egui::Vec2, zoom: f32) -> PageTexture {
+        let zoom_bucket = zoom_bucket(zoom);
+        let key = RenderKey { file_path: file_path.to_string(), page_index, zoom_bucket };
+        let frame = self.frame;
+
+        if !self.entries.contains_key(&key) {
+            let max_side = ctx.input(|i| i.max_texture_side) as f32;
+            let (width, height) = render_size(size, zoom_bucket, max_side);
+            let worker = self.worker.get_or_insert_with(|| spawn_worker(ctx.clone()));
+            // 同一页其他档位还没开始的请求不再需要
+            for replaced in worker.queue.push(RenderRequest { key: key.clone(), width, height }) {
+                self.entries.remove(&replaced);
+            }
+            self.entries.insert(key.clone(), CacheEntry { page: CachedPage::Pending, last_used: frame });
+        }
+
+        let failed = match self.entries.get_mut(&key) {
+            Some(entry) => {
+                entry.last_used = frame;
+                match &entry.page {
+                    CachedPage::Ready(texture) => return PageTexture::Ready(texture.clone()),
+                    CachedPage::Failed(e) => Some(e.clone()),
+                    CachedPage::Pending => None,
+                }
+            }
+            None => None,
+        };
+        let fallback = self.entries.iter_mut()
+            .filter(|(other, entry)| other.same_page(&key) && matches!(entry.page, CachedPage::Ready(_)))
+            .min_by_key(|(other, _)| other.zoom_bucket.abs_diff(zoom_bucket))
+            .and_then(|(_, entry)| {
+                entry.last_used = frame;
+                match &entry.page {
+                    CachedPage::Ready(texture) => Some(texture.clone()),
+                    _ => None,
+                }
+            });
+        match (fallback, failed) {
+            (Some(texture), _) => PageTexture::Ready(texture),
+            (None, Some(e)) => PageTexture::Failed(e),
+            (None, None) => PageTexture::Pending,
+        }
+    }
+
+    // 丢弃这一页渲染失败的结果，下次绘制时重新请求
+    pub fn retry(&mut self, file_path: &str, page_index: usize) {
+        self.entries.retain(|key, entry| {
+            !(key.file_path == file_path && key.page_index == page_index && matches!(entry.page, CachedPage::Failed(_)))
+        });
+    }
+}
+
+fn zoom_bucket(zoom: f32) -> u32 {
+    (zoom.min(MAX_ZOOM) / ZOOM_STEP).ceil().max(1.0) as u32
+}
+
+// 按档位的缩放计算像素尺寸，不超过显卡允许的最大纹理边长
+fn render_size(size: egui::Vec2, zoom_bucket: u32, max_side: f32) -> (i32, i32) {
+    let zoom = zoom_bucket as f32 * ZOOM_STEP;
+    let scale = (max_side / (size.x.max(size.y) * zoom)).min(1.0);
+    let pixels = |length: f32| (length * zoom * scale).round().max(1.0) as i32;
+    (pixels(size.x), pixels(size.y))
+}
+
+// 先找随程序附带、放在工作目录中的pdfium动态库，找不到时使用系统安装的
+fn pdfium() -> Result<&'static Pdfium, PdfiumError> {
+    let mut pdfium = PDFIUM.lock().unwrap_or_else(PoisonError::into_inner);
+    if let Some(pdfium) = *pdfium {
+        return Ok(pdfium);
+    }
+    let bindings = Pdfium::bind_to_library(Pdfium::pdfium_platform_library_name_at_path("./"))
+        .or_else(|_| Pdfium::bind_to_system_library())?;
+    let bound: &'static Pdfium = Box::leak(Box::new(Pdfium::new(bindings)));
+    *pdfium = Some(bound);
+    Ok(bound)
+}
+
+// pdfium动态库在后台线程中加载，找不到时请求返回错误
+fn spawn_worker(ctx: egui::Context) -> Worker {
+    let queue = Arc::new(RenderQueue::default());
+    let requests = Arc::clone(&queue);
+    let (result_sender, results) = mpsc::channel();
+
+    thread::spawn(move || {
+        // 连续渲染同一文件的页面时复用已打开的文档
+        let mut document = None;
+        while let Some(request) = requests.pop() {
+            let result = render_page(&mut document, &request).map_err(|e| e.to_string());
+            if result_sender.send((request.key, result)).is_err() {
+                break;
+            }
+            ctx.request_repaint();
+        }
+    });
+    Worker { queue, results }
+}
+
+fn render_page(document: &mut Option<(String, PdfDocument<'static>)>, request: &RenderRequest) -> Result<egui::ColorImage, PdfiumError> {
+    let file_path = &request.key.file_path;
+    let (_, document) = match document.take() {
+        Some((open_path, open)) if open_path == *file_path => document.insert((open_path, open)),
+        _ => document.insert((file_path.clone(), pdfium()?.load_pdf_from_file(file_path, None)?)),
+    };
+
+    let (width, height, rgba) = render_rgba(document, request.key.page_index, request.width, request.height)?;
+    Ok(egui::ColorImage::from_rgba_unmultiplied([width as usize, height as usize], &rgba))
+}
+
+// 导出PDF时在当前线程中渲染背景页；每个源文件在一次导出中只加载一次
+#[derive(Default)]
+pub struct PdfSources {
+    documents: HashMap<String, PdfDocument<'static>>,
+}
+
+impl PdfSources {
+    // 把一页渲染成width×height像素的图片
+    pub fn render_page(&mut self, file_path: &str, page_index: usize, width: i32, height: i32) -> Result<image::RgbaImage, PdfiumError> {
+        let document = match self.documents.entry(file_path.to_string()) {
+            Entry::Occupied(entry) => entry.into_mut(),
+            Entry::Vacant(entry) => entry.insert(pdfium()?.load_pdf_from_file(file_path, None)?),
+        };
+        let (width, height, rgba) = render_rgba(document, page_index, width, height)?;
+        image::RgbaImage::from_raw(width, height, rgba).ok_or(PdfiumError::ImageError)
+    }
+}
+
+// pdfium按页面的/Rotate旋转，得到的方向与导入时记录的页面尺寸一致
+fn render_rgba(document: &PdfDocument, page_index: usize, width: i32, height: i32) -> Result<(u32, u32, Vec<u8>), PdfiumError> {
+    let page_index = PdfPageIndex::try_from(page_index)
+        .map_err(|_| PdfiumError::PageIndexOutOfBounds)?;
+    let page = document.pages().get(page_index)?;
+    let config = PdfRenderConfig::new().set_target_size(width, height);
+    let bitmap = page.render_with_config(&config)?;
+    Ok((bitmap.width() as u32, bitmap.height() as u32, bitmap.as_rgba_bytes()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_zoom_buckets_and_render_size() {
+        assert_eq!(zoom_bucket(1.0), 2);
+        assert_eq!(zoom_bucket(1.2), 3);
+        assert_eq!(zoom_bucket(0.1), 1);
+        assert_eq!(zoom_bucket(10.0), 8);
+
+        let a4 = egui::Vec2::new(794.0, 1123.0);
+        assert_eq!(render_size(a4, zoom_bucket(2.0), 8192.0), (1588, 2246));
+        // 超过最大纹理边长时等比缩小
+        assert_eq!(render_size(a4, zoom_bucket(4.0), 2048.0), (1448, 2048));
+    }
+
+    #[test]
+    fn test_render_queue_keeps_newest_request_per_page() {
+        let request = |page_index, zoom_bucket| RenderRequest {
+            key: RenderKey { file_path: "a.pdf".to_string(), page_index, zoom_bucket },
+            width: 1,
+            height: 1,
+        };
+        let queue = RenderQueue::default();
+        assert!(queue.push(request(0, 2)).is_empty());
+        assert!(queue.push(request(1, 2)).is_empty());
+        // 同一页换了档位，旧请求被替换
+        let replaced = queue.push(request(0, 4));
+        assert_eq!(replaced, vec![request(0, 2).key]);
+
+        // 淘汰的页面撤回请求，剩下的后进先出
+        queue.push(request(2, 2));
+        queue.remove(&request(1, 2).key);
+        assert_eq!(queue.pop().map(|r| r.key), Some(request(2, 2).key));
+        assert_eq!(queue.pop().map(|r| r.key), Some(request(0, 4).key));
+
+        queue.close();
+        assert!(queue.pop().is_none());
+    }
+}
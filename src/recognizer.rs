@@ -0,0 +1,351 @@
+// 手绘笔画到矢量图形的识别，纯几何计算，不依赖UI
+use crate::geometry::{self, Vec2};
+use crate::note::{Point, ShapeKind};
+
+// 落笔后保持静止多久触发识别（毫秒）
+pub const HOLD_MS: u64 = 600;
+// 视为静止的最大抖动距离
+pub const HOLD_TOLERANCE: f32 = 4.0;
+
+// 直线允许的最大偏离（相对于弦长）
+const LINE_TOLERANCE: f32 = 0.06;
+// 首尾距离小于路径长度的这个比例时视为闭合图形
+const CLOSED_RATIO: f32 = 0.2;
+// 椭圆拟合允许的平均径向误差
+const ELLIPSE_TOLERANCE: f32 = 0.08;
+// 多边形顶点简化的容差（相对于包围盒对角线）
+const CORNER_TOLERANCE: f32 = 0.08;
+// 矩形各边与坐标轴允许的夹角（弧度）
+const AXIS_TOLERANCE: f32 = 0.2;
+// 识别所需的最短路径长度
+const MIN_LENGTH: f32 = 10.0;
+
+// 笔尖在结尾是否已静止至少hold_ms；now为当前时间，笔静止时设备可能不再上报新的采样点
+pub fn is_held(points: &[Point], now: u64, hold_ms: u64, tolerance: f32) -> bool {
+    // 整条笔画都没有移动（原地按住）不算
+    let start = stationary_start(points, tolerance);
+    start > 0 && start < points.len() && now.saturating_sub(points[start].timestamp) >= hold_ms
+}
+
+// 结尾静止段的起始索引：从这里到结尾的点都在最后一个点的tolerance范围内
+fn stationary_start(points: &[Point], tolerance: f32) -> usize {
+    let last = match points.last() {
+        Some(last) => last.position(),
+        None => return 0,
+    };
+
+    points.iter()
+        .rposition(|p| p.position().distance(last) > tolerance)
+        .map_or(0, |i| i + 1)
+}
+
+// 识别笔画对应的图形，结尾静止的抖动部分会被忽略
+pub fn recognize(points: &[Point]) -> Option<ShapeKind> {
+    let end = stationary_start(points, HOLD_TOLERANCE).min(points.len().saturating_sub(1));
+    let positions: Vec<Vec2> = points.iter()
+        .take(end + 1)
+        .map(Point::position)
+        .collect();
+
+    recognize_path(&positions)
+}
+
+pub fn recognize_path(points: &[Vec2]) -> Option<ShapeKind> {
+    if points.len() < 2 {
+        return None;
+    }
+
+    let length = path_length(points);
+    if length < MIN_LENGTH {
+        return None;
+    }
+
+    let first = points[0];
+    let last = points[points.len() - 1];
+
+    if first.distance(last) < length * CLOSED_RATIO {
+        recognize_closed(points)
+    } else {
+        recognize_open(points)
+    }
+}
+
+fn path_length(points: &[Vec2]) -> f32 {
+    points.windows(2).map(|w| w[0].distance(w[1])).sum()
+}
+
+fn is_straight(points: &[Vec2]) -> bool {
+    let first = points[0];
+    let last = points[points.len() - 1];
+    let chord = first.distance(last);
+    if chord < MIN_LENGTH {
+        return false;
+    }
+
+    let max_deviation = points.iter()
+        .map(|p| geometry::point_segment_distance(*p, first, last))
+        .fold(0.0, f32::max);
+    max_deviation <= (chord * LINE_TOLERANCE).max(2.0)
+}
+
+fn recognize_open(points: &[Vec2]) -> Option<ShapeKind> {
+    let first = points[0];
+    let last = points[points.len() - 1];
+
+    if is_straight(points) {
+        return Some(ShapeKind::Line { start: first, end: last });
+    }
+
+    // 箭头：直的箭杆画到最远点后折回一小段作为箭头
+    let tip = points.iter()
+        .enumerate()
+        .max_by(|(_, a), (_, b)| a.distance(first).total_cmp(&b.distance(first)))
+        .map(|(i, _)| i)?;
+    let shaft = &points[..=tip];
+    let head = &points[tip..];
+    if head.len() < 2 || !is_straight(shaft) {
+        return None;
+    }
+
+    let shaft_length = first.distance(points[tip]);
+    let head_length = path_length(head);
+    let head_reach = head.iter()
+        .map(|p| p.distance(points[tip]))
+        .fold(0.0, f32::max);
+    if head_length > shaft_length * 0.08 && head_reach < shaft_length * 0.5 {
+        return Some(ShapeKind::Arrow { start: first, end: points[tip] });
+    }
+
+    None
+}
+
+fn recognize_closed(points: &[Vec2]) -> Option<ShapeKind> {
+    if let Some(ellipse) = fit_ellipse(points) {
+        return Some(ellipse);
+    }
+
+    let corners = polygon_corners(points);
+    match corners.len() {
+        4 if is_axis_aligned(&corners) => {
+            let (min, max) = bounding_box(&corners);
+            Some(ShapeKind::Rectangle { min, max })
+        }
+        3..=8 => Some(ShapeKind::Polygon { points: corners }),
+        _ => None,
+    }
+}
+
+// 用主成分分析求椭圆的方向，再检查各点到椭圆的归一化径向误差
+fn fit_ellipse(points: &[Vec2]) -> Option<ShapeKind> {
+    let count = points.len() as f32;
+    let mean = points.iter().fold(Vec2::new(0.0, 0.0), |acc, p| acc + *p) * (1.0 / count);
+
+    let (mut xx, mut yy, mut xy) = (0.0, 0.0, 0.0);
+    for p in points {
+        let d = *p - mean;
+        xx += d.x * d.x;
+        yy += d.y * d.y;
+        xy += d.x * d.y;
+    }
+    let rotation = 0.5 * (2.0 * xy).atan2(xx - yy);
+    let (sin, cos) = rotation.sin_cos();
+
+    // 在旋转后的坐标系中求范围
+    let local: Vec<Vec2> = points.iter()
+        .map(|p| {
+            let d = *p - mean;
+            Vec2::new(d.x * cos + d.y * sin, -d.x * sin + d.y * cos)
+        })
+        .collect();
+    let (min, max) = bounding_box(&local);
+    let radius_x = (max.x - min.x) / 2.0;
+    let radius_y = (max.y - min.y) / 2.0;
+    if radius_x < MIN_LENGTH / 2.0 || radius_y < MIN_LENGTH / 2.0 {
+        return None;
+    }
+
+    let local_center = Vec2::new((min.x + max.x) / 2.0, (min.y + max.y) / 2.0);
+    let error = local.iter()
+        .map(|p| {
+            let u = (p.x - local_center.x) / radius_x;
+            let v = (p.y - local_center.y) / radius_y;
+            ((u * u + v * v).sqrt() - 1.0).abs()
+        })
+        .sum::<f32>() / count;
+    if error > ELLIPSE_TOLERANCE {
+        return None;
+    }
+
+    let center = Vec2::new(
+        mean.x + local_center.x * cos - local_center.y * sin,
+        mean.y + local_center.x * sin + local_center.y * cos,
+    );
+    Some(ShapeKind::Ellipse { center, radius_x, radius_y, rotation })
+}
+
+// 闭合路径简化后的拐点，去掉首尾重复点和落在边中间的起点
+fn polygon_corners(points: &[Vec2]) -> Vec<Vec2> {
+    let (min, max) = bounding_box(points);
+    let tolerance = min.distance(max) * CORNER_TOLERANCE;
+
+    let mut corners: Vec<Vec2> = geometry::simplify_rdp(points, tolerance)
+        .into_iter()
+        .map(|i| points[i])
+        .collect();
+
+    if corners.len() > 1 && corners[0].distance(corners[corners.len() - 1]) < tolerance * 2.0 {
+        corners.pop();
+    }
+
+    // 起笔点如果落在一条边的中间，和前后两个拐点几乎共线，不算拐点
+    if corners.len() > 3 {
+        let previous = corners[corners.len() - 1];
+        if geometry::point_segment_distance(corners[0], previous, corners[1]) < tolerance {
+            corners.remove(0);
+        }
+    }
+
+    corners
+}
+
+fn is_axis_aligned(corners: &[Vec2]) -> bool {
+    (0..corners.len()).all(|i| {
+        let edge = corners[(i + 1) % corners.len()] - corners[i];
+        let angle = edge.y.atan2(edge.x).abs();
+        let off_axis = (angle % std::f32::consts::FRAC_PI_2)
+            .min(std::f32::consts::FRAC_PI_2 - angle % std::f32::consts::FRAC_PI_2);
+        off_axis < AXIS_TOLERANCE
+    })
+}
+
+fn bounding_box(points: &[Vec2]) -> (Vec2, Vec2) {
+    points.iter().fold(
+        (Vec2::new(f32::INFINITY, f32::INFINITY), Vec2::new(f32::NEG_INFINITY, f32::NEG_INFINITY)),
+        |(min, max), p| (
+            Vec2::new(min.x.min(p.x), min.y.min(p.y)),
+            Vec2::new(max.x.max(p.x), max.y.max(p.y)),
+        ),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // 按固定间隔记录的采样点，模拟笔画的实际输入
+    fn record(path: &[(f32, f32)]) -> Vec<Point> {
+        path.iter()
+            .enumerate()
+            .map(|(i, &(x, y))| Point { x, y, pressure: 1.0, timestamp: i as u64 * 10, tilt: None })
+            .collect()
+    }
+
+    fn interpolate(corners: &[(f32, f32)], steps: usize) -> Vec<(f32, f32)> {
+        let mut path = Vec::new();
+        for w in corners.windows(2) {
+            for k in 0..steps {
+                let t = k as f32 / steps as f32;
+                path.push((w[0].0 + (w[1].0 - w[0].0) * t, w[0].1 + (w[1].1 - w[0].1) * t));
+            }
+        }
+        path.push(*corners.last().unwrap());
+        path
+    }
+
+    #[test]
+    fn test_recognizes_wobbly_line() {
+        let path: Vec<(f32, f32)> = (0..=50)
+            .map(|i| (i as f32 * 4.0, 100.0 + if i % 2 == 0 { 1.5 } else { -1.5 }))
+            .collect();
+
+        match recognize(&record(&path)) {
+            Some(ShapeKind::Line { start, end }) => {
+                assert_eq!(start.x, 0.0);
+                assert_eq!(end.x, 200.0);
+            }
+            other => panic!("expected line, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_recognizes_circle() {
+        let path: Vec<(f32, f32)> = (0..=72)
+            .map(|i| {
+                let t = std::f32::consts::TAU * i as f32 / 72.0;
+                let r = 50.0 + (i % 3) as f32;
+                (200.0 + r * t.cos(), 150.0 + r * t.sin())
+            })
+            .collect();
+
+        match recognize(&record(&path)) {
+            Some(ShapeKind::Ellipse { center, radius_x, radius_y, .. }) => {
+                assert!(center.distance(Vec2::new(200.0, 150.0)) < 3.0);
+                assert!((radius_x - 51.0).abs() < 3.0 && (radius_y - 51.0).abs() < 3.0);
+            }
+            other => panic!("expected ellipse, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_recognizes_rectangle_starting_mid_edge() {
+        let path = interpolate(
+            &[(50.0, 10.0), (100.0, 10.0), (100.0, 80.0), (10.0, 80.0), (10.0, 10.0), (52.0, 11.0)],
+            20,
+        );
+
+        match recognize(&record(&path)) {
+            Some(ShapeKind::Rectangle { min, max }) => {
+                assert!(min.distance(Vec2::new(10.0, 10.0)) < 2.0);
+                assert!(max.distance(Vec2::new(100.0, 80.0)) < 2.0);
+            }
+            other => panic!("expected rectangle, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_recognizes_triangle() {
+        let path = interpolate(&[(0.0, 100.0), (50.0, 0.0), (100.0, 100.0), (2.0, 99.0)], 20);
+
+        match recognize(&record(&path)) {
+            Some(ShapeKind::Polygon { points }) => assert_eq!(points.len(), 3),
+            other => panic!("expected triangle, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_recognizes_arrow() {
+        let path = interpolate(&[(0.0, 0.0), (200.0, 0.0), (175.0, -18.0)], 20);
+
+        match recognize(&record(&path)) {
+            Some(ShapeKind::Arrow { start, end }) => {
+                assert_eq!(start, Vec2::new(0.0, 0.0));
+                assert_eq!(end, Vec2::new(200.0, 0.0));
+            }
+            other => panic!("expected arrow, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_scribble_is_not_a_shape() {
+        let path: Vec<(f32, f32)> = (0..=80)
+            .map(|i| {
+                let t = i as f32 * 0.3;
+                (t * 10.0, 40.0 * (t * 1.7).sin() * (t * 0.5).cos())
+            })
+            .collect();
+
+        assert!(recognize(&record(&path)).is_none());
+    }
+
+    #[test]
+    fn test_hold_detection() {
+        let mut points = record(&interpolate(&[(0.0, 0.0), (100.0, 0.0)], 10));
+        let last_moving = points.last().unwrap().timestamp;
+        // 结尾的轻微抖动仍然算静止
+        points.push(Point { x: 101.0, y: 1.0, pressure: 1.0, timestamp: last_moving + 100, tilt: None });
+
+        assert!(!is_held(&points, last_moving + 300, HOLD_MS, HOLD_TOLERANCE));
+        assert!(is_held(&points, last_moving + HOLD_MS, HOLD_MS, HOLD_TOLERANCE));
+        assert_eq!(recognize(&points).map(|s| matches!(s, ShapeKind::Line { .. })), Some(true));
+    }
+}
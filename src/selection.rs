@@ -0,0 +1,122 @@
+// 页面内容的选择状态和变换手柄，命中测试在note.rs的Page上完成
+use crate::geometry::{Rect, Transform, Vec2};
+use crate::note::{ItemRef, Page};
+
+// 旋转手柄在选框上边中点上方的距离
+pub const ROTATE_HANDLE_OFFSET: f32 = 24.0;
+// 缩放的最小比例，避免把内容压扁成一条线
+const MIN_SCALE: f32 = 0.05;
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Selection {
+    pub page_index: usize,
+    pub items: Vec<ItemRef>,
+}
+
+// 选框上可以拖动的部位
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Handle {
+    Move,
+    // 选框的角，按Rect::corners的顺序
+    Scale(usize),
+    Rotate,
+}
+
+impl Selection {
+    pub fn new(page_index: usize, items: Vec<ItemRef>) -> Option<Self> {
+        if items.is_empty() {
+            None
+        } else {
+            Some(Self { page_index, items })
+        }
+    }
+
+    pub fn bounds(&self, page: &Page) -> Option<Rect> {
+        page.items_bounds(&self.items)
+    }
+}
+
+pub fn rotate_handle(bounds: &Rect) -> Vec2 {
+    Vec2::new(bounds.center().x, bounds.min.y - ROTATE_HANDLE_OFFSET)
+}
+
+// 指针位置对应的手柄；手柄优先于选框内部
+pub fn handle_at(bounds: &Rect, position: Vec2, radius: f32) -> Option<Handle> {
+    if rotate_handle(bounds).distance(position) <= radius {
+        return Some(Handle::Rotate);
+    }
+    if let Some(corner) = bounds.corners().iter().position(|c| c.distance(position) <= radius) {
+        return Some(Handle::Scale(corner));
+    }
+    if bounds.contains(position) {
+        return Some(Handle::Move);
+    }
+    None
+}
+
+// 从start拖到current时手柄对应的变换；缩放以对角为原点，旋转以选框中心为原点
+pub fn drag_transform(handle: Handle, bounds: &Rect, start: Vec2, current: Vec2) -> Transform {
+    match handle {
+        Handle::Move => Transform::Translate(current - start),
+        Handle::Scale(corner) => {
+            let corners = bounds.corners();
+            let origin = corners[(corner + 2) % 4];
+            let from = corners[corner] - origin;
+            let to = current - origin;
+            let ratio = |to: f32, from: f32| {
+                if from.abs() <= f32::EPSILON {
+                    1.0
+                } else {
+                    (to / from).max(MIN_SCALE)
+                }
+            };
+            Transform::Scale { origin, x: ratio(to.x, from.x), y: ratio(to.y, from.y) }
+        }
+        Handle::Rotate => {
+            let origin = bounds.center();
+            let from = start - origin;
+            let to = current - origin;
+            Transform::Rotate { origin, angle: to.y.atan2(to.x) - from.y.atan2(from.x) }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn bounds() -> Rect {
+        Rect { min: Vec2::new(10.0, 10.0), max: Vec2::new(110.0, 60.0) }
+    }
+
+    #[test]
+    fn test_handle_at() {
+        let bounds = bounds();
+
+        assert_eq!(handle_at(&bounds, Vec2::new(60.0, 10.0 - ROTATE_HANDLE_OFFSET), 6.0), Some(Handle::Rotate));
+        assert_eq!(handle_at(&bounds, Vec2::new(108.0, 62.0), 6.0), Some(Handle::Scale(2)));
+        assert_eq!(handle_at(&bounds, Vec2::new(50.0, 30.0), 6.0), Some(Handle::Move));
+        assert_eq!(handle_at(&bounds, Vec2::new(200.0, 30.0), 6.0), None);
+    }
+
+    #[test]
+    fn test_scale_keeps_opposite_corner() {
+        let bounds = bounds();
+        let transform = drag_transform(Handle::Scale(2), &bounds, bounds.max, Vec2::new(210.0, 110.0));
+
+        assert_eq!(transform, Transform::Scale { origin: bounds.min, x: 2.0, y: 2.0 });
+        assert_eq!(transform.apply(bounds.min), bounds.min);
+        assert_eq!(transform.apply(bounds.max), Vec2::new(210.0, 110.0));
+    }
+
+    #[test]
+    fn test_rotate_about_center() {
+        let bounds = bounds();
+        let center = bounds.center();
+        let start = Vec2::new(center.x + 10.0, center.y);
+        let transform = drag_transform(Handle::Rotate, &bounds, start, Vec2::new(center.x, center.y + 10.0));
+
+        let rotated = transform.apply(start);
+        assert!(rotated.distance(Vec2::new(center.x, center.y + 10.0)) < 1e-4);
+    }
+}
@@ -1,6 +1,16 @@
 use warp::Filter;
+use warp::http::StatusCode;
+use warp::reply::{Reply, Response};
 use std::net::SocketAddr;
+use serde::Deserialize;
 use serde_json::json;
+use crate::note;
+use crate::storage::{self, NoteEntry};
+
+#[derive(Debug, Deserialize)]
+struct NotesQuery {
+    tag: Option<String>,
+}
 
 pub async fn start_server(port: u16) -> Result<(), Box<dyn std::error::Error>> {
     let addr: SocketAddr = ([0, 0, 0, 0], port).into();
@@ -16,16 +26,24 @@ pub async fn start_server(port: u16) -> Result<(), Box<dyn std::error::Error>> {
             }))
         });
     
-    // API端点
-    let api_route = warp::path("api")
-        .and(warp::path("notes"))
+    // API端点，只读地访问notes目录中保存的笔记；服务没有鉴权，修改元数据只能通过应用内的命令
+    // GET /api/notes?tag=xxx 按标签筛选笔记列表
+    let notes_route = warp::path!("api" / "notes")
         .and(warp::get())
-        .map(|| {
-            warp::reply::json(&json!({
-                "notes": [],
-                "message": "API端点准备就绪"
-            }))
-        });
+        .and(warp::query::<NotesQuery>())
+        .then(|query| blocking(move || list_notes(query)));
+    
+    let tags_route = warp::path!("api" / "tags")
+        .and(warp::get())
+        .then(|| blocking(list_tags));
+    
+    let get_metadata_route = warp::path!("api" / "notes" / String / "metadata")
+        .and(warp::get())
+        .then(|note_id| blocking(move || get_metadata(note_id)));
+    
+    let api_route = notes_route
+        .or(tags_route)
+        .or(get_metadata_route);
     
     // 静态文件服务（用于Web界面）
     let static_files = warp::path::end()
@@ -53,6 +71,60 @@ pub async fn start_server(port: u16) -> Result<(), Box<dyn std::error::Error>> {
     Ok(())
 }
 
+// 读取笔记文件是阻塞的磁盘IO，放到阻塞线程池中执行，不占用异步工作线程
+async fn blocking<F>(reply: F) -> Response
+where
+    F: FnOnce() -> Response + Send + 'static,
+{
+    match tokio::task::spawn_blocking(reply).await {
+        Ok(response) => response,
+        Err(e) => error_reply(StatusCode::INTERNAL_SERVER_ERROR, e.to_string()),
+    }
+}
+
+fn error_reply(status: StatusCode, message: String) -> Response {
+    warp::reply::with_status(warp::reply::json(&json!({ "error": message })), status).into_response()
+}
+
+// 列表只返回摘要和元数据，只读取笔记文件的头部字段
+fn note_summary(note: &NoteEntry) -> serde_json::Value {
+    json!({
+        "id": note.id,
+        "title": note.title,
+        "created_at": note.created_at,
+        "updated_at": note.updated_at,
+        "page_count": note.page_count,
+        "metadata": note.metadata
+    })
+}
+
+fn list_notes(query: NotesQuery) -> Response {
+    match storage::list_note_entries() {
+        Ok(notes) => {
+            let notes: Vec<serde_json::Value> = notes.iter()
+                .filter(|note| query.tag.as_deref().is_none_or(|tag| note.metadata.has_tag(tag)))
+                .map(note_summary)
+                .collect();
+            warp::reply::json(&json!({ "notes": notes })).into_response()
+        }
+        Err(e) => error_reply(StatusCode::INTERNAL_SERVER_ERROR, e.to_string()),
+    }
+}
+
+fn list_tags() -> Response {
+    match storage::list_note_entries() {
+        Ok(notes) => warp::reply::json(&json!({ "tags": note::collect_tags(notes.iter().map(|note| &note.metadata)) })).into_response(),
+        Err(e) => error_reply(StatusCode::INTERNAL_SERVER_ERROR, e.to_string()),
+    }
+}
+
+fn get_metadata(note_id: String) -> Response {
+    match storage::load_note(&note_id) {
+        Ok(note) => warp::reply::json(&note.metadata).into_response(),
+        Err(e) => error_reply(StatusCode::NOT_FOUND, e.to_string()),
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -0,0 +1,208 @@
+// 松开笔时对笔画做的后处理：防抖、RDP简化，以及渲染/导出时的三次贝塞尔拟合
+use crate::geometry::{self, Vec2};
+use crate::note::{Point, Stroke};
+use crate::outline::StrokeSample;
+
+// 压力变化超过这个值的采样点在简化时保留，避免丢失粗细变化
+const PRESSURE_TOLERANCE: f32 = 0.1;
+// 曲线展开成折线时每段的最大长度（像素）
+pub const FLATTEN_STEP: f32 = 2.0;
+// 防抖强度的上限，再高笔迹会明显跟不上笔尖
+const MAX_STABILIZATION: f32 = 0.95;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct SmoothingSettings {
+    // 防抖强度，0为关闭，越大越平滑
+    pub stabilization: f32,
+    // RDP简化容差（像素），0为不简化
+    pub tolerance: f32,
+}
+
+impl Default for SmoothingSettings {
+    fn default() -> Self {
+        Self {
+            stabilization: 0.3,
+            tolerance: 0.5,
+        }
+    }
+}
+
+// 三次贝塞尔曲线段
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct CubicBezier {
+    pub start: Vec2,
+    pub control1: Vec2,
+    pub control2: Vec2,
+    pub end: Vec2,
+}
+
+impl CubicBezier {
+    pub fn point_at(&self, t: f32) -> Vec2 {
+        let u = 1.0 - t;
+        self.start * (u * u * u)
+            + self.control1 * (3.0 * u * u * t)
+            + self.control2 * (3.0 * u * t * t)
+            + self.end * (t * t * t)
+    }
+}
+
+pub fn process_stroke(mut stroke: Stroke, settings: &SmoothingSettings) -> Stroke {
+    let stabilized = stabilize(&stroke.points, settings.stabilization);
+    stroke.points = simplify(&stabilized, settings.tolerance);
+    stroke.invalidate_outline();
+    stroke
+}
+
+// 指数滑动平均防抖；终点保留笔尖的实际位置，避免笔画缩短
+pub fn stabilize(points: &[Point], strength: f32) -> Vec<Point> {
+    let strength = strength.clamp(0.0, MAX_STABILIZATION);
+    if strength <= 0.0 || points.len() < 3 {
+        return points.to_vec();
+    }
+
+    let mut result = Vec::with_capacity(points.len());
+    let mut smoothed = points[0].position();
+    for point in points {
+        smoothed = smoothed.lerp(point.position(), 1.0 - strength);
+        result.push(Point { x: smoothed.x, y: smoothed.y, ..*point });
+    }
+
+    if let (Some(last), Some(raw)) = (result.last_mut(), points.last()) {
+        *last = *raw;
+    }
+    result
+}
+
+// RDP简化，同时保留压力明显变化的点
+pub fn simplify(points: &[Point], tolerance: f32) -> Vec<Point> {
+    if tolerance <= 0.0 || points.len() < 3 {
+        return points.to_vec();
+    }
+
+    let positions: Vec<Vec2> = points.iter().map(Point::position).collect();
+    let mut keep = vec![false; points.len()];
+    for index in geometry::simplify_rdp(&positions, tolerance) {
+        keep[index] = true;
+    }
+
+    let mut last_pressure = points[0].pressure;
+    for (index, point) in points.iter().enumerate() {
+        if (point.pressure - last_pressure).abs() > PRESSURE_TOLERANCE {
+            keep[index] = true;
+        }
+        if keep[index] {
+            last_pressure = point.pressure;
+        }
+    }
+
+    points.iter()
+        .zip(keep)
+        .filter(|(_, keep)| *keep)
+        .map(|(point, _)| *point)
+        .collect()
+}
+
+// 经过所有点的平滑曲线（Catmull-Rom样条转换成的贝塞尔段），每两个相邻点之间一段
+pub fn fit_curve(points: &[Vec2]) -> Vec<CubicBezier> {
+    if points.len() < 2 {
+        return Vec::new();
+    }
+
+    let last = points.len() - 1;
+    (0..last)
+        .map(|i| {
+            let before = points[i.saturating_sub(1)];
+            let start = points[i];
+            let end = points[i + 1];
+            let after = points[(i + 2).min(last)];
+            CubicBezier {
+                start,
+                control1: start + (end - before) * (1.0 / 6.0),
+                control2: end - (after - start) * (1.0 / 6.0),
+                end,
+            }
+        })
+        .collect()
+}
+
+// 沿拟合曲线重新采样，压力线性插值；原本就很密的笔画不会增加点数
+pub fn flatten_stroke(points: &[Point]) -> Vec<StrokeSample> {
+    let positions: Vec<Vec2> = points.iter().map(Point::position).collect();
+    let curves = fit_curve(&positions);
+    if curves.is_empty() {
+        return points.iter()
+            .map(|p| StrokeSample { position: p.position(), pressure: p.pressure })
+            .collect();
+    }
+
+    let mut samples = vec![StrokeSample { position: positions[0], pressure: points[0].pressure }];
+    for (i, curve) in curves.iter().enumerate() {
+        let (from, to) = (points[i].pressure, points[i + 1].pressure);
+        let steps = (curve.start.distance(curve.end) / FLATTEN_STEP).ceil().max(1.0) as usize;
+        for step in 1..=steps {
+            let t = step as f32 / steps as f32;
+            samples.push(StrokeSample {
+                position: curve.point_at(t),
+                pressure: from + (to - from) * t,
+            });
+        }
+    }
+    samples
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn point(x: f32, y: f32, pressure: f32) -> Point {
+        Point { x, y, pressure, timestamp: 0, tilt: None }
+    }
+
+    #[test]
+    fn test_stabilize_reduces_jitter_and_keeps_endpoints() {
+        let points: Vec<Point> = (0..40)
+            .map(|i| point(i as f32 * 2.0, if i % 2 == 0 { 1.0 } else { -1.0 }, 1.0))
+            .collect();
+        let stabilized = stabilize(&points, 0.6);
+
+        assert_eq!(stabilized.len(), points.len());
+        assert_eq!(stabilized[0].position(), points[0].position());
+        assert_eq!(stabilized[39].position(), points[39].position());
+        let max_offset = stabilized[10..30].iter().map(|p| p.y.abs()).fold(0.0, f32::max);
+        assert!(max_offset < 0.5);
+    }
+
+    #[test]
+    fn test_simplify_keeps_pressure_changes() {
+        let mut points: Vec<Point> = (0..=20).map(|i| point(i as f32 * 5.0, 0.0, 0.5)).collect();
+        points[10].pressure = 0.9;
+        let simplified = simplify(&points, 0.5);
+
+        let xs: Vec<f32> = simplified.iter().map(|p| p.x).collect();
+        assert_eq!(xs, vec![0.0, 50.0, 55.0, 100.0]);
+    }
+
+    #[test]
+    fn test_fit_curve_passes_through_points() {
+        let points = [Vec2::new(0.0, 0.0), Vec2::new(10.0, 10.0), Vec2::new(20.0, 0.0)];
+        let curves = fit_curve(&points);
+
+        assert_eq!(curves.len(), 2);
+        assert_eq!(curves[0].point_at(0.0), points[0]);
+        assert_eq!(curves[0].point_at(1.0), points[1]);
+        assert_eq!(curves[1].start, points[1]);
+        // 经过中间点时切线连续
+        let incoming = curves[0].end - curves[0].control2;
+        let outgoing = curves[1].control1 - curves[1].start;
+        assert!(incoming.cross(outgoing).abs() < 1e-4);
+    }
+
+    #[test]
+    fn test_flatten_interpolates_pressure() {
+        let samples = flatten_stroke(&[point(0.0, 0.0, 0.0), point(10.0, 0.0, 1.0)]);
+
+        assert_eq!(samples.len(), 6);
+        assert!((samples[3].pressure - 0.6).abs() < 1e-6);
+        assert_eq!(samples[5].position, Vec2::new(10.0, 0.0));
+    }
+}
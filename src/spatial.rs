@@ -0,0 +1,181 @@
+// 均匀网格划分的空间索引，用于快速找出某个区域附近的笔画，同时缓存每一项的包围盒
+use crate::geometry::{Rect, Vec2};
+use std::collections::HashMap;
+
+// 网格单元的边长（画布坐标），约为常见笔画的大小
+pub const CELL_SIZE: f32 = 128.0;
+
+type Cell = (i32, i32);
+
+// 项目按插入顺序编号，与图层中笔画的索引一一对应
+#[derive(Debug, Clone, Default)]
+pub struct SpatialIndex {
+    // 每一项的包围盒，没有点的笔画为None且不进入网格
+    bounds: Vec<Option<Rect>>,
+    cells: HashMap<Cell, Vec<usize>>,
+}
+
+impl SpatialIndex {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn from_bounds(bounds: impl IntoIterator<Item = Option<Rect>>) -> Self {
+        let mut index = Self::new();
+        for item in bounds {
+            index.push(item);
+        }
+        index
+    }
+
+    pub fn len(&self) -> usize {
+        self.bounds.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.bounds.is_empty()
+    }
+
+    pub fn bounds(&self, index: usize) -> Option<Rect> {
+        self.bounds.get(index).copied().flatten()
+    }
+
+    pub fn push(&mut self, bounds: Option<Rect>) {
+        let index = self.bounds.len();
+        self.bounds.push(bounds);
+        self.add_to_cells(index, bounds);
+    }
+
+    // 在中间插入时后面各项的编号加一
+    pub fn insert(&mut self, index: usize, bounds: Option<Rect>) {
+        if index >= self.bounds.len() {
+            self.push(bounds);
+            return;
+        }
+
+        for entries in self.cells.values_mut() {
+            for entry in entries.iter_mut().filter(|entry| **entry >= index) {
+                *entry += 1;
+            }
+        }
+        self.bounds.insert(index, bounds);
+        self.add_to_cells(index, bounds);
+    }
+
+    // 删除后后面各项的编号减一
+    pub fn remove(&mut self, index: usize) {
+        if index >= self.bounds.len() {
+            return;
+        }
+
+        let bounds = self.bounds.remove(index);
+        self.remove_from_cells(index, bounds);
+        if index < self.bounds.len() {
+            for entries in self.cells.values_mut() {
+                for entry in entries.iter_mut().filter(|entry| **entry > index) {
+                    *entry -= 1;
+                }
+            }
+        }
+    }
+
+    // 内容变化（移动、缩放、改粗细）后更新包围盒
+    pub fn update(&mut self, index: usize, bounds: Option<Rect>) {
+        if index >= self.bounds.len() {
+            return;
+        }
+
+        let old = std::mem::replace(&mut self.bounds[index], bounds);
+        self.remove_from_cells(index, old);
+        self.add_to_cells(index, bounds);
+    }
+
+    // 包围盒与area相交的项目，按编号升序
+    pub fn query(&self, area: &Rect) -> Vec<usize> {
+        let mut found: Vec<usize> = cells_for(area)
+            .filter_map(|cell| self.cells.get(&cell))
+            .flatten()
+            .copied()
+            .filter(|index| self.bounds(*index).is_some_and(|bounds| bounds.intersects(area)))
+            .collect();
+        found.sort_unstable();
+        found.dedup();
+        found
+    }
+
+    pub fn query_point(&self, point: Vec2, radius: f32) -> Vec<usize> {
+        self.query(&Rect { min: point, max: point }.expand(radius))
+    }
+
+    fn add_to_cells(&mut self, index: usize, bounds: Option<Rect>) {
+        if let Some(bounds) = bounds {
+            for cell in cells_for(&bounds) {
+                self.cells.entry(cell).or_default().push(index);
+            }
+        }
+    }
+
+    fn remove_from_cells(&mut self, index: usize, bounds: Option<Rect>) {
+        if let Some(bounds) = bounds {
+            for cell in cells_for(&bounds) {
+                if let Some(entries) = self.cells.get_mut(&cell) {
+                    entries.retain(|entry| *entry != index);
+                    if entries.is_empty() {
+                        self.cells.remove(&cell);
+                    }
+                }
+            }
+        }
+    }
+}
+
+fn cell_of(p: Vec2) -> Cell {
+    ((p.x / CELL_SIZE).floor() as i32, (p.y / CELL_SIZE).floor() as i32)
+}
+
+fn cells_for(area: &Rect) -> impl Iterator<Item = Cell> {
+    let (min, max) = (cell_of(area.min), cell_of(area.max));
+    (min.1..=max.1).flat_map(move |y| (min.0..=max.0).map(move |x| (x, y)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn square(x: f32, y: f32, size: f32) -> Option<Rect> {
+        Some(Rect::from_min_size(Vec2::new(x, y), size, size))
+    }
+
+    #[test]
+    fn test_query_matches_linear_scan() {
+        let bounds: Vec<Option<Rect>> = (0..200)
+            .map(|i| square((i * 37 % 1000) as f32 - 300.0, (i * 53 % 900) as f32, (i % 7) as f32 * 40.0))
+            .chain([None])
+            .collect();
+        let index = SpatialIndex::from_bounds(bounds.iter().copied());
+        let area = Rect { min: Vec2::new(-50.0, 100.0), max: Vec2::new(400.0, 350.0) };
+
+        let expected: Vec<usize> = (0..bounds.len())
+            .filter(|i| bounds[*i].is_some_and(|b| b.intersects(&area)))
+            .collect();
+        assert!(!expected.is_empty());
+        assert_eq!(index.query(&area), expected);
+    }
+
+    #[test]
+    fn test_insert_remove_and_update_renumber() {
+        let mut index = SpatialIndex::from_bounds([square(0.0, 0.0, 10.0), square(500.0, 500.0, 10.0)]);
+
+        index.insert(0, square(505.0, 505.0, 10.0));
+        assert_eq!(index.query_point(Vec2::new(506.0, 506.0), 1.0), vec![0, 2]);
+
+        index.remove(1);
+        assert_eq!(index.len(), 2);
+        assert_eq!(index.query_point(Vec2::new(506.0, 506.0), 1.0), vec![0, 1]);
+        assert!(index.query_point(Vec2::new(5.0, 5.0), 1.0).is_empty());
+
+        index.update(0, square(-1000.0, 0.0, 10.0));
+        assert_eq!(index.query_point(Vec2::new(-995.0, 5.0), 1.0), vec![0]);
+        assert_eq!(index.query_point(Vec2::new(506.0, 506.0), 1.0), vec![1]);
+    }
+}
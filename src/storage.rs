@@ -1,44 +1,127 @@
-use crate::note::Note;
-use serde_json;
+use crate::migration;
+use crate::note::{self, Backlink, Element, Note, NoteMetadata, FORMAT_VERSION};
+use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, HashSet};
 use std::fs;
-use std::path::Path;
+use std::path::{Path, PathBuf};
+use std::sync::{LazyLock, Mutex, PoisonError};
+use walkdir::WalkDir;
 
 const NOTES_DIR: &str = "notes";
 const FILE_EXTENSION: &str = "spn";
+const ASSETS_EXTENSION: &str = "assets";
 
-pub fn save_note(note: &Note) -> Result<(), Box<dyn std::error::Error>> {
-    // 确保notes目录存在
-    if !Path::new(NOTES_DIR).exists() {
-        fs::create_dir_all(NOTES_DIR)?;
+// 笔记id到.spn文件路径的索引，按notes根目录分别保存。第一次查找时遍历目录建立，
+// 之后在保存、移动和删除时同步更新，查找笔记不再需要每次遍历所有文件夹
+static NOTE_INDEX: LazyLock<Mutex<HashMap<PathBuf, HashMap<String, PathBuf>>>> = LazyLock::new(Default::default);
+
+// 文件夹就是notes目录下的子目录，可以任意嵌套；笔记的.spn文件和资源目录放在所在的文件夹中。
+// 文件夹路径用"/"分隔并相对于notes目录，例如"课程/线性代数"，根目录为空字符串
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct Folder {
+    pub name: String,
+    pub path: String,
+    pub folders: Vec<Folder>,
+    pub notes: Vec<NoteEntry>,
+}
+
+// 文件夹树中的笔记，只包含列表需要的信息
+#[derive(Debug, Clone, Serialize)]
+pub struct NoteEntry {
+    pub id: String,
+    pub title: String,
+    pub created_at: u64,
+    pub updated_at: u64,
+    pub page_count: usize,
+    pub metadata: NoteMetadata,
+}
+
+// 文件夹树只需要的头部字段，页面内容不解析也不迁移；这些字段在各个格式版本中没有变化
+#[derive(Deserialize)]
+struct NoteHeader {
+    id: String,
+    title: String,
+    #[serde(default)]
+    created_at: u64,
+    updated_at: u64,
+    #[serde(default, rename = "pages", deserialize_with = "count_pages")]
+    page_count: usize,
+    format_version: Option<u64>,
+    #[serde(default)]
+    metadata: NoteMetadata,
+}
+
+// 只数页面个数，跳过页面内容
+fn count_pages<'de, D: serde::Deserializer<'de>>(deserializer: D) -> Result<usize, D::Error> {
+    Ok(Vec::<serde::de::IgnoredAny>::deserialize(deserializer)?.len())
+}
+
+impl Folder {
+    // 自身及所有子文件夹的路径，先序排列，用于"移动到"菜单
+    pub fn paths(&self) -> Vec<String> {
+        let mut paths = vec![self.path.clone()];
+        for folder in &self.folders {
+            paths.extend(folder.paths());
+        }
+        paths
+    }
+    
+    pub fn find(&self, path: &str) -> Option<&Folder> {
+        if self.path == path {
+            return Some(self);
+        }
+        self.folders.iter().find_map(|folder| folder.find(path))
     }
     
+    // 自身及所有子文件夹中的笔记
+    pub fn all_notes(&self) -> Vec<&NoteEntry> {
+        let mut notes: Vec<&NoteEntry> = self.notes.iter().collect();
+        for folder in &self.folders {
+            notes.extend(folder.all_notes());
+        }
+        notes
+    }
+}
+
+// 已保存的笔记写回原来的文件夹，新笔记保存在根目录
+pub fn save_note(note: &Note) -> Result<(), Box<dyn std::error::Error>> {
+    save_note_in(Path::new(NOTES_DIR), note)
+}
+
+fn save_note_in(root: &Path, note: &Note) -> Result<(), Box<dyn std::error::Error>> {
     // 序列化笔记数据
     let json_data = serde_json::to_string_pretty(note)?;
     
     // 构建文件路径
-    let file_name = format!("{}.{}", note.id, FILE_EXTENSION);
-    let file_path = Path::new(NOTES_DIR).join(file_name);
+    let file_path = find_note_file(root, &note.id)
+        .unwrap_or_else(|| root.join(format!("{}.{}", note.id, FILE_EXTENSION)));
+    
+    // 确保所在目录存在
+    if let Some(dir) = file_path.parent() {
+        fs::create_dir_all(dir)?;
+    }
     
     // 写入文件
-    fs::write(file_path, json_data)?;
+    fs::write(&file_path, json_data)?;
+    with_index(root, |index| index.insert(note.id.clone(), file_path));
     
     Ok(())
 }
 
 pub fn load_note(note_id: &str) -> Result<Note, Box<dyn std::error::Error>> {
-    let file_name = format!("{}.{}", note_id, FILE_EXTENSION);
-    let file_path = Path::new(NOTES_DIR).join(file_name);
-    
-    if !file_path.exists() {
-        return Err(format!("Note file not found: {}", note_id).into());
-    }
-    
+    let file_path = find_note_file(Path::new(NOTES_DIR), note_id)
+        .ok_or_else(|| format!("Note file not found: {}", note_id))?;
+    read_note(&file_path)
+}
+
+fn read_note(file_path: &Path) -> Result<Note, Box<dyn std::error::Error>> {
     let json_data = fs::read_to_string(file_path)?;
-    let note: Note = serde_json::from_str(&json_data)?;
+    let note = migration::parse_note(&json_data)?;
     
     Ok(note)
 }
 
+// 所有文件夹中的笔记
 pub fn list_notes() -> Result<Vec<Note>, Box<dyn std::error::Error>> {
     let notes_dir = Path::new(NOTES_DIR);
     
@@ -46,35 +129,49 @@ pub fn list_notes() -> Result<Vec<Note>, Box<dyn std::error::Error>> {
         return Ok(Vec::new());
     }
     
-    let mut notes = Vec::new();
-    
-    for entry in fs::read_dir(notes_dir)? {
-        let entry = entry?;
-        let path = entry.path();
-        
-        if path.extension().and_then(|s| s.to_str()) == Some(FILE_EXTENSION) {
-            if let Ok(note) = load_note(
-                path.file_stem()
-                    .and_then(|s| s.to_str())
-                    .unwrap_or("")
-            ) {
-                notes.push(note);
-            }
-        }
-    }
+    let mut notes: Vec<Note> = note_files(notes_dir)
+        .filter_map(|path| read_note(&path).ok())
+        .collect();
     
     // 按更新时间排序
-    notes.sort_by(|a, b| b.updated_at.cmp(&a.updated_at));
+    notes.sort_by_key(|n| std::cmp::Reverse(n.updated_at));
     
     Ok(notes)
 }
 
+// 所有文件夹中笔记的头部信息，按更新时间排序；不解析页面内容，适合只需要列表的场合
+pub fn list_note_entries() -> Result<Vec<NoteEntry>, Box<dyn std::error::Error>> {
+    let notes_dir = Path::new(NOTES_DIR);
+    
+    if !notes_dir.exists() {
+        return Ok(Vec::new());
+    }
+    
+    let mut entries: Vec<NoteEntry> = note_files(notes_dir)
+        .filter_map(|path| read_note_entry(&path).ok())
+        .collect();
+    entries.sort_by_key(|entry| std::cmp::Reverse(entry.updated_at));
+    
+    Ok(entries)
+}
+
+// 所有已保存的笔记中指向note的链接
+pub fn stored_backlinks(note: &Note) -> Result<Vec<Backlink>, Box<dyn std::error::Error>> {
+    Ok(note::backlinks(note, &list_notes()?))
+}
+
 pub fn delete_note(note_id: &str) -> Result<(), Box<dyn std::error::Error>> {
-    let file_name = format!("{}.{}", note_id, FILE_EXTENSION);
-    let file_path = Path::new(NOTES_DIR).join(file_name);
+    let root = Path::new(NOTES_DIR);
+    let file_path = match find_note_file(root, note_id) {
+        Some(file_path) => file_path,
+        None => return Ok(()),
+    };
+    fs::remove_file(&file_path)?;
+    with_index(root, |index| index.remove(note_id));
     
-    if file_path.exists() {
-        fs::remove_file(file_path)?;
+    let assets_dir = assets_dir_for(&file_path);
+    if assets_dir.exists() {
+        fs::remove_dir_all(assets_dir)?;
     }
     
     Ok(())
@@ -83,11 +180,496 @@ pub fn delete_note(note_id: &str) -> Result<(), Box<dyn std::error::Error>> {
 pub fn export_note_as_spn(note: &Note, export_path: &str) -> Result<(), Box<dyn std::error::Error>> {
     let json_data = serde_json::to_string_pretty(note)?;
     fs::write(export_path, json_data)?;
+    
+    // 图片等资源随笔记一起导出到同名的.assets目录
+    copy_assets(&note_assets_dir(&note.id), &assets_dir_for(Path::new(export_path)))?;
     Ok(())
 }
 
 pub fn import_note_from_spn(import_path: &str) -> Result<Note, Box<dyn std::error::Error>> {
     let json_data = fs::read_to_string(import_path)?;
-    let note: Note = serde_json::from_str(&json_data)?;
+    let note = migration::parse_note(&json_data)?;
+    
+    copy_assets(&assets_dir_for(Path::new(import_path)), &note_assets_dir(&note.id))?;
     Ok(note)
-}
\ No newline at end of file
+}
+
+// .spn文件对应的资源目录，例如 notes/abc.spn 对应 notes/abc.assets
+fn assets_dir_for(spn_path: &Path) -> PathBuf {
+    spn_path.with_extension(ASSETS_EXTENSION)
+}
+
+fn is_assets_dir(path: &Path) -> bool {
+    path.is_dir() && path.extension().and_then(|s| s.to_str()) == Some(ASSETS_EXTENSION)
+}
+
+// 还没有保存过的笔记按根目录处理
+pub fn note_assets_dir(note_id: &str) -> PathBuf {
+    let root = Path::new(NOTES_DIR);
+    let file_path = find_note_file(root, note_id)
+        .unwrap_or_else(|| root.join(format!("{}.{}", note_id, FILE_EXTENSION)));
+    assets_dir_for(&file_path)
+}
+
+// 所有文件夹中的.spn文件，跳过资源目录
+fn note_files(root: &Path) -> impl Iterator<Item = PathBuf> {
+    WalkDir::new(root)
+        .into_iter()
+        .filter_entry(|entry| !is_assets_dir(entry.path()))
+        .filter_map(Result::ok)
+        .filter(|entry| {
+            entry.file_type().is_file()
+                && entry.path().extension().and_then(|s| s.to_str()) == Some(FILE_EXTENSION)
+        })
+        .map(|entry| entry.into_path())
+}
+
+fn scan_note_files(root: &Path) -> HashMap<String, PathBuf> {
+    note_files(root)
+        .filter_map(|path| Some((path.file_stem()?.to_str()?.to_string(), path)))
+        .collect()
+}
+
+fn with_index<R>(root: &Path, f: impl FnOnce(&mut HashMap<String, PathBuf>) -> R) -> R {
+    let mut indexes = NOTE_INDEX.lock().unwrap_or_else(PoisonError::into_inner);
+    let index = indexes.entry(root.to_path_buf()).or_insert_with(|| scan_note_files(root));
+    f(index)
+}
+
+// 丢弃索引，下次查找时重新遍历
+fn forget_index(root: &Path) {
+    NOTE_INDEX.lock().unwrap_or_else(PoisonError::into_inner).remove(root);
+}
+
+fn find_note_file(root: &Path, note_id: &str) -> Option<PathBuf> {
+    with_index(root, |index| {
+        match index.get(note_id) {
+            Some(path) if path.is_file() => Some(path.clone()),
+            // 文件在程序之外被移动或删除了，重新遍历一次
+            Some(_) => {
+                *index = scan_note_files(root);
+                index.get(note_id).cloned()
+            }
+            None => None,
+        }
+    })
+}
+
+// 文件夹移动后，其中笔记的路径换成新的前缀
+fn reindex_folder(root: &Path, from: &Path, to: &Path) {
+    with_index(root, |index| {
+        for path in index.values_mut() {
+            if let Ok(relative) = path.strip_prefix(from) {
+                *path = to.join(relative);
+            }
+        }
+    });
+}
+
+// 文件夹名不能为空，不能含路径分隔符，不能以"."开头，也不能与资源目录混淆
+fn validate_folder_name(name: &str) -> Result<(), Box<dyn std::error::Error>> {
+    let extension = Path::new(name).extension().and_then(|s| s.to_str());
+    if name.trim().is_empty() || name.contains(['/', '\\']) || name.starts_with('.') || extension == Some(ASSETS_EXTENSION) {
+        return Err(format!("Invalid folder name: {}", name).into());
+    }
+    Ok(())
+}
+
+fn folder_dir(root: &Path, folder: &str) -> Result<PathBuf, Box<dyn std::error::Error>> {
+    let mut dir = root.to_path_buf();
+    for name in folder.split('/').filter(|name| !name.is_empty()) {
+        validate_folder_name(name)?;
+        dir.push(name);
+    }
+    Ok(dir)
+}
+
+fn join_folder(parent: &str, name: &str) -> String {
+    if parent.is_empty() {
+        name.to_string()
+    } else {
+        format!("{}/{}", parent, name)
+    }
+}
+
+// 去掉多余的"/"，根目录为空字符串
+fn normalize_folder(folder: &str) -> String {
+    folder.split('/').filter(|name| !name.is_empty()).collect::<Vec<_>>().join("/")
+}
+
+// 整个文件夹树；子文件夹按名字排序，笔记按更新时间排序
+pub fn folder_tree() -> Result<Folder, Box<dyn std::error::Error>> {
+    let root = Path::new(NOTES_DIR);
+    if !root.exists() {
+        return Ok(Folder::default());
+    }
+    // 刷新列表时也重建索引，包括程序之外新加入的笔记
+    forget_index(root);
+    read_folder(root, String::new(), String::new())
+}
+
+fn read_folder(dir: &Path, name: String, path: String) -> Result<Folder, Box<dyn std::error::Error>> {
+    let mut folder = Folder { name, path, ..Default::default() };
+    
+    for entry in fs::read_dir(dir)? {
+        let entry_path = entry?.path();
+        if entry_path.is_dir() {
+            if is_assets_dir(&entry_path) {
+                continue;
+            }
+            let name = entry_path.file_name().and_then(|s| s.to_str()).unwrap_or_default().to_string();
+            let path = join_folder(&folder.path, &name);
+            folder.folders.push(read_folder(&entry_path, name, path)?);
+        } else if entry_path.extension().and_then(|s| s.to_str()) == Some(FILE_EXTENSION) {
+            // 无法解析的文件不出现在列表中
+            if let Ok(entry) = read_note_entry(&entry_path) {
+                folder.notes.push(entry);
+            }
+        }
+    }
+    
+    folder.folders.sort_by(|a, b| a.name.cmp(&b.name));
+    folder.notes.sort_by_key(|note| std::cmp::Reverse(note.updated_at));
+    Ok(folder)
+}
+
+fn read_note_entry(file_path: &Path) -> Result<NoteEntry, Box<dyn std::error::Error>> {
+    let header: NoteHeader = serde_json::from_str(&fs::read_to_string(file_path)?)?;
+    let version = header.format_version.unwrap_or(1);
+    if version > FORMAT_VERSION as u64 {
+        return Err(format!("Unsupported .spn format version: {}", version).into());
+    }
+    
+    Ok(NoteEntry {
+        id: header.id,
+        title: header.title,
+        created_at: header.created_at,
+        updated_at: header.updated_at,
+        page_count: header.page_count,
+        metadata: header.metadata,
+    })
+}
+
+// 在parent下新建文件夹，返回新文件夹的路径
+pub fn create_folder(parent: &str, name: &str) -> Result<String, Box<dyn std::error::Error>> {
+    create_folder_in(Path::new(NOTES_DIR), parent, name)
+}
+
+fn create_folder_in(root: &Path, parent: &str, name: &str) -> Result<String, Box<dyn std::error::Error>> {
+    validate_folder_name(name)?;
+    let parent_dir = folder_dir(root, parent)?;
+    if !parent_dir.is_dir() && !normalize_folder(parent).is_empty() {
+        return Err(format!("Folder not found: {}", parent).into());
+    }
+    
+    let dir = parent_dir.join(name);
+    if dir.exists() {
+        return Err(format!("Folder already exists: {}", name).into());
+    }
+    fs::create_dir_all(dir)?;
+    Ok(join_folder(&normalize_folder(parent), name))
+}
+
+// 重命名文件夹，返回新的路径
+pub fn rename_folder(folder: &str, new_name: &str) -> Result<String, Box<dyn std::error::Error>> {
+    rename_folder_in(Path::new(NOTES_DIR), folder, new_name)
+}
+
+fn rename_folder_in(root: &Path, folder: &str, new_name: &str) -> Result<String, Box<dyn std::error::Error>> {
+    let folder = normalize_folder(folder);
+    let parent = folder.rsplit_once('/').map_or("", |(parent, _)| parent).to_string();
+    move_folder_to(root, &folder, &parent, new_name)
+}
+
+// 把文件夹连同其中的笔记移动到new_parent下，返回新的路径
+pub fn move_folder(folder: &str, new_parent: &str) -> Result<String, Box<dyn std::error::Error>> {
+    move_folder_in(Path::new(NOTES_DIR), folder, new_parent)
+}
+
+fn move_folder_in(root: &Path, folder: &str, new_parent: &str) -> Result<String, Box<dyn std::error::Error>> {
+    let folder = normalize_folder(folder);
+    let name = folder.rsplit('/').next().unwrap_or_default().to_string();
+    move_folder_to(root, &folder, new_parent, &name)
+}
+
+fn move_folder_to(root: &Path, folder: &str, new_parent: &str, name: &str) -> Result<String, Box<dyn std::error::Error>> {
+    validate_folder_name(name)?;
+    let new_parent = normalize_folder(new_parent);
+    let from = folder_dir(root, folder)?;
+    if folder.is_empty() || !from.is_dir() {
+        return Err(format!("Folder not found: {}", folder).into());
+    }
+    // 不能移动到自己或自己的子文件夹中
+    if new_parent == folder || new_parent.starts_with(&format!("{}/", folder)) {
+        return Err("Cannot move a folder into itself".into());
+    }
+    
+    let new_path = join_folder(&new_parent, name);
+    if new_path == folder {
+        return Ok(new_path);
+    }
+    let to = folder_dir(root, &new_path)?;
+    if to.exists() {
+        return Err(format!("Folder already exists: {}", new_path).into());
+    }
+    if let Some(parent) = to.parent() {
+        fs::create_dir_all(parent)?;
+    }
+    fs::rename(&from, &to)?;
+    reindex_folder(root, &from, &to);
+    Ok(new_path)
+}
+
+// 删除文件夹；recursive为false时只能删除空文件夹，否则连同其中的笔记一起删除
+pub fn delete_folder(folder: &str, recursive: bool) -> Result<(), Box<dyn std::error::Error>> {
+    delete_folder_in(Path::new(NOTES_DIR), folder, recursive)
+}
+
+fn delete_folder_in(root: &Path, folder: &str, recursive: bool) -> Result<(), Box<dyn std::error::Error>> {
+    let dir = folder_dir(root, folder)?;
+    if normalize_folder(folder).is_empty() {
+        return Err("Cannot delete the root folder".into());
+    }
+    if !dir.is_dir() {
+        return Err(format!("Folder not found: {}", folder).into());
+    }
+    
+    if recursive {
+        fs::remove_dir_all(&dir)?;
+        with_index(root, |index| index.retain(|_, path| !path.starts_with(&dir)));
+    } else {
+        if fs::read_dir(&dir)?.next().is_some() {
+            return Err(format!("Folder is not empty: {}", folder).into());
+        }
+        fs::remove_dir(dir)?;
+    }
+    Ok(())
+}
+
+// 把笔记及其资源目录移动到另一个文件夹
+pub fn move_note(note_id: &str, folder: &str) -> Result<(), Box<dyn std::error::Error>> {
+    move_note_in(Path::new(NOTES_DIR), note_id, folder)
+}
+
+fn move_note_in(root: &Path, note_id: &str, folder: &str) -> Result<(), Box<dyn std::error::Error>> {
+    let from = find_note_file(root, note_id)
+        .ok_or_else(|| format!("Note file not found: {}", note_id))?;
+    let dir = folder_dir(root, folder)?;
+    if !dir.is_dir() && !normalize_folder(folder).is_empty() {
+        return Err(format!("Folder not found: {}", folder).into());
+    }
+    
+    let to = dir.join(format!("{}.{}", note_id, FILE_EXTENSION));
+    if to == from {
+        return Ok(());
+    }
+    fs::create_dir_all(&dir)?;
+    fs::rename(&from, &to)?;
+    with_index(root, |index| index.insert(note_id.to_string(), to.clone()));
+    
+    let assets_dir = assets_dir_for(&from);
+    if assets_dir.exists() {
+        fs::rename(assets_dir, assets_dir_for(&to))?;
+    }
+    Ok(())
+}
+
+fn copy_assets(from: &Path, to: &Path) -> Result<(), Box<dyn std::error::Error>> {
+    if !from.exists() || from == to {
+        return Ok(());
+    }
+    
+    fs::create_dir_all(to)?;
+    for entry in fs::read_dir(from)? {
+        let entry = entry?;
+        if entry.file_type()?.is_file() {
+            fs::copy(entry.path(), to.join(entry.file_name()))?;
+        }
+    }
+    
+    Ok(())
+}
+
+// 解码图片并生成资源名，返回资源名和图片的原始尺寸；这一步还不写入磁盘
+pub fn prepare_image_asset(data: &[u8]) -> Result<(String, u32, u32), Box<dyn std::error::Error>> {
+    let format = image::guess_format(data)?;
+    let decoded = image::load_from_memory_with_format(data, format)?;
+    let extension = format.extensions_str().first().copied().unwrap_or("img");
+    
+    let asset = format!("{}.{}", uuid::Uuid::new_v4(), extension);
+    Ok((asset, decoded.width(), decoded.height()))
+}
+
+// 图片确定能加入笔记后再写入资源目录，插入失败时不会留下文件
+pub fn write_image_asset(note_id: &str, asset: &str, data: &[u8]) -> Result<(), Box<dyn std::error::Error>> {
+    let path = asset_path(note_id, asset)?;
+    fs::create_dir_all(note_assets_dir(note_id))?;
+    fs::write(path, data)?;
+    Ok(())
+}
+
+// 删除资源目录中没有被任何图片引用的文件，例如插入后又被撤销的图片。
+// 重做可能还需要这些文件，所以只在笔记刚读入、没有撤销历史时调用
+pub fn remove_unused_assets(note: &Note) -> Result<usize, Box<dyn std::error::Error>> {
+    remove_unused_assets_in(&note_assets_dir(&note.id), note)
+}
+
+fn remove_unused_assets_in(assets_dir: &Path, note: &Note) -> Result<usize, Box<dyn std::error::Error>> {
+    if !assets_dir.is_dir() {
+        return Ok(0);
+    }
+    
+    let used: HashSet<&str> = note.pages.iter()
+        .flat_map(|page| page.elements())
+        .filter_map(|element| match element {
+            Element::Image(image) => Some(image.asset.as_str()),
+            _ => None,
+        })
+        .collect();
+    
+    let mut removed = 0;
+    for entry in fs::read_dir(assets_dir)? {
+        let entry = entry?;
+        let name = entry.file_name();
+        if entry.file_type()?.is_file() && !name.to_str().is_some_and(|name| used.contains(name)) {
+            fs::remove_file(entry.path())?;
+            removed += 1;
+        }
+    }
+    Ok(removed)
+}
+
+// 资源名只能是资源目录下的文件名
+fn validate_asset_name(asset: &str) -> Result<(), Box<dyn std::error::Error>> {
+    if asset.is_empty() || asset.contains(['/', '\\']) || asset.starts_with('.') {
+        return Err(format!("Invalid asset name: {}", asset).into());
+    }
+    Ok(())
+}
+
+fn asset_path(note_id: &str, asset: &str) -> Result<PathBuf, Box<dyn std::error::Error>> {
+    validate_asset_name(asset)?;
+    Ok(note_assets_dir(note_id).join(asset))
+}
+
+pub fn load_image_asset(note_id: &str, asset: &str) -> Result<image::RgbaImage, Box<dyn std::error::Error>> {
+    let data = read_image_asset(note_id, asset)?;
+    Ok(image::load_from_memory(&data)?.to_rgba8())
+}
+
+// 图片资源的原始文件内容
+pub fn read_image_asset(note_id: &str, asset: &str) -> Result<Vec<u8>, Box<dyn std::error::Error>> {
+    Ok(fs::read(asset_path(note_id, asset)?)?)
+}
+
+// 把图片资源复制到另一个笔记的资源目录，资源名保持不变
+pub fn copy_image_asset(from_note_id: &str, to_note_id: &str, asset: &str) -> Result<(), Box<dyn std::error::Error>> {
+    let target = asset_path(to_note_id, asset)?;
+    if target.exists() {
+        return Ok(());
+    }
+    
+    fs::create_dir_all(note_assets_dir(to_note_id))?;
+    fs::copy(asset_path(from_note_id, asset)?, target)?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::note::ImageElement;
+    
+    fn temp_root() -> PathBuf {
+        let root = std::env::temp_dir().join(format!("speedynote-{}", uuid::Uuid::new_v4()));
+        fs::create_dir_all(&root).unwrap();
+        root
+    }
+    
+    #[test]
+    fn test_folders_and_moving_notes() {
+        let root = temp_root();
+        let note = Note::new("特征值".to_string());
+        save_note_in(&root, &note).unwrap();
+        fs::create_dir_all(assets_dir_for(&find_note_file(&root, &note.id).unwrap())).unwrap();
+        
+        assert_eq!(create_folder_in(&root, "", "课程").unwrap(), "课程");
+        assert_eq!(create_folder_in(&root, "课程", "线代").unwrap(), "课程/线代");
+        assert!(create_folder_in(&root, "", "课程").is_err());
+        assert!(create_folder_in(&root, "", "图片.assets").is_err());
+        assert!(create_folder_in(&root, "../外面", "x").is_err());
+        
+        // 移动后仍能按id找到，资源目录一起移动，再次保存写回新位置
+        move_note_in(&root, &note.id, "课程/线代").unwrap();
+        let moved = root.join("课程/线代").join(format!("{}.spn", note.id));
+        assert_eq!(find_note_file(&root, &note.id), Some(moved.clone()));
+        assert!(assets_dir_for(&moved).is_dir());
+        save_note_in(&root, &note).unwrap();
+        assert_eq!(note_files(&root).count(), 1);
+        
+        assert_eq!(rename_folder_in(&root, "课程/线代", "线性代数").unwrap(), "课程/线性代数");
+        assert!(move_folder_in(&root, "课程", "课程/线性代数").is_err());
+        assert_eq!(move_folder_in(&root, "课程/线性代数", "").unwrap(), "线性代数");
+        assert!(delete_folder_in(&root, "线性代数", false).is_err());
+        
+        let tree = read_folder(&root, String::new(), String::new()).unwrap();
+        assert_eq!(tree.paths(), vec!["", "线性代数", "课程"]);
+        assert_eq!(tree.folders[0].notes[0].title, "特征值");
+        assert_eq!(tree.all_notes().len(), 1);
+        
+        delete_folder_in(&root, "线性代数", true).unwrap();
+        assert_eq!(find_note_file(&root, &note.id), None);
+        assert!(delete_folder_in(&root, "", true).is_err());
+        
+        fs::remove_dir_all(root).unwrap();
+    }
+    
+    #[test]
+    fn test_note_index_and_entries() {
+        let root = temp_root();
+        let note = Note::new("索引".to_string());
+        save_note_in(&root, &note).unwrap();
+        let file_path = find_note_file(&root, &note.id).unwrap();
+        
+        // 在程序之外移动的文件，查找时发现索引过期后重新遍历
+        fs::create_dir_all(root.join("外部")).unwrap();
+        let moved = root.join("外部").join(format!("{}.spn", note.id));
+        fs::rename(&file_path, &moved).unwrap();
+        assert_eq!(find_note_file(&root, &note.id), Some(moved.clone()));
+        
+        // 列表只读取头部字段，页面内容不解析
+        fs::write(&moved, r#"{"id": "x", "title": "旧格式", "updated_at": 7, "pages": [{"不解析": true}, 1]}"#).unwrap();
+        let entry = read_note_entry(&moved).unwrap();
+        assert_eq!((entry.title.as_str(), entry.updated_at, entry.page_count), ("旧格式", 7, 2));
+        fs::write(&moved, r#"{"id": "x", "title": "新格式", "updated_at": 7, "format_version": 99}"#).unwrap();
+        assert!(read_note_entry(&moved).is_err());
+        
+        fs::remove_dir_all(root).unwrap();
+    }
+    
+    #[test]
+    fn test_asset_names() {
+        assert!(validate_asset_name("3f2a.png").is_ok());
+        assert!(validate_asset_name("").is_err());
+        assert!(validate_asset_name("../other.spn").is_err());
+        assert!(validate_asset_name("a/b.png").is_err());
+        assert!(validate_asset_name("a\\b.png").is_err());
+        assert!(validate_asset_name(".hidden").is_err());
+        assert!(prepare_image_asset(b"not an image").is_err());
+    }
+    
+    #[test]
+    fn test_remove_unused_assets() {
+        let root = temp_root();
+        let mut note = Note::new("图片".to_string());
+        note.add_element(Element::Image(ImageElement::fitted("used.png".to_string(), 0.0, 0.0, 10, 10, 100.0)));
+        fs::write(root.join("used.png"), b"png").unwrap();
+        fs::write(root.join("undone.png"), b"png").unwrap();
+        
+        assert_eq!(remove_unused_assets_in(&root, &note).unwrap(), 1);
+        assert!(root.join("used.png").exists());
+        assert!(!root.join("undone.png").exists());
+        assert_eq!(remove_unused_assets_in(&root.join("missing"), &note).unwrap(), 0);
+        
+        fs::remove_dir_all(root).unwrap();
+    }
+}
@@ -0,0 +1,266 @@
+// 纸张模板的几何：背景展开成线段和圆点，屏幕绘制和PDF导出使用同一份结果，保证两边一致
+use crate::geometry::{Rect, Vec2};
+use crate::note::{Background, Color, TemplateStyle};
+
+// 康奈尔笔记线索栏的宽度和总结区的高度占纸张的比例
+const CORNELL_CUE_RATIO: f32 = 0.3;
+const CORNELL_SUMMARY_RATIO: f32 = 0.2;
+// 分隔线相对于普通线的粗细
+const DIVIDER_WIDTH_SCALE: f32 = 2.0;
+// 五线谱每行的谱线数
+const STAFF_LINES: usize = 5;
+// 间距的下限，避免过密的模板生成大量线条
+const MIN_SPACING: f32 = 2.0;
+
+// 模板中的一个图形（画布坐标）
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Mark {
+    Line { start: Vec2, end: Vec2, width: f32, color: Color },
+    Dot { center: Vec2, radius: f32, color: Color },
+}
+
+// paper为纸张的范围，线条以它为基准排列；visible为需要画出的区域，
+// 固定页面两者相同，无限画布上是可见或导出的区域。返回的图形都在visible以内
+pub fn marks(background: &Background, paper: Rect, visible: Rect) -> Vec<Mark> {
+    let style = match background.style() {
+        Some(style) => *style,
+        None => return Vec::new(),
+    };
+    let mut marks = Vec::new();
+
+    match *background {
+        Background::Blank | Background::Pdf { .. } => {}
+        Background::Lined { spacing, .. } => {
+            horizontal_lines(&mut marks, &style, paper, visible, spacing, visible.max.y);
+        }
+        Background::Grid { spacing, .. } => {
+            horizontal_lines(&mut marks, &style, paper, visible, spacing, visible.max.y);
+            for x in steps(paper.min.x, visible.min.x, visible.max.x, spacing) {
+                marks.push(line(&style, Vec2::new(x, visible.min.y), Vec2::new(x, visible.max.y)));
+            }
+        }
+        Background::Dotted { spacing, .. } => {
+            for y in steps(paper.min.y, visible.min.y, visible.max.y, spacing) {
+                for x in steps(paper.min.x, visible.min.x, visible.max.x, spacing) {
+                    marks.push(Mark::Dot { center: Vec2::new(x, y), radius: style.line_width, color: style.line_color });
+                }
+            }
+        }
+        Background::LinedMargin { spacing, margin, margin_color, .. } => {
+            horizontal_lines(&mut marks, &style, paper, visible, spacing, visible.max.y);
+            let x = paper.min.x + margin;
+            if x > visible.min.x && x < visible.max.x {
+                marks.push(Mark::Line {
+                    start: Vec2::new(x, visible.min.y),
+                    end: Vec2::new(x, visible.max.y),
+                    width: style.line_width,
+                    color: margin_color,
+                });
+            }
+        }
+        Background::Cornell { spacing, .. } => {
+            let cue_x = paper.min.x + paper.width() * CORNELL_CUE_RATIO;
+            let summary_y = paper.max.y - paper.height() * CORNELL_SUMMARY_RATIO;
+            // 总结区不画横线
+            horizontal_lines(&mut marks, &style, paper, visible, spacing, summary_y.min(visible.max.y));
+
+            let divider = |start: Vec2, end: Vec2| Mark::Line {
+                start,
+                end,
+                width: style.line_width * DIVIDER_WIDTH_SCALE,
+                color: style.line_color,
+            };
+            if cue_x > visible.min.x && cue_x < visible.max.x && summary_y > visible.min.y {
+                let end = summary_y.min(visible.max.y);
+                marks.push(divider(Vec2::new(cue_x, visible.min.y), Vec2::new(cue_x, end)));
+            }
+            if summary_y > visible.min.y && summary_y < visible.max.y {
+                marks.push(divider(Vec2::new(visible.min.x, summary_y), Vec2::new(visible.max.x, summary_y)));
+            }
+        }
+        Background::MusicStaff { spacing, staff_gap, .. } => {
+            let spacing = spacing.max(MIN_SPACING);
+            // 每行谱的第一条线距上一行谱（或纸张顶部）staff_gap
+            let period = spacing * (STAFF_LINES - 1) as f32 + staff_gap.max(MIN_SPACING);
+            let first_staff = ((visible.min.y - paper.min.y - staff_gap) / period).floor() as i64;
+            for staff in first_staff.. {
+                let top = paper.min.y + staff_gap + staff as f32 * period;
+                if top >= visible.max.y {
+                    break;
+                }
+                for i in 0..STAFF_LINES {
+                    let y = top + i as f32 * spacing;
+                    if y > visible.min.y && y < visible.max.y {
+                        marks.push(line(&style, Vec2::new(visible.min.x, y), Vec2::new(visible.max.x, y)));
+                    }
+                }
+            }
+        }
+        Background::Isometric { spacing, .. } => {
+            let spacing = spacing.max(MIN_SPACING);
+            // 竖线间距为三角形的高，斜线在竖直方向上相隔spacing
+            let column = spacing * 3f32.sqrt() / 2.0;
+            for x in steps(paper.min.x, visible.min.x, visible.max.x, column) {
+                marks.push(line(&style, Vec2::new(x, visible.min.y), Vec2::new(x, visible.max.y)));
+            }
+
+            let slope = 1.0 / 3f32.sqrt();
+            let rise = (visible.max.x - visible.min.x) * slope;
+            let offset = (visible.min.x - paper.min.x) * slope;
+            for direction in [Vec2::new(1.0, slope), Vec2::new(1.0, -slope)] {
+                // 斜线在visible左边缘处的高度覆盖visible，上升的线还要往上多取一段
+                let shift = if direction.y > 0.0 { offset } else { -offset };
+                let (low, high) = if direction.y > 0.0 { (-rise, 0.0) } else { (0.0, rise) };
+                let from = visible.min.y - paper.min.y - shift + low;
+                let to = visible.max.y - paper.min.y - shift + high;
+                for k in (from / spacing).ceil() as i64..=(to / spacing).floor() as i64 {
+                    let start = Vec2::new(visible.min.x, paper.min.y + shift + k as f32 * spacing);
+                    if let Some((start, end)) = clip_line(&visible, start, direction) {
+                        marks.push(line(&style, start, end));
+                    }
+                }
+            }
+        }
+    }
+    marks
+}
+
+fn line(style: &TemplateStyle, start: Vec2, end: Vec2) -> Mark {
+    Mark::Line { start, end, width: style.line_width, color: style.line_color }
+}
+
+// 从纸张顶部起每隔spacing一条横线，画到bottom为止
+fn horizontal_lines(marks: &mut Vec<Mark>, style: &TemplateStyle, paper: Rect, visible: Rect, spacing: f32, bottom: f32) {
+    for y in steps(paper.min.y, visible.min.y, bottom, spacing) {
+        marks.push(line(style, Vec2::new(visible.min.x, y), Vec2::new(visible.max.x, y)));
+    }
+}
+
+// anchor + n * spacing中落在(from, to)内的值，不含两端，纸张边缘本身不画线
+fn steps(anchor: f32, from: f32, to: f32, spacing: f32) -> impl Iterator<Item = f32> {
+    let spacing = spacing.max(MIN_SPACING);
+    let first = ((from - anchor) / spacing).floor() as i64;
+    (first..)
+        .map(move |n| anchor + n as f32 * spacing)
+        .skip_while(move |value| *value <= from)
+        .take_while(move |value| *value < to)
+}
+
+// 按颜色和粗细把连续的图形分组，同一组可以放在一条路径里绘制（PDF导出时整页横线只需一次描边）
+pub fn paint_groups(marks: &[Mark]) -> impl Iterator<Item = &[Mark]> {
+    marks.chunk_by(same_paint)
+}
+
+fn same_paint(a: &Mark, b: &Mark) -> bool {
+    match (a, b) {
+        (Mark::Line { width: w1, color: c1, .. }, Mark::Line { width: w2, color: c2, .. }) => w1 == w2 && c1 == c2,
+        (Mark::Dot { color: c1, .. }, Mark::Dot { color: c2, .. }) => c1 == c2,
+        _ => false,
+    }
+}
+
+// 过point、方向为direction的直线被矩形截出的线段
+fn clip_line(rect: &Rect, point: Vec2, direction: Vec2) -> Option<(Vec2, Vec2)> {
+    let mut range = (f32::NEG_INFINITY, f32::INFINITY);
+    for (p, d, min, max) in [
+        (point.x, direction.x, rect.min.x, rect.max.x),
+        (point.y, direction.y, rect.min.y, rect.max.y),
+    ] {
+        if d == 0.0 {
+            if p < min || p > max {
+                return None;
+            }
+            continue;
+        }
+        let (t0, t1) = ((min - p) / d, (max - p) / d);
+        range = (range.0.max(t0.min(t1)), range.1.min(t0.max(t1)));
+    }
+    if range.0 >= range.1 {
+        return None;
+    }
+    Some((point + direction * range.0, point + direction * range.1))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn page() -> Rect {
+        Rect::from_min_size(Vec2::new(0.0, 0.0), 100.0, 100.0)
+    }
+
+    fn lines(marks: &[Mark]) -> Vec<(Vec2, Vec2)> {
+        marks.iter()
+            .filter_map(|mark| match mark {
+                Mark::Line { start, end, .. } => Some((*start, *end)),
+                Mark::Dot { .. } => None,
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_lined_and_margin() {
+        let style = TemplateStyle::default();
+        let lined = marks(&Background::Lined { spacing: 30.0, style }, page(), page());
+        let ys: Vec<f32> = lines(&lined).iter().map(|(start, _)| start.y).collect();
+        assert_eq!(ys, vec![30.0, 60.0, 90.0]);
+
+        let margin = Background::LinedMargin { spacing: 30.0, margin: 20.0, margin_color: Color::rgb(255, 0, 0), style };
+        let marks = marks(&margin, page(), page());
+        assert_eq!(marks.len(), 4);
+        assert!(matches!(marks[3], Mark::Line { start, color, .. } if start.x == 20.0 && color == Color::rgb(255, 0, 0)));
+    }
+
+    #[test]
+    fn test_paint_groups_merge_ruling() {
+        let style = TemplateStyle::default();
+        // 横线和竖线颜色粗细相同，整页只有一组
+        let lined = marks(&Background::Lined { spacing: 30.0, style }, page(), page());
+        let groups: Vec<usize> = paint_groups(&lined).map(|group| group.len()).collect();
+        assert_eq!(groups, vec![3]);
+        let grid = marks(&Background::Grid { spacing: 30.0, style }, page(), page());
+        let groups: Vec<usize> = paint_groups(&grid).map(|group| group.len()).collect();
+        assert_eq!(groups, vec![6]);
+
+        // 页边线颜色不同，单独一组
+        let margin = Background::LinedMargin { spacing: 30.0, margin: 20.0, margin_color: Color::rgb(255, 0, 0), style };
+        let margin = marks(&margin, page(), page());
+        let groups: Vec<usize> = paint_groups(&margin).map(|group| group.len()).collect();
+        assert_eq!(groups, vec![3, 1]);
+    }
+
+    #[test]
+    fn test_infinite_canvas_follows_paper() {
+        // 平移后的可见区域里，线条仍然对齐到纸张的间距
+        let style = TemplateStyle::default();
+        let visible = Rect { min: Vec2::new(-45.0, 215.0), max: Vec2::new(55.0, 285.0) };
+        let dots = marks(&Background::Dotted { spacing: 20.0, style }, page(), visible);
+        assert_eq!(dots.len(), 5 * 4);
+        for mark in dots {
+            let Mark::Dot { center, .. } = mark else { panic!("expected dots") };
+            assert!(visible.contains(center));
+            assert_eq!((center.x % 20.0, center.y % 20.0), (0.0, 0.0));
+        }
+
+        let staff = marks(&Background::MusicStaff { spacing: 10.0, staff_gap: 30.0, style }, page(), visible);
+        let ys: Vec<f32> = lines(&staff).iter().map(|(start, _)| start.y).collect();
+        // 每行谱占70：第一行从30开始，第四行从240开始
+        assert_eq!(ys, vec![240.0, 250.0, 260.0, 270.0, 280.0]);
+    }
+
+    #[test]
+    fn test_cornell_and_isometric_stay_inside() {
+        let style = TemplateStyle::default();
+        let cornell = lines(&marks(&Background::Cornell { spacing: 30.0, style }, page(), page()));
+        // 横线只画到总结区（y=80）之上，另有线索栏和总结区的分隔线
+        assert_eq!(cornell.len(), 2 + 2);
+        let cue = cornell.iter().find(|(start, end)| start.x == end.x).unwrap();
+        assert!((cue.0.x - 30.0).abs() < 1e-3 && cue.0.y == 0.0 && (cue.1.y - 80.0).abs() < 1e-3);
+
+        let isometric = lines(&marks(&Background::Isometric { spacing: 20.0, style }, page(), page()));
+        let bounds = page().expand(1e-3);
+        assert!(isometric.iter().all(|(start, end)| bounds.contains(*start) && bounds.contains(*end)));
+        let diagonals = isometric.iter().filter(|(start, end)| start.x != end.x).count();
+        assert!(diagonals > 10);
+    }
+}
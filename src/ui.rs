@@ -1,26 +1,207 @@
 use eframe::egui;
-use crate::note::{Note, Stroke, Point, Background};
+use std::collections::HashMap;
+use crate::markdown::{self, BlockKind};
+use crate::clipboard::{self, Fragment};
+use crate::geometry::{Rect, Transform, Vec2};
+use crate::note::{self, Color, LinkTarget, Note, NoteMetadata, OutlineEntry, Page, Stroke, Point, Background, EraserMode, Element, TextBox, ImageElement, ShapeElement, ShapeKind, StrokeTool, PageFormat, PageSize, Orientation, PageKind, TemplateStyle, MM_PER_INCH, TEXT_BOX_PADDING};
+use crate::layer_composite::{self, CompositeKey, LayerCompositor};
+use crate::outline::{self, PENCIL_GRAIN_WIDTH};
+use crate::pdf_render::{PageTexture, PdfPageCache};
+use crate::recognizer;
+use crate::selection::{self, Handle, Selection};
+use crate::smoothing::{self, SmoothingSettings};
+use crate::storage;
+use crate::template::{self, Mark};
+
+// 便签默认底色
+const STICKY_NOTE_COLOR: Color = Color::rgb(0xFF, 0xF5, 0x9D);
+// 选框手柄的点击半径
+const HANDLE_RADIUS: f32 = 8.0;
+// 复制到剪贴板的图片最长边的像素数
+const MAX_CLIPBOARD_IMAGE_SIZE: f32 = 4096.0;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Tool {
+    Pen,
+    StrokeEraser,
+    PreciseEraser,
+    Text,
+    Lasso,
+    RectSelect,
+}
+
+// 正在原地编辑的文本框
+struct TextEditState {
+    page_index: usize,
+    draft: TextBox,
+    // 新建的文本框在提交时才加入笔记
+    is_new: bool,
+    focus_requested: bool,
+}
+
+// 选择工具的拖动手势
+enum SelectionDrag {
+    // 正在圈选，记录经过的点
+    Select(Vec<Vec2>),
+    // 拖动选框或手柄；preview为当前的变换及应用后的页面，松开时才写入笔记
+    Transform {
+        handle: Handle,
+        bounds: Rect,
+        start: Vec2,
+        preview: Option<(Transform, Page)>,
+    },
+}
 
 pub struct SpeedyNoteApp {
     notes: Vec<Note>,
     current_note: Option<Note>,
+    // 侧边栏只显示带有该标签的笔记
+    tag_filter: Option<String>,
+    // notes目录中的文件夹树，修改后置为None在下一帧重新读取
+    folder_tree: Option<storage::Folder>,
+    // 正在重命名的文件夹（路径, 输入的新名字）
+    renaming_folder: Option<(String, String)>,
+    // 正在重命名的大纲条目（位置, 输入的标题）
+    renaming_outline: Option<(Vec<usize>, String)>,
+    // 已保存的笔记中指向当前笔记的链接（笔记id, 链接），读取一次后缓存
+    stored_backlinks: Option<(String, Vec<note::Backlink>)>,
+    // 选中内容的链接菜单：是否添加为链接区域，以及输入中的网址
+    link_as_region: bool,
+    link_url_draft: String,
     drawing: bool,
+    // 落笔的触点（设备, 触点id），这一笔只采用它的触摸事件
+    stroke_touch: Option<(egui::TouchDeviceId, egui::TouchId)>,
     current_stroke: Option<Stroke>,
-    brush_color: String,
+    // 落笔后停住识别出的图形，松开时代替笔画加入笔记
+    recognized_shape: Option<ShapeKind>,
+    brush_color: Color,
     brush_thickness: f32,
+    stroke_tool: StrokeTool,
+    smoothing: SmoothingSettings,
     show_dial: bool,
+    tool: Tool,
+    eraser_radius: f32,
+    eraser_path: Vec<Point>,
+    // 正在拖动的图层不透明度（图层索引, 预览值），松开后才提交
+    pending_opacity: Option<(usize, f32)>,
+    editing_text: Option<TextEditState>,
+    selection: Option<Selection>,
+    selection_drag: Option<SelectionDrag>,
+    text_font_size: f32,
+    text_sticky: bool,
+    // 已上传的图片纹理，键为"笔记id/资源名"；加载失败记为None避免每帧重试
+    image_textures: HashMap<String, Option<egui::TextureHandle>>,
+    // PDF背景页的纹理，在后台线程中渲染
+    pdf_pages: PdfPageCache,
+    // 半透明图层合成后的纹理
+    layer_composites: LayerCompositor,
+    // 页面尺寸输入框的单位是否为英寸，以及是否刚在规格列表中选了自定义（尺寸可能恰好等于预设）
+    custom_size_inches: bool,
+    custom_size_chosen: bool,
+    // 正在编辑的自定义页面尺寸（页面索引，尺寸），提交前只用于显示
+    pending_page_size: Option<(usize, PageSize)>,
+    // 正在编辑的纸张模板样式（页面索引，样式）
+    pending_template_style: Option<(usize, TemplateStyle)>,
+    // 上一帧画布在屏幕上的可见区域及页面坐标原点的位置，用于把拖放位置换算成页面坐标
+    canvas_rect: Option<egui::Rect>,
+    canvas_origin: egui::Pos2,
+    // 无限画布的平移量（视口左上角对应的页面坐标），换页时归零
+    canvas_pan: egui::Vec2,
+    panned_page: Option<usize>,
+}
+
+// 图层面板上的操作，收集后统一应用到笔记
+enum LayerAction {
+    Select(usize),
+    SetVisible(usize, bool),
+    SetLocked(usize, bool),
+    SetOpacity(usize, f32),
+    Move(usize, usize),
+    Remove(usize),
+    Add,
+}
+
+// 侧边栏页面列表上的操作
+enum PageAction {
+    Select(usize),
+    Move(usize, usize),
+    Insert(usize),
+    Duplicate(usize),
+    Bookmark(usize),
+    Remove(usize),
+}
+
+// 侧边栏中拖动的页面
+struct DraggedPage(usize);
+
+// 大纲面板上的操作，条目用在大纲中的位置表示
+enum OutlineAction {
+    Go(usize),
+    Bookmark(usize),
+    StartRename(Vec<usize>, String),
+    Rename(Vec<usize>, String),
+    Indent(Vec<usize>),
+    Outdent(Vec<usize>),
+    Move(Vec<usize>, bool),
+    Remove(Vec<usize>),
+}
+
+// 侧边栏文件夹树上的操作，文件夹用路径表示
+enum FolderAction {
+    OpenNote(String),
+    Create(String),
+    StartRename(String, String),
+    Rename(String, String),
+    Delete(String),
+    MoveNote(String, String),
+    MoveFolder(String, String),
 }
 
+// 侧边栏中拖动的笔记（id）和文件夹（路径）
+struct DraggedNote(String);
+struct DraggedFolder(String);
+
 impl Default for SpeedyNoteApp {
     fn default() -> Self {
         Self {
             notes: Vec::new(),
             current_note: None,
+            tag_filter: None,
+            folder_tree: None,
+            renaming_folder: None,
+            renaming_outline: None,
+            stored_backlinks: None,
+            link_as_region: false,
+            link_url_draft: String::new(),
             drawing: false,
+            stroke_touch: None,
             current_stroke: None,
-            brush_color: "#000000".to_string(),
+            recognized_shape: None,
+            brush_color: Color::BLACK,
             brush_thickness: 2.0,
+            stroke_tool: StrokeTool::Pen,
+            smoothing: SmoothingSettings::default(),
             show_dial: false,
+            tool: Tool::Pen,
+            eraser_radius: 8.0,
+            eraser_path: Vec::new(),
+            pending_opacity: None,
+            editing_text: None,
+            selection: None,
+            selection_drag: None,
+            text_font_size: 16.0,
+            text_sticky: false,
+            image_textures: HashMap::new(),
+            pdf_pages: PdfPageCache::default(),
+            layer_composites: LayerCompositor::default(),
+            custom_size_inches: false,
+            custom_size_chosen: false,
+            pending_page_size: None,
+            pending_template_style: None,
+            canvas_rect: None,
+            canvas_origin: egui::Pos2::ZERO,
+            canvas_pan: egui::Vec2::ZERO,
+            panned_page: None,
         }
     }
 }
@@ -30,6 +211,25 @@ impl eframe::App for SpeedyNoteApp {
         // 设置窗口模糊背景
         self.setup_blur_background(ctx);
         
+        // 快捷键
+        self.handle_shortcuts(ctx);
+        
+        // 拖放到窗口中的图片
+        self.handle_dropped_files(ctx);
+        
+        // 上传后台渲染好的PDF页面
+        self.pdf_pages.update(ctx);
+        self.layer_composites.update();
+        
+        // 图层和大纲面板（侧边面板需要在中央面板之前添加）
+        egui::SidePanel::right("layers").show(ctx, |ui| {
+            self.render_layer_panel(ui);
+            ui.separator();
+            self.render_outline_panel(ui);
+            ui.separator();
+            self.render_backlinks_panel(ui);
+        });
+        
         // 主界面布局
         egui::CentralPanel::default().show(ctx, |ui| {
             self.render_main_interface(ui);
@@ -68,30 +268,150 @@ impl SpeedyNoteApp {
         ctx.set_visuals(visuals);
     }
     
-    fn render_main_interface(&mut self, ui: &mut egui::Ui) {
+    fn handle_shortcuts(&mut self, ctx: &egui::Context) {
+        let redo_shortcut = egui::KeyboardShortcut::new(egui::Modifiers::COMMAND, egui::Key::Y);
+        let redo_shift_shortcut = egui::KeyboardShortcut::new(
+            egui::Modifiers::COMMAND | egui::Modifiers::SHIFT,
+            egui::Key::Z
+        );
+        let undo_shortcut = egui::KeyboardShortcut::new(egui::Modifiers::COMMAND, egui::Key::Z);
+        
+        // 先检查带Shift的组合，避免被Ctrl+Z抢先匹配
+        let redo = ctx.input_mut(|i| {
+            i.consume_shortcut(&redo_shift_shortcut) || i.consume_shortcut(&redo_shortcut)
+        });
+        let undo = ctx.input_mut(|i| i.consume_shortcut(&undo_shortcut));
+        let delete = self.editing_text.is_none() && ctx.input(|i| i.key_pressed(egui::Key::Delete));
+        
+        // 复制/剪切/粘贴由平台集成转换成事件；编辑文本框时交给文本框处理
+        // 剪贴板里只有图片时平台集成不会产生粘贴事件，按Ctrl+V时再读取图片
+        if self.editing_text.is_none() {
+            let events = ctx.input(|i| i.events.clone());
+            let mut paste_key = false;
+            let mut pasted = false;
+            for event in events {
+                match event {
+                    egui::Event::Copy => self.copy_selection(ctx, false),
+                    egui::Event::Cut => self.copy_selection(ctx, true),
+                    egui::Event::Paste(text) => pasted |= self.paste_from_clipboard(ctx, &text),
+                    egui::Event::Key { key: egui::Key::V, pressed: true, modifiers, .. } if modifiers.command => paste_key = true,
+                    _ => {}
+                }
+            }
+            if paste_key && !pasted {
+                self.paste_image_from_clipboard(ctx);
+            }
+        }
+        
         if let Some(note) = &mut self.current_note {
-            if let Some(page) = note.pages.get_mut(note.current_page) {
-                // 创建绘图区域
-                let (rect, response) = ui.allocate_exact_size(
-                    egui::Vec2::new(page.width, page.height),
-                    egui::Sense::drag()
-                );
+            // 撤销/重做后选择中的索引可能失效
+            if redo {
+                note.redo();
+                self.selection = None;
+            } else if undo {
+                note.undo();
+                self.selection = None;
+            } else if delete {
+                if let Some(selection) = self.selection.take() {
+                    let _ = note.remove_items(selection.page_index, &selection.items);
+                }
+            }
+        }
+    }
+    
+    fn render_main_interface(&mut self, ui: &mut egui::Ui) {
+        if let Some(mut note) = self.current_note.take() {
+            // 点击的链接在笔记放回后再跳转
+            let mut follow = None;
+            if let Some(page) = note.pages.get(note.current_page) {
+                // 创建绘图区域；rect为页面（纸张）在屏幕上的位置，viewport为实际可见的区域
+                let page_size = egui::Vec2::new(page.width(), page.height());
+                let (rect, response, viewport) = if page.is_infinite() {
+                    // 无限画布占满可用区域，可以向任意方向平移
+                    let (viewport, response) = ui.allocate_exact_size(ui.available_size(), egui::Sense::click_and_drag());
+                    self.pan_canvas(ui, &response, note.current_page);
+                    (egui::Rect::from_min_size(viewport.min - self.canvas_pan, page_size), response, viewport)
+                } else {
+                    let (rect, response) = ui.allocate_exact_size(page_size, egui::Sense::click_and_drag());
+                    (rect, response, rect)
+                };
+                self.canvas_rect = Some(viewport);
+                self.canvas_origin = rect.min;
+                let previous_clip = ui.clip_rect();
+                ui.set_clip_rect(viewport.intersect(previous_clip));
                 
                 // 绘制背景
-                self.draw_background(ui, &page.background, rect);
+                self.draw_background(ui, &page.background, rect, viewport);
+                
+                // 绘制前先准备好本页图片的纹理
+                self.load_image_textures(ui.ctx(), &note.id, page);
+                
+                // 按图层顺序绘制已有笔画，拖动选中内容时绘制变换后的预览
+                self.validate_selection(note.current_page);
+                let selection_drag = self.selection_drag.take();
+                let (display_page, revision) = match &selection_drag {
+                    Some(SelectionDrag::Transform { preview: Some((_, preview)), .. }) => (preview, None),
+                    _ => (page, Some(note.revision)),
+                };
+                self.draw_layers(ui, display_page, &note.id, rect, revision);
+                self.selection_drag = selection_drag;
+                self.draw_links(ui, page, rect);
+                let active_opacity = page.active_layer().map_or(1.0, |layer| layer.opacity);
+                
+                // 按住Ctrl（macOS上为Cmd）点击链接时跳转，不交给当前工具处理
+                let following = ui.input(|i| i.modifiers.command);
+                if following {
+                    let link = response.hover_pos()
+                        .and_then(|pos| page.link_at(Vec2::new(pos.x - rect.left(), pos.y - rect.top())))
+                        .cloned();
+                    if link.is_some() {
+                        ui.ctx().set_cursor_icon(egui::CursorIcon::PointingHand);
+                    }
+                    if response.clicked() {
+                        follow = link;
+                    }
+                }
                 
-                // 绘制已有笔画
-                self.draw_existing_strokes(ui, &page.strokes, rect);
+                // 处理绘图输入，中键拖动只用于平移
+                let panning = response.dragged_by(egui::PointerButton::Middle);
+                match self.tool {
+                    _ if panning || following => {}
+                    Tool::Pen => self.handle_drawing_input(&response, rect, &mut note),
+                    Tool::StrokeEraser => self.handle_eraser_input(&response, rect, &mut note, EraserMode::Stroke),
+                    Tool::PreciseEraser => self.handle_eraser_input(&response, rect, &mut note, EraserMode::Precise),
+                    Tool::Text => self.handle_text_input(&response, rect, &mut note),
+                    Tool::Lasso => self.handle_selection_input(&response, rect, &mut note, true),
+                    Tool::RectSelect => self.handle_selection_input(&response, rect, &mut note, false),
+                }
+                
+                // 选框和手柄
+                self.draw_selection(ui, rect, &note);
                 
-                // 处理绘图输入
-                self.handle_drawing_input(&response, rect, page);
+                // 文本框编辑器
+                self.render_text_editor(ui, rect, &mut note);
                 
-                // 绘制当前笔画
+                // 绘制当前笔画，已识别为图形时预览图形
                 if let Some(stroke) = &self.current_stroke {
-                    self.draw_stroke(ui, stroke, rect);
+                    match &self.recognized_shape {
+                        Some(kind) => {
+                            let preview = ShapeElement::new(kind.clone(), stroke.color, stroke.thickness);
+                            self.draw_shape(ui, &preview, rect, active_opacity);
+                        }
+                        None => ui.painter().extend(Self::live_stroke_shapes(stroke, rect, active_opacity)),
+                    }
                 }
+                
+                // 绘制橡皮擦轨迹
+                self.draw_eraser_path(ui, rect);
+                ui.set_clip_rect(previous_clip);
+            }
+            self.current_note = Some(note);
+            if let Some(target) = follow {
+                self.follow_link(ui.ctx(), &target);
             }
         } else {
+            self.editing_text = None;
+            
             // 欢迎界面
             ui.vertical_centered(|ui| {
                 ui.heading("欢迎使用 SpeedyNote");
@@ -105,227 +425,1881 @@ impl SpeedyNoteApp {
     }
     
     fn render_sidebar(&mut self, ui: &mut egui::Ui) {
-        ui.heading("笔记列表");
+        let tree = match self.folder_tree.take() {
+            Some(tree) => tree,
+            None => storage::folder_tree().unwrap_or_else(|e| {
+                log::warn!("读取文件夹失败: {}", e);
+                storage::Folder::default()
+            }),
+        };
+        let saved = tree.all_notes();
+        let mut actions = Vec::new();
+        
+        // 拖到标题上移到根目录
+        let heading = ui.heading("笔记列表");
+        Self::folder_drop_target(ui, &heading, "", &mut actions);
         
+        let metadata = self.notes.iter()
+            .map(|note| &note.metadata)
+            .chain(saved.iter().map(|entry| &entry.metadata));
+        let tags = note::collect_tags(metadata);
+        if self.tag_filter.as_ref().is_some_and(|tag| !tags.contains(tag)) {
+            self.tag_filter = None;
+        }
+        if !tags.is_empty() {
+            egui::ComboBox::from_id_source("tag_filter")
+                .selected_text(self.tag_filter.as_deref().unwrap_or("全部标签"))
+                .show_ui(ui, |ui| {
+                    ui.selectable_value(&mut self.tag_filter, None, "全部标签");
+                    for tag in tags {
+                        ui.selectable_value(&mut self.tag_filter, Some(tag.clone()), tag);
+                    }
+                });
+        }
+        
+        self.render_folder(ui, &tree, &mut actions);
+        
+        // 还没有保存到notes目录的笔记显示在最后
         for note in &self.notes {
-            if ui.selectable_label(
-                self.current_note.as_ref().map(|n| n.id.as_str()) == Some(&note.id),
-                &note.title
-            ).clicked() {
-                self.current_note = Some(note.clone());
+            if saved.iter().any(|entry| entry.id == note.id) || !self.matches_tag_filter(&note.metadata) {
+                continue;
+            }
+            if self.note_label(ui, &note.id, &note.title, note.metadata.label).clicked() {
+                actions.push(FolderAction::OpenNote(note.id.clone()));
             }
         }
         
         ui.separator();
         
-        if ui.button("新建笔记").clicked() {
-            self.create_new_note();
+        ui.horizontal(|ui| {
+            if ui.button("新建笔记").clicked() {
+                self.create_new_note();
+            }
+            if ui.button("新建文件夹").clicked() {
+                actions.push(FolderAction::Create(String::new()));
+            }
+        });
+        
+        let mut reload = false;
+        for action in actions {
+            let result = match action {
+                FolderAction::OpenNote(note_id) => {
+                    self.open_note(&note_id);
+                    Ok(())
+                }
+                FolderAction::StartRename(path, name) => {
+                    self.renaming_folder = Some((path, name));
+                    Ok(())
+                }
+                FolderAction::Create(parent) => {
+                    reload = true;
+                    self.create_folder(&tree, &parent)
+                }
+                FolderAction::Rename(path, name) => {
+                    reload = true;
+                    storage::rename_folder(&path, &name).map(|_| ())
+                }
+                FolderAction::Delete(path) => {
+                    reload = true;
+                    storage::delete_folder(&path, false)
+                }
+                FolderAction::MoveNote(note_id, folder) => {
+                    reload = true;
+                    storage::move_note(&note_id, &folder)
+                }
+                FolderAction::MoveFolder(path, parent) => {
+                    reload = true;
+                    storage::move_folder(&path, &parent).map(|_| ())
+                }
+            };
+            if let Err(e) = result {
+                log::warn!("文件夹操作失败: {}", e);
+            }
         }
+        if !reload {
+            self.folder_tree = Some(tree);
+        }
+        
+        ui.separator();
+        self.render_page_list(ui);
     }
     
-    fn render_toolbar(&mut self, ui: &mut egui::Ui) {
-        ui.horizontal(|ui| {
-            // 画笔设置
-            ui.color_edit_button_srgba(&mut self.brush_color.parse().unwrap_or(egui::Color32::BLACK));
-            ui.add(egui::Slider::new(&mut self.brush_thickness, 1.0..=10.0).text("粗细"));
+    // 文件夹和笔记可以拖到其他文件夹上移动，文件夹的右键菜单新建子文件夹、重命名或删除
+    fn render_folder(&mut self, ui: &mut egui::Ui, folder: &storage::Folder, actions: &mut Vec<FolderAction>) {
+        for child in &folder.folders {
+            if let Some((path, name)) = &mut self.renaming_folder {
+                if *path == child.path {
+                    let response = ui.text_edit_singleline(name);
+                    if response.lost_focus() {
+                        // Esc取消，回车或点击别处确认
+                        if !ui.input(|i| i.key_pressed(egui::Key::Escape)) {
+                            actions.push(FolderAction::Rename(child.path.clone(), name.clone()));
+                        }
+                        self.renaming_folder = None;
+                    } else {
+                        response.request_focus();
+                    }
+                    continue;
+                }
+            }
             
-            ui.separator();
+            let header = egui::CollapsingHeader::new(&child.name)
+                .id_source(("folder", &child.path))
+                .show(ui, |ui| self.render_folder(ui, child, actions));
+            let response = header.header_response.interact(egui::Sense::click_and_drag());
+            response.dnd_set_drag_payload(DraggedFolder(child.path.clone()));
+            Self::folder_drop_target(ui, &response, &child.path, actions);
             
-            // 页面控制
-            if let Some(note) = &mut self.current_note {
-                if ui.button("上一页").clicked() && note.current_page > 0 {
-                    note.current_page -= 1;
+            response.context_menu(|ui| {
+                if ui.button("新建子文件夹").clicked() {
+                    actions.push(FolderAction::Create(child.path.clone()));
+                    ui.close_menu();
                 }
-                
-                ui.label(format!("第 {} 页 / 共 {} 页", note.current_page + 1, note.pages.len()));
-                
-                if ui.button("下一页").clicked() && note.current_page < note.pages.len() - 1 {
-                    note.current_page += 1;
+                if ui.button("重命名").clicked() {
+                    actions.push(FolderAction::StartRename(child.path.clone(), child.name.clone()));
+                    ui.close_menu();
                 }
-                
-                if ui.button("新增页面").clicked() {
-                    note.add_page(Background::Blank);
+                // 只能删除空文件夹，避免误删其中的笔记
+                let empty = child.folders.is_empty() && child.notes.is_empty();
+                if ui.add_enabled(empty, egui::Button::new("删除文件夹")).clicked() {
+                    actions.push(FolderAction::Delete(child.path.clone()));
+                    ui.close_menu();
                 }
+            });
+        }
+        
+        for entry in &folder.notes {
+            if !self.matches_tag_filter(&entry.metadata) {
+                continue;
             }
-            
-            ui.separator();
-            
-            // Dial控制
-            if ui.button("Dial").clicked() {
-                self.show_dial = !self.show_dial;
+            let response = self.note_label(ui, &entry.id, &entry.title, entry.metadata.label);
+            response.dnd_set_drag_payload(DraggedNote(entry.id.clone()));
+            if response.clicked() {
+                actions.push(FolderAction::OpenNote(entry.id.clone()));
             }
-        });
+        }
     }
     
-    fn render_dial_ui(&mut self, ctx: &egui::Context) {
-        egui::Window::new("Magic Dial")
-            .collapsible(false)
-            .resizable(false)
-            .show(ctx, |ui| {
-                ui.vertical_centered(|ui| {
-                    ui.heading("Magic Dial");
-                    
-                    // 模拟拨盘控制
-                    ui.horizontal(|ui| {
-                        if ui.button("←").clicked() {
-                            // 上一页或减小笔刷
-                        }
-                        
-                        ui.label("○");
-                        
-                        if ui.button("→").clicked() {
-                            // 下一页或增加笔刷
-                        }
-                    });
-                    
-                    ui.label("旋转控制: 缩放/翻页/笔刷大小");
-                });
-            });
+    // 把拖动的笔记或文件夹放进folder
+    fn folder_drop_target(ui: &egui::Ui, response: &egui::Response, folder: &str, actions: &mut Vec<FolderAction>) {
+        if response.dnd_hover_payload::<DraggedNote>().is_some() || response.dnd_hover_payload::<DraggedFolder>().is_some() {
+            ui.painter().rect_stroke(response.rect, 2.0, (2.0, ui.visuals().selection.stroke.color));
+        }
+        if let Some(note) = response.dnd_release_payload::<DraggedNote>() {
+            actions.push(FolderAction::MoveNote(note.0.clone(), folder.to_string()));
+        }
+        if let Some(dragged) = response.dnd_release_payload::<DraggedFolder>() {
+            if dragged.0 != folder {
+                actions.push(FolderAction::MoveFolder(dragged.0.clone(), folder.to_string()));
+            }
+        }
     }
     
-    fn draw_background(&self, ui: &mut egui::Ui, background: &Background, rect: egui::Rect) {
-        match background {
-            Background::Blank => {
-                // 空白背景 - 轻微模糊效果
-                ui.painter().rect_filled(rect, 0.0, egui::Color32::from_rgba_premultiplied(255, 255, 255, 50));
+    // 笔记标题，颜色标记显示为标题前的圆点
+    fn note_label(&self, ui: &mut egui::Ui, note_id: &str, title: &str, label: Option<Color>) -> egui::Response {
+        ui.horizontal(|ui| {
+            if let Some(label) = label {
+                let (rect, _) = ui.allocate_exact_size(egui::Vec2::splat(10.0), egui::Sense::hover());
+                ui.painter().circle_filled(rect.center(), 4.0, egui_color(label));
             }
-            Background::Lined { spacing } => {
-                // 横线背景
-                let painter = ui.painter();
-                painter.rect_filled(rect, 0.0, egui::Color32::from_rgba_premultiplied(255, 255, 255, 50));
-                
-                let mut y = rect.top() + *spacing;
-                while y < rect.bottom() {
-                    painter.line_segment(
-                        [egui::Pos2::new(rect.left(), y), egui::Pos2::new(rect.right(), y)],
-                        (1.0, egui::Color32::from_rgba_premultiplied(200, 200, 200, 100))
-                    );
-                    y += spacing;
-                }
-            }
-            Background::Grid { spacing } => {
-                // 网格背景
-                let painter = ui.painter();
-                painter.rect_filled(rect, 0.0, egui::Color32::from_rgba_premultiplied(255, 255, 255, 50));
-                
-                // 水平线
-                let mut y = rect.top() + *spacing;
-                while y < rect.bottom() {
-                    painter.line_segment(
-                        [egui::Pos2::new(rect.left(), y), egui::Pos2::new(rect.right(), y)],
-                        (0.5, egui::Color32::from_rgba_premultiplied(200, 200, 200, 80))
-                    );
-                    y += spacing;
+            let selected = self.current_note.as_ref().is_some_and(|note| note.id == note_id);
+            ui.selectable_label(selected, title).interact(egui::Sense::click_and_drag())
+        }).inner
+    }
+    
+    fn matches_tag_filter(&self, metadata: &NoteMetadata) -> bool {
+        self.tag_filter.as_deref().is_none_or(|tag| metadata.has_tag(tag))
+    }
+    
+    // 已经打开过的笔记直接切换，否则从notes目录读取
+    fn open_note(&mut self, note_id: &str) {
+        // 当前笔记的修改先放回列表，切换回来时不会丢失
+        if let Some(current) = &self.current_note {
+            if let Some(saved) = self.notes.iter_mut().find(|note| note.id == current.id) {
+                *saved = current.clone();
+            }
+        }
+        if let Some(note) = self.notes.iter().find(|note| note.id == note_id) {
+            self.current_note = Some(note.clone());
+            return;
+        }
+        match storage::load_note(note_id) {
+            Ok(note) => {
+                // 刚读入的笔记没有撤销历史，清理上次被撤销插入的图片留下的文件
+                if let Err(e) = storage::remove_unused_assets(&note) {
+                    log::warn!("清理图片资源失败 {}: {}", note_id, e);
                 }
-                
-                // 垂直线
-                let mut x = rect.left() + *spacing;
-                while x < rect.right() {
-                    painter.line_segment(
-                        [egui::Pos2::new(x, rect.top()), egui::Pos2::new(x, rect.bottom())],
-                        (0.5, egui::Color32::from_rgba_premultiplied(200, 200, 200, 80))
-                    );
-                    x += spacing;
+                self.notes.push(note.clone());
+                self.current_note = Some(note);
+            }
+            Err(e) => log::warn!("打开笔记失败 {}: {}", note_id, e),
+        }
+    }
+    
+    // 跳转到链接的目标，其他笔记的链接先打开该笔记
+    fn follow_link(&mut self, ctx: &egui::Context, target: &LinkTarget) {
+        let page_id = match target {
+            LinkTarget::Url { url } => {
+                ctx.open_url(egui::OpenUrl::new_tab(url));
+                return;
+            }
+            LinkTarget::Page { page_id } => Some(page_id),
+            LinkTarget::Note { note_id, page_id } => {
+                self.open_note(note_id);
+                if self.current_note.as_ref().is_none_or(|note| note.id != *note_id) {
+                    return;
                 }
+                page_id.as_ref()
             }
-            Background::Pdf { .. } => {
-                // PDF背景 - 显示占位符
-                ui.painter().rect_filled(rect, 0.0, egui::Color32::from_rgba_premultiplied(240, 240, 240, 100));
-                ui.painter().text(
-                    rect.center(),
-                    egui::Align2::CENTER_CENTER,
-                    "PDF背景",
-                    egui::FontId::proportional(24.0),
-                    egui::Color32::from_rgba_premultiplied(150, 150, 150, 150)
-                );
+        };
+        
+        self.selection = None;
+        if let (Some(note), Some(page_id)) = (&mut self.current_note, page_id) {
+            match note.page_index_of(page_id) {
+                Some(page_index) => note.current_page = page_index,
+                None => log::warn!("链接指向的页面已被删除: {}", page_id),
             }
         }
     }
     
-    fn draw_existing_strokes(&self, ui: &mut egui::Ui, strokes: &[Stroke], rect: egui::Rect) {
-        for stroke in strokes {
-            self.draw_stroke(ui, stroke, rect);
+    // 可以链接到的其他笔记（id, 标题）：已打开的和notes目录中保存的
+    fn link_candidates(&self) -> Vec<(String, String)> {
+        let current_id = self.current_note.as_ref().map(|note| note.id.as_str());
+        let mut candidates: Vec<(String, String)> = self.notes.iter()
+            .map(|note| (note.id.clone(), note.title.clone()))
+            .collect();
+        if let Some(tree) = &self.folder_tree {
+            for entry in tree.all_notes() {
+                if !candidates.iter().any(|(id, _)| *id == entry.id) {
+                    candidates.push((entry.id.clone(), entry.title.clone()));
+                }
+            }
         }
+        candidates.retain(|(id, _)| Some(id.as_str()) != current_id);
+        candidates
     }
     
-    fn draw_stroke(&self, ui: &mut egui::Ui, stroke: &Stroke, rect: egui::Rect) {
-        if stroke.points.len() < 2 {
-            return;
-        }
+    // 新建的文件夹取一个不重名的默认名字，并立即进入重命名
+    fn create_folder(&mut self, tree: &storage::Folder, parent: &str) -> Result<(), Box<dyn std::error::Error>> {
+        let siblings: Vec<&str> = tree.find(parent)
+            .map(|folder| folder.folders.iter().map(|child| child.name.as_str()).collect())
+            .unwrap_or_default();
+        let name = (1..)
+            .map(|n| if n == 1 { "新建文件夹".to_string() } else { format!("新建文件夹 {}", n) })
+            .find(|name| !siblings.contains(&name.as_str()))
+            .unwrap_or_default();
         
-        let painter = ui.painter();
-        let color = self.parse_color(&stroke.color).unwrap_or(egui::Color32::BLACK);
-        
-        for i in 0..stroke.points.len() - 1 {
-            let start = stroke.points[i];
-            let end = stroke.points[i + 1];
-            
-            let start_pos = egui::Pos2::new(
-                rect.left() + start.x,
-                rect.top() + start.y
-            );
-            
-            let end_pos = egui::Pos2::new(
-                rect.left() + end.x,
-                rect.top() + end.y
-            );
-            
-            painter.line_segment(
-                [start_pos, end_pos],
-                (stroke.thickness, color)
-            );
-        }
+        let path = storage::create_folder(parent, &name)?;
+        self.renaming_folder = Some((path, name));
+        Ok(())
     }
     
-    fn handle_drawing_input(&mut self, response: &egui::Response, rect: egui::Rect, page: &mut crate::note::Page) {
-        if response.dragged() {
-            if let Some(pointer_pos) = response.interact_pointer_pos() {
-                let point = Point {
-                    x: pointer_pos.x - rect.left(),
-                    y: pointer_pos.y - rect.top(),
-                    timestamp: std::time::SystemTime::now()
-                        .duration_since(std::time::UNIX_EPOCH)
-                        .unwrap()
-                        .as_millis() as u64,
+    // 当前笔记的页面列表，拖动页面可以调整顺序，右键菜单插入、复制或删除
+    fn render_page_list(&mut self, ui: &mut egui::Ui) {
+        let note = match &mut self.current_note {
+            Some(note) => note,
+            None => return,
+        };
+        
+        ui.heading("页面");
+        
+        let page_count = note.pages.len();
+        let mut actions = Vec::new();
+        egui::ScrollArea::vertical().show(ui, |ui| {
+            for index in 0..page_count {
+                let label = if note.is_bookmarked(index) {
+                    format!("第 {} 页 🔖", index + 1)
+                } else {
+                    format!("第 {} 页", index + 1)
                 };
+                let response = ui.selectable_label(note.current_page == index, label)
+                    .interact(egui::Sense::click_and_drag());
+                response.dnd_set_drag_payload(DraggedPage(index));
                 
-                if self.drawing {
-                    if let Some(stroke) = &mut self.current_stroke {
-                        stroke.points.push(point);
+                if response.clicked() {
+                    actions.push(PageAction::Select(index));
+                }
+                
+                // 拖到某一页的上半部分插到它前面，下半部分插到它后面
+                if let (Some(pointer), Some(_)) = (
+                    ui.input(|i| i.pointer.interact_pos()),
+                    response.dnd_hover_payload::<DraggedPage>(),
+                ) {
+                    let rect = response.rect;
+                    let insert_at = if pointer.y < rect.center().y { index } else { index + 1 };
+                    let y = if insert_at == index { rect.top() } else { rect.bottom() };
+                    ui.painter().hline(rect.x_range(), y, (2.0, ui.visuals().selection.stroke.color));
+                    
+                    if let Some(dragged) = response.dnd_release_payload::<DraggedPage>() {
+                        let from = dragged.0;
+                        let to = if insert_at > from { insert_at - 1 } else { insert_at };
+                        actions.push(PageAction::Move(from, to));
                     }
-                } else {
-                    self.drawing = true;
-                    self.current_stroke = Some(Stroke {
-                        points: vec![point],
-                        color: self.brush_color.clone(),
-                        thickness: self.brush_thickness,
-                        pressure: vec![1.0], // 简化压力感应
-                    });
                 }
+                
+                response.context_menu(|ui| {
+                    if ui.button("在前面插入").clicked() {
+                        actions.push(PageAction::Insert(index));
+                        ui.close_menu();
+                    }
+                    if ui.button("在后面插入").clicked() {
+                        actions.push(PageAction::Insert(index + 1));
+                        ui.close_menu();
+                    }
+                    if ui.button("复制页面").clicked() {
+                        actions.push(PageAction::Duplicate(index));
+                        ui.close_menu();
+                    }
+                    if ui.button("添加书签").clicked() {
+                        actions.push(PageAction::Bookmark(index));
+                        ui.close_menu();
+                    }
+                    if ui.add_enabled(page_count > 1, egui::Button::new("删除页面")).clicked() {
+                        actions.push(PageAction::Remove(index));
+                        ui.close_menu();
+                    }
+                });
             }
-        } else if response.drag_released() {
-            if let Some(stroke) = self.current_stroke.take() {
-                page.strokes.push(stroke);
-            }
-            self.drawing = false;
+        });
+        
+        for action in actions {
+            let _ = match action {
+                PageAction::Select(index) => {
+                    note.current_page = index;
+                    Ok(())
+                }
+                PageAction::Move(from, to) => note.move_page(from, to),
+                PageAction::Insert(index) => note.insert_page(index, Background::Blank),
+                PageAction::Duplicate(index) => note.duplicate_page(index),
+                PageAction::Bookmark(index) => note.bookmark_page(index, None),
+                PageAction::Remove(index) => note.remove_page(index),
+            };
         }
     }
     
-    fn create_new_note(&mut self) {
-        let new_note = Note::new(format!("新笔记 {}", self.notes.len() + 1));
-        self.notes.push(new_note.clone());
-        self.current_note = Some(new_note);
+    fn render_layer_panel(&mut self, ui: &mut egui::Ui) {
+        ui.heading("图层");
+        
+        let note = match &mut self.current_note {
+            Some(note) => note,
+            None => return,
+        };
+        let page_index = note.current_page;
+        let page = match note.pages.get(page_index) {
+            Some(page) => page,
+            None => return,
+        };
+        
+        let mut actions = Vec::new();
+        
+        // 列表从上到下显示，与绘制顺序相反
+        for (index, layer) in page.layers.iter().enumerate().rev() {
+            ui.horizontal(|ui| {
+                let mut visible = layer.visible;
+                if ui.checkbox(&mut visible, "").on_hover_text("显示").changed() {
+                    actions.push(LayerAction::SetVisible(index, visible));
+                }
+                
+                let mut locked = layer.locked;
+                if ui.toggle_value(&mut locked, "🔒").on_hover_text("锁定").changed() {
+                    actions.push(LayerAction::SetLocked(index, locked));
+                }
+                
+                if ui.selectable_label(page.active_layer == index, &layer.name).clicked() {
+                    actions.push(LayerAction::Select(index));
+                }
+                
+                if ui.small_button("↑").clicked() && index + 1 < page.layers.len() {
+                    actions.push(LayerAction::Move(index, index + 1));
+                }
+                
+                if ui.small_button("↓").clicked() && index > 0 {
+                    actions.push(LayerAction::Move(index, index - 1));
+                }
+            });
+            
+            // 拖动时只更新预览值，松开后提交为一次可撤销的修改
+            let mut opacity = match self.pending_opacity {
+                Some((pending_index, value)) if pending_index == index => value,
+                _ => layer.opacity,
+            };
+            let response = ui.add(egui::Slider::new(&mut opacity, 0.0..=1.0).text("不透明度"));
+            if response.dragged() {
+                self.pending_opacity = Some((index, opacity));
+            } else if response.drag_released() || response.changed() {
+                if opacity != layer.opacity {
+                    actions.push(LayerAction::SetOpacity(index, opacity));
+                }
+                self.pending_opacity = None;
+            }
+            
+            ui.separator();
+        }
+        
+        ui.horizontal(|ui| {
+            if ui.button("新建图层").clicked() {
+                actions.push(LayerAction::Add);
+            }
+            
+            if ui.add_enabled(page.layers.len() > 1, egui::Button::new("删除图层")).clicked() {
+                actions.push(LayerAction::Remove(page.active_layer));
+            }
+        });
+        
+        let layer_count = page.layers.len();
+        for action in actions {
+            let _ = match action {
+                LayerAction::Select(index) => note.set_active_layer(page_index, index),
+                LayerAction::SetVisible(index, visible) => note.set_layer_visible(page_index, index, visible),
+                LayerAction::SetLocked(index, locked) => note.set_layer_locked(page_index, index, locked),
+                LayerAction::SetOpacity(index, opacity) => note.set_layer_opacity(page_index, index, opacity),
+                LayerAction::Move(from, to) => note.move_layer(page_index, from, to),
+                LayerAction::Remove(index) => note.remove_layer(page_index, index),
+                LayerAction::Add => note.add_layer(page_index, format!("图层 {}", layer_count + 1)),
+            };
+        }
     }
     
-    fn parse_color(&self, color_str: &str) -> Option<egui::Color32> {
-        if color_str.starts_with('#') && color_str.len() == 7 {
-            let r = u8::from_str_radix(&color_str[1..3], 16).ok()?;
-            let g = u8::from_str_radix(&color_str[3..5], 16).ok()?;
-            let b = u8::from_str_radix(&color_str[5..7], 16).ok()?;
-            
-            Some(egui::Color32::from_rgb(r, g, b))
-        } else {
-            None
+    // 大纲面板：点击条目跳到对应的页面，右键菜单重命名、调整层级和顺序或删除
+    fn render_outline_panel(&mut self, ui: &mut egui::Ui) {
+        ui.heading("大纲");
+        
+        let note = match &mut self.current_note {
+            Some(note) => note,
+            None => return,
+        };
+        
+        let mut actions = Vec::new();
+        egui::ScrollArea::vertical().id_source("outline").show(ui, |ui| {
+            Self::render_outline_entries(ui, note, &note.outline, &mut Vec::new(), &mut self.renaming_outline, &mut actions);
+        });
+        
+        if ui.button("为当前页添加书签").clicked() {
+            actions.push(OutlineAction::Bookmark(note.current_page));
+        }
+        
+        for action in actions {
+            match action {
+                OutlineAction::Go(page_index) => note.current_page = page_index,
+                OutlineAction::Bookmark(page_index) => {
+                    let _ = note.bookmark_page(page_index, None);
+                }
+                OutlineAction::StartRename(path, title) => self.renaming_outline = Some((path, title)),
+                OutlineAction::Rename(path, title) => note.edit_outline(|outline| {
+                    if let Some(entry) = OutlineEntry::get_mut(outline, &path) {
+                        entry.title = title;
+                    }
+                }),
+                OutlineAction::Indent(path) => note.edit_outline(|outline| {
+                    OutlineEntry::indent(outline, &path);
+                }),
+                OutlineAction::Outdent(path) => note.edit_outline(|outline| {
+                    OutlineEntry::outdent(outline, &path);
+                }),
+                OutlineAction::Move(path, up) => note.edit_outline(|outline| {
+                    OutlineEntry::move_by(outline, &path, up);
+                }),
+                OutlineAction::Remove(path) => note.edit_outline(|outline| {
+                    OutlineEntry::remove(outline, &path);
+                }),
+            }
         }
     }
+    
+    // 反向链接面板：列出指向当前笔记的链接，点击打开来源笔记的对应页面。
+    // 已保存的笔记切换笔记或点击刷新时才重新读取，打开的笔记每帧按最新内容统计
+    fn render_backlinks_panel(&mut self, ui: &mut egui::Ui) {
+        ui.horizontal(|ui| {
+            ui.heading("反向链接");
+            if ui.small_button("刷新").clicked() {
+                self.stored_backlinks = None;
+            }
+        });
+        
+        let note = match &self.current_note {
+            Some(note) => note,
+            None => return,
+        };
+        if self.stored_backlinks.as_ref().is_none_or(|(note_id, _)| *note_id != note.id) {
+            let stored = storage::stored_backlinks(note).unwrap_or_else(|e| {
+                log::warn!("读取反向链接失败: {}", e);
+                Vec::new()
+            });
+            self.stored_backlinks = Some((note.id.clone(), stored));
+        }
+        let stored = self.stored_backlinks.as_ref().map_or(&[][..], |(_, links)| links.as_slice());
+        // 当前笔记以正在编辑的版本为准
+        let open: Vec<&Note> = std::iter::once(note)
+            .chain(self.notes.iter().filter(|other| other.id != note.id))
+            .collect();
+        let links = note::merge_backlinks(note, stored, &open);
+        
+        let mut go = None;
+        egui::ScrollArea::vertical().id_source("backlinks").show(ui, |ui| {
+            if links.is_empty() {
+                ui.weak("没有指向本笔记的链接");
+            }
+            for link in &links {
+                if ui.selectable_label(false, format!("{} · 第 {} 页", link.note_title, link.page_index + 1)).clicked() {
+                    go = Some((link.note_id.clone(), link.page_index));
+                }
+            }
+        });
+        
+        if let Some((note_id, page_index)) = go {
+            self.selection = None;
+            self.open_note(&note_id);
+            if let Some(note) = self.current_note.as_mut().filter(|note| note.id == note_id) {
+                note.current_page = page_index.min(note.pages.len().saturating_sub(1));
+            }
+        }
+    }
+    
+    // 子项按层级缩进；指向已删除页面的条目显示为灰色，不能跳转
+    fn render_outline_entries(
+        ui: &mut egui::Ui,
+        note: &Note,
+        entries: &[OutlineEntry],
+        path: &mut Vec<usize>,
+        renaming: &mut Option<(Vec<usize>, String)>,
+        actions: &mut Vec<OutlineAction>,
+    ) {
+        for (index, entry) in entries.iter().enumerate() {
+            path.push(index);
+            let page_index = note.page_index_of(&entry.page_id);
+            
+            ui.horizontal(|ui| {
+                ui.add_space((path.len() - 1) as f32 * 12.0);
+                
+                if let Some((_, title)) = renaming.as_mut().filter(|(renaming_path, _)| renaming_path == path) {
+                    let response = ui.text_edit_singleline(title);
+                    if response.lost_focus() {
+                        // Esc取消，回车或点击别处确认
+                        if !ui.input(|i| i.key_pressed(egui::Key::Escape)) {
+                            actions.push(OutlineAction::Rename(path.clone(), title.clone()));
+                        }
+                        *renaming = None;
+                    } else {
+                        response.request_focus();
+                    }
+                    return;
+                }
+                
+                let text = match page_index {
+                    Some(_) => egui::RichText::new(&entry.title),
+                    None => egui::RichText::new(&entry.title).weak(),
+                };
+                let response = ui.selectable_label(page_index == Some(note.current_page), text)
+                    .on_hover_text(page_index.map_or("页面已删除".to_string(), |i| format!("第 {} 页", i + 1)));
+                if let (true, Some(page_index)) = (response.clicked(), page_index) {
+                    actions.push(OutlineAction::Go(page_index));
+                }
+                
+                response.context_menu(|ui| {
+                    let menu = [
+                        ("重命名", OutlineAction::StartRename(path.clone(), entry.title.clone())),
+                        ("降一级", OutlineAction::Indent(path.clone())),
+                        ("升一级", OutlineAction::Outdent(path.clone())),
+                        ("上移", OutlineAction::Move(path.clone(), true)),
+                        ("下移", OutlineAction::Move(path.clone(), false)),
+                        ("删除", OutlineAction::Remove(path.clone())),
+                    ];
+                    for (label, action) in menu {
+                        if ui.button(label).clicked() {
+                            actions.push(action);
+                            ui.close_menu();
+                        }
+                    }
+                });
+            });
+            
+            Self::render_outline_entries(ui, note, &entry.children, path, renaming, actions);
+            path.pop();
+        }
+    }
+    
+    fn render_toolbar(&mut self, ui: &mut egui::Ui) {
+        ui.horizontal(|ui| {
+            // 工具选择
+            ui.selectable_value(&mut self.tool, Tool::Pen, "笔");
+            ui.selectable_value(&mut self.tool, Tool::StrokeEraser, "整笔擦除");
+            ui.selectable_value(&mut self.tool, Tool::PreciseEraser, "精确擦除");
+            ui.selectable_value(&mut self.tool, Tool::Text, "文本");
+            ui.selectable_value(&mut self.tool, Tool::Lasso, "套索");
+            ui.selectable_value(&mut self.tool, Tool::RectSelect, "框选");
+            
+            ui.separator();
+            
+            // 画笔设置
+            Self::color_button(ui, &mut self.brush_color);
+            ui.add(egui::Slider::new(&mut self.brush_thickness, 1.0..=10.0).text("粗细"));
+            
+            match self.tool {
+                Tool::StrokeEraser | Tool::PreciseEraser => {
+                    ui.add(egui::Slider::new(&mut self.eraser_radius, 2.0..=40.0).text("橡皮大小"));
+                }
+                Tool::Text => {
+                    ui.add(egui::Slider::new(&mut self.text_font_size, 8.0..=48.0).text("字号"));
+                    ui.checkbox(&mut self.text_sticky, "便签");
+                }
+                Tool::Pen => {
+                    egui::ComboBox::from_id_source("stroke_tool")
+                        .selected_text(self.stroke_tool.name())
+                        .show_ui(ui, |ui| {
+                            for tool in StrokeTool::ALL {
+                                ui.selectable_value(&mut self.stroke_tool, tool, tool.name());
+                            }
+                        });
+                    ui.add(egui::Slider::new(&mut self.smoothing.stabilization, 0.0..=0.9).text("防抖"));
+                    ui.add(egui::Slider::new(&mut self.smoothing.tolerance, 0.0..=2.0).text("简化"));
+                }
+                Tool::Lasso | Tool::RectSelect => {
+                    let candidates = self.link_candidates();
+                    // 把当前的颜色和粗细应用到选中内容
+                    if let (Some(selection), Some(note)) = (&self.selection, &mut self.current_note) {
+                        if ui.button("应用颜色").clicked() {
+                            let _ = note.set_items_color(selection.page_index, &selection.items, self.brush_color);
+                        }
+                        if ui.button("应用粗细").clicked() {
+                            let _ = note.set_items_thickness(selection.page_index, &selection.items, self.brush_thickness);
+                        }
+                        
+                        // 选中内容链接到页面、其他笔记或网址；作为链接区域时使用选框的范围
+                        let mut chosen: Option<Option<LinkTarget>> = None;
+                        ui.menu_button("链接", |ui| {
+                            ui.checkbox(&mut self.link_as_region, "作为链接区域");
+                            ui.menu_button("本笔记的页面", |ui| {
+                                for (index, page) in note.pages.iter().enumerate() {
+                                    if ui.button(format!("第 {} 页", index + 1)).clicked() {
+                                        chosen = Some(Some(LinkTarget::Page { page_id: page.id.clone() }));
+                                        ui.close_menu();
+                                    }
+                                }
+                            });
+                            ui.add_enabled_ui(!candidates.is_empty(), |ui| {
+                                ui.menu_button("其他笔记", |ui| {
+                                    for (note_id, title) in &candidates {
+                                        if ui.button(title).clicked() {
+                                            chosen = Some(Some(LinkTarget::Note { note_id: note_id.clone(), page_id: None }));
+                                            ui.close_menu();
+                                        }
+                                    }
+                                });
+                            });
+                            ui.horizontal(|ui| {
+                                ui.text_edit_singleline(&mut self.link_url_draft);
+                                if ui.button("链接网址").clicked() {
+                                    match LinkTarget::url(&self.link_url_draft) {
+                                        Ok(target) => {
+                                            chosen = Some(Some(target));
+                                            ui.close_menu();
+                                        }
+                                        Err(e) => log::warn!("{}", e),
+                                    }
+                                }
+                            });
+                            ui.separator();
+                            if ui.button("移除链接").clicked() {
+                                chosen = Some(None);
+                                ui.close_menu();
+                            }
+                        });
+                        
+                        if let Some(link) = chosen {
+                            let page_index = selection.page_index;
+                            let bounds = note.pages.get(page_index).and_then(|page| selection.bounds(page));
+                            let result = match (link, bounds) {
+                                (Some(target), Some(bounds)) if self.link_as_region => {
+                                    note.add_link_region(page_index, bounds, target).map(|_| ())
+                                }
+                                (None, Some(bounds)) if self.link_as_region => {
+                                    // 移除完全位于选框内的链接区域
+                                    let link_ids: Vec<String> = note.pages[page_index].links.iter()
+                                        .filter(|link| bounds.contains(link.rect.min) && bounds.contains(link.rect.max))
+                                        .map(|link| link.id.clone())
+                                        .collect();
+                                    note.remove_link_regions(page_index, &link_ids)
+                                }
+                                (link, _) => note.set_items_link(page_index, &selection.items, link),
+                            };
+                            if let Err(e) = result {
+                                log::warn!("设置链接失败: {}", e);
+                            }
+                        }
+                        
+                        if ui.button("删除").clicked() {
+                            let _ = note.remove_items(selection.page_index, &selection.items);
+                            self.selection = None;
+                        }
+                    }
+                }
+            }
+            
+            ui.separator();
+            
+            // 页面控制
+            if let Some(note) = &mut self.current_note {
+                if ui.add_enabled(note.can_undo(), egui::Button::new("撤销")).clicked() {
+                    note.undo();
+                    self.selection = None;
+                }
+                
+                if ui.add_enabled(note.can_redo(), egui::Button::new("重做")).clicked() {
+                    note.redo();
+                    self.selection = None;
+                }
+                
+                ui.separator();
+                
+                if ui.button("上一页").clicked() && note.current_page > 0 {
+                    note.current_page -= 1;
+                }
+                
+                ui.label(format!("第 {} 页 / 共 {} 页", note.current_page + 1, note.pages.len()));
+                
+                if ui.button("下一页").clicked() && note.current_page < note.pages.len() - 1 {
+                    note.current_page += 1;
+                }
+                
+                if ui.button("新增页面").clicked() {
+                    note.add_page(Background::Blank);
+                }
+                
+                // 当前页的纸张规格和方向
+                if let Some(page) = note.pages.get(note.current_page) {
+                    let page_index = note.current_page;
+                    let current = PageFormat::detect(page.size);
+                    let orientation = page.size.orientation();
+                    // 拖动或输入中的自定义尺寸只用于显示，松开或输入框失去焦点后才提交
+                    if self.pending_page_size.is_some_and(|(pending_index, _)| pending_index != page_index) {
+                        self.pending_page_size = None;
+                    }
+                    let shown_size = self.pending_page_size.map_or(page.size, |(_, size)| size);
+                    let custom = PageFormat::CustomMm { width: shown_size.width_mm, height: shown_size.height_mm };
+                    let custom_selected = current.is_custom() || self.custom_size_chosen;
+                    // 只在选择规格、切换方向或编辑自定义尺寸时修改页面，查看页面不产生撤销记录
+                    let mut new_size = None;
+                    egui::ComboBox::from_id_source("page_format")
+                        .selected_text(if custom_selected { custom.name() } else { current.name() })
+                        .show_ui(ui, |ui| {
+                            for preset in PageFormat::PRESETS {
+                                if ui.selectable_label(!custom_selected && current == preset, preset.name()).clicked() {
+                                    new_size = Some(preset.size(orientation));
+                                    self.custom_size_chosen = false;
+                                }
+                            }
+                            // 从预设切换到自定义时先沿用当前尺寸
+                            if ui.selectable_label(custom_selected, custom.name()).clicked() {
+                                self.custom_size_chosen = true;
+                            }
+                        });
+                    let mut landscape = orientation == Orientation::Landscape;
+                    if ui.checkbox(&mut landscape, "横向").changed() {
+                        let orientation = if landscape { Orientation::Landscape } else { Orientation::Portrait };
+                        new_size = Some(new_size.unwrap_or(shown_size).oriented(orientation));
+                    }
+                    
+                    // 自定义尺寸按所选单位输入宽和高，方向随宽高变化
+                    if new_size.is_none() && (current.is_custom() || self.custom_size_chosen) {
+                        let unit = if self.custom_size_inches { MM_PER_INCH } else { 1.0 };
+                        let (speed, range) = if self.custom_size_inches { (0.05, 1.0..=100.0) } else { (1.0, 25.0..=2500.0) };
+                        let mut width = shown_size.width_mm / unit;
+                        let mut height = shown_size.height_mm / unit;
+                        let width_response = ui.add(egui::DragValue::new(&mut width).speed(speed).clamp_range(range.clone()).max_decimals(2));
+                        ui.label("×");
+                        let height_response = ui.add(egui::DragValue::new(&mut height).speed(speed).clamp_range(range).max_decimals(2));
+                        egui::ComboBox::from_id_source("page_size_unit")
+                            .selected_text(if self.custom_size_inches { "英寸" } else { "毫米" })
+                            .width(60.0)
+                            .show_ui(ui, |ui| {
+                                ui.selectable_value(&mut self.custom_size_inches, false, "毫米");
+                                ui.selectable_value(&mut self.custom_size_inches, true, "英寸");
+                            });
+                        if width_response.changed() || height_response.changed() {
+                            let custom = if self.custom_size_inches {
+                                PageFormat::CustomInches { width, height }
+                            } else {
+                                PageFormat::CustomMm { width, height }
+                            };
+                            self.pending_page_size = Some((page_index, custom.size(PageSize::new(width, height).orientation())));
+                        }
+                        if [&width_response, &height_response].iter().any(|r| r.drag_released() || r.lost_focus()) {
+                            new_size = self.pending_page_size.take().map(|(_, size)| size);
+                        }
+                    }
+                    
+                    let mut infinite = page.is_infinite();
+                    let kind_changed = ui.checkbox(&mut infinite, "无限画布").changed();
+                    
+                    // 纸张模板，切换模板时保留当前的颜色和线宽
+                    let mut background = page.background.clone();
+                    egui::ComboBox::from_id_source("page_background")
+                        .selected_text(background.name())
+                        .show_ui(ui, |ui| {
+                            for mut template in Background::templates() {
+                                if let (Some(style), Some(current)) = (template.style_mut(), page.background.style()) {
+                                    *style = *current;
+                                }
+                                let selected = std::mem::discriminant(&template) == std::mem::discriminant(&page.background);
+                                if ui.selectable_label(selected, template.name()).clicked() && !selected {
+                                    background = template;
+                                }
+                            }
+                        });
+                    // 菜单里的修改先记在待提交的样式中，松开线宽或关闭菜单时提交为一次可撤销的修改
+                    if self.pending_template_style.is_some_and(|(pending_index, _)| pending_index != page_index) {
+                        self.pending_template_style = None;
+                    }
+                    if let Some(style) = background.style_mut() {
+                        let mut edited = self.pending_template_style.map_or(*style, |(_, pending)| pending);
+                        let menu = ui.menu_button("模板样式", |ui| {
+                            egui::Grid::new("template_style").show(ui, |ui| {
+                                ui.label("线条颜色");
+                                Self::color_button(ui, &mut edited.line_color);
+                                ui.end_row();
+                                ui.label("线宽");
+                                let line_width = ui.add(egui::DragValue::new(&mut edited.line_width).clamp_range(0.25..=8.0).speed(0.05));
+                                ui.end_row();
+                                ui.label("纸张颜色");
+                                Self::color_button(ui, &mut edited.paper_color);
+                                ui.end_row();
+                                line_width.drag_released() || line_width.lost_focus()
+                            }).inner
+                        });
+                        self.pending_template_style = (edited != *style).then_some((page_index, edited));
+                        if menu.inner.unwrap_or(true) {
+                            if let Some((_, pending)) = self.pending_template_style.take() {
+                                *style = pending;
+                            }
+                        }
+                    }
+                    
+                    let background_changed = background != page.background;
+                    if let Some(size) = new_size.filter(|size| *size != page.size) {
+                        let _ = note.set_page_size(page_index, size);
+                    }
+                    if background_changed {
+                        let _ = note.set_page_background(page_index, background);
+                    }
+                    if kind_changed {
+                        let kind = if infinite { PageKind::Infinite } else { PageKind::Fixed };
+                        let _ = note.set_page_kind(page_index, kind);
+                    }
+                }
+            }
+            
+            ui.separator();
+            
+            // Dial控制
+            if ui.button("Dial").clicked() {
+                self.show_dial = !self.show_dial;
+            }
+        });
+    }
+    
+    fn color_button(ui: &mut egui::Ui, color: &mut Color) {
+        let mut edited = egui_color(*color);
+        if ui.color_edit_button_srgba(&mut edited).changed() {
+            let [r, g, b, a] = edited.to_srgba_unmultiplied();
+            *color = Color::rgba(r, g, b, a);
+        }
+    }
+    
+    fn render_dial_ui(&mut self, ctx: &egui::Context) {
+        egui::Window::new("Magic Dial")
+            .collapsible(false)
+            .resizable(false)
+            .show(ctx, |ui| {
+                ui.vertical_centered(|ui| {
+                    ui.heading("Magic Dial");
+                    
+                    // 模拟拨盘控制
+                    ui.horizontal(|ui| {
+                        if ui.button("←").clicked() {
+                            // 上一页或减小笔刷
+                        }
+                        
+                        ui.label("○");
+                        
+                        if ui.button("→").clicked() {
+                            // 下一页或增加笔刷
+                        }
+                    });
+                    
+                    ui.label("旋转控制: 缩放/翻页/笔刷大小");
+                });
+            });
+    }
+    
+    // 中键拖动或滚轮平移无限画布
+    fn pan_canvas(&mut self, ui: &egui::Ui, response: &egui::Response, page_index: usize) {
+        if self.panned_page != Some(page_index) {
+            self.canvas_pan = egui::Vec2::ZERO;
+            self.panned_page = Some(page_index);
+        }
+        
+        if response.dragged_by(egui::PointerButton::Middle) {
+            self.canvas_pan -= response.drag_delta();
+        }
+        if response.hovered() {
+            self.canvas_pan -= ui.input(|i| i.smooth_scroll_delta);
+        }
+    }
+    
+    // paper为纸张在屏幕上的位置，visible为要画出的区域（固定页面两者相同，无限画布为视口）；
+    // 模板的线条和点由template模块生成，与PDF导出一致，并随纸张一起平移
+    fn draw_background(&mut self, ui: &mut egui::Ui, background: &Background, paper: egui::Rect, visible: egui::Rect) {
+        let painter = ui.painter();
+        match background {
+            Background::Blank => {
+                // 空白背景 - 轻微模糊效果
+                painter.rect_filled(visible, 0.0, egui::Color32::from_rgba_premultiplied(255, 255, 255, 50));
+            }
+            Background::Pdf { file_path, page_index, .. } => {
+                // 按屏幕的像素密度渲染，后台渲染完成前显示占位符，失败时显示原因和重试按钮
+                let zoom = ui.ctx().pixels_per_point();
+                match self.pdf_pages.texture(ui.ctx(), file_path, *page_index, paper.size(), zoom) {
+                    PageTexture::Ready(texture) => {
+                        let uv = egui::Rect::from_min_max(egui::Pos2::ZERO, egui::Pos2::new(1.0, 1.0));
+                        painter.image(texture.id(), paper, uv, egui::Color32::WHITE);
+                    }
+                    PageTexture::Failed(error) => {
+                        painter.rect_filled(visible, 0.0, egui::Color32::from_rgba_premultiplied(250, 235, 235, 200));
+                        painter.text(
+                            visible.center() - egui::Vec2::new(0.0, 24.0),
+                            egui::Align2::CENTER_CENTER,
+                            format!("PDF背景 第 {} 页渲染失败", page_index + 1),
+                            egui::FontId::proportional(20.0),
+                            egui::Color32::from_rgb(180, 60, 60)
+                        );
+                        let button = egui::Rect::from_center_size(visible.center() + egui::Vec2::new(0.0, 12.0), egui::Vec2::new(80.0, 28.0));
+                        if ui.put(button, egui::Button::new("重试")).on_hover_text(error).clicked() {
+                            self.pdf_pages.retry(file_path, *page_index);
+                        }
+                    }
+                    PageTexture::Pending => {
+                        painter.rect_filled(visible, 0.0, egui::Color32::from_rgba_premultiplied(240, 240, 240, 100));
+                        painter.text(
+                            visible.center(),
+                            egui::Align2::CENTER_CENTER,
+                            format!("PDF背景 第 {} 页", page_index + 1),
+                            egui::FontId::proportional(24.0),
+                            egui::Color32::from_rgba_premultiplied(150, 150, 150, 150)
+                        );
+                    }
+                }
+            }
+            _ => {
+                if let Some(style) = background.style() {
+                    painter.rect_filled(visible, 0.0, egui_color(style.paper_color));
+                }
+                
+                let to_canvas = |r: egui::Rect| Rect {
+                    min: Vec2::new(r.left() - paper.left(), r.top() - paper.top()),
+                    max: Vec2::new(r.right() - paper.left(), r.bottom() - paper.top()),
+                };
+                let to_screen = |p: Vec2| egui::Pos2::new(paper.left() + p.x, paper.top() + p.y);
+                for mark in template::marks(background, to_canvas(paper), to_canvas(visible)) {
+                    match mark {
+                        Mark::Line { start, end, width, color } => {
+                            painter.line_segment([to_screen(start), to_screen(end)], (width, egui_color(color)));
+                        }
+                        Mark::Dot { center, radius, color } => {
+                            painter.circle_filled(to_screen(center), radius, egui_color(color));
+                        }
+                    }
+                }
+            }
+        }
+    }
+    
+    // revision为笔记内容的版本，用于缓存半透明图层的合成结果；
+    // 拖动预览等临时内容传None，半透明图层退回逐笔淡化绘制
+    fn draw_layers(&mut self, ui: &mut egui::Ui, page: &Page, note_id: &str, rect: egui::Rect, revision: Option<u64>) {
+        let editing_id = self.editing_text.as_ref().map(|state| state.draft.id.clone());
+        // 只绘制与可见区域相交的笔画，无限画布上的大量内容不必每帧遍历
+        let clip = ui.clip_rect();
+        let visible = Rect {
+            min: Vec2::new(clip.left() - rect.left(), clip.top() - rect.top()),
+            max: Vec2::new(clip.right() - rect.left(), clip.bottom() - rect.top()),
+        };
+        
+        for (layer_index, layer) in page.layers.iter().enumerate().filter(|(_, layer)| layer.visible) {
+            let composite = revision.filter(|_| layer.opacity < 1.0);
+            let mut shapes = Vec::new();
+            // 同一图层内元素在下，手写笔迹在上
+            for element in &layer.elements {
+                if editing_id.as_deref() == Some(element.id()) {
+                    continue;
+                }
+                match element {
+                    Element::Text(text) => self.draw_text_box(ui, text, rect, layer.opacity),
+                    Element::Image(image) => self.draw_image(ui, image, note_id, rect, layer.opacity),
+                    Element::Shape(shape) if composite.is_some() => shapes.push(shape),
+                    Element::Shape(shape) => self.draw_shape(ui, shape, rect, layer.opacity),
+                }
+            }
+            let strokes = layer.draw_order(layer.strokes_in(&visible));
+            
+            let Some(revision) = composite else {
+                for index in strokes {
+                    self.draw_stroke(ui, &layer.strokes[index], rect, layer.opacity);
+                }
+                continue;
+            };
+            // 半透明图层的图形和笔画合成一张图后整体淡化，文字和图片仍逐个淡化绘制，位于合成图之下。
+            // 合成在页面坐标中进行，平移时只移动图的位置
+            let pixels_per_point = ui.ctx().pixels_per_point();
+            let key = CompositeKey { revision, pixels_per_point };
+            let page_visible = egui::Rect::from_min_max(
+                egui::Pos2::new(visible.min.x, visible.min.y),
+                egui::Pos2::new(visible.max.x, visible.max.y),
+            );
+            let (texture, area) = self.layer_composites.texture(ui.ctx(), &page.id, layer_index, key, page_visible, || {
+                // 合成的范围可能比可见范围大，按范围重新筛选笔画
+                let composite_area = layer_composite::composite_area(page_visible);
+                let area = Rect {
+                    min: Vec2::new(composite_area.left(), composite_area.top()),
+                    max: Vec2::new(composite_area.right(), composite_area.bottom()),
+                };
+                let page_origin = egui::Rect::from_min_size(egui::Pos2::ZERO, rect.size());
+                let mut content: Vec<egui::Shape> = shapes.iter()
+                    .flat_map(|shape| Self::shape_shapes(shape, page_origin, 1.0))
+                    .collect();
+                for index in layer.draw_order(layer.strokes_in(&area)) {
+                    content.extend(Self::stroke_shapes(&layer.strokes[index], page_origin, 1.0));
+                }
+                content
+            });
+            let uv = egui::Rect::from_min_max(egui::Pos2::ZERO, egui::Pos2::new(1.0, 1.0));
+            let screen = layer_composite::screen_rect(area, rect.min, pixels_per_point);
+            ui.painter().image(texture, screen, uv, egui::Color32::WHITE.gamma_multiply(layer.opacity));
+        }
+    }
+    
+    fn draw_stroke(&self, ui: &mut egui::Ui, stroke: &Stroke, rect: egui::Rect, opacity: f32) {
+        ui.painter().extend(Self::stroke_shapes(stroke, rect, opacity));
+    }
+    
+    fn stroke_shapes(stroke: &Stroke, rect: egui::Rect, opacity: f32) -> Vec<egui::Shape> {
+        if stroke.points.is_empty() {
+            return Vec::new();
+        }
+        
+        let ink = egui_color(stroke.color).gamma_multiply(opacity);
+        let color = ink.gamma_multiply(stroke.tool.opacity());
+        let to_screen = |p: Vec2| egui::Pos2::new(rect.left() + p.x, rect.top() + p.y);
+        
+        // 按压力和工具生成的填充轮廓，与PDF导出使用同一份拟合结果
+        let fitted = stroke.outline();
+        let tessellation = &fitted.tessellation;
+        let mut mesh = egui::Mesh::default();
+        for vertex in &tessellation.vertices {
+            mesh.colored_vertex(to_screen(*vertex), color);
+        }
+        for triangle in tessellation.indices.chunks_exact(3) {
+            mesh.add_triangle(triangle[0], triangle[1], triangle[2]);
+        }
+        let mut shapes = vec![egui::Shape::mesh(mesh)];
+        
+        // 铅笔的颗粒用原色叠加在半透明的笔迹上
+        let grain_stroke = egui::Stroke::new(PENCIL_GRAIN_WIDTH, ink);
+        for (start, end) in &fitted.grain {
+            shapes.push(egui::Shape::line_segment([to_screen(*start), to_screen(*end)], grain_stroke));
+        }
+        shapes
+    }
+    
+    // 书写中的笔画：不拟合轮廓，按采样点画折线，每段的粗细取两端压力的平均。
+    // 半透明的笔（荧光笔等）画成等宽的一条折线，避免线段重叠处颜色加深
+    fn live_stroke_shapes(stroke: &Stroke, rect: egui::Rect, opacity: f32) -> Vec<egui::Shape> {
+        let color = egui_color(stroke.color).gamma_multiply(opacity).gamma_multiply(stroke.tool.opacity());
+        let to_screen = |point: &Point| egui::Pos2::new(rect.left() + point.x, rect.top() + point.y);
+        let width = |pressure: f32| outline::width_for_pressure(stroke.width(), pressure);
+        
+        if stroke.tool.opacity() < 1.0 {
+            let points = stroke.points.iter().map(to_screen).collect();
+            return vec![egui::Shape::line(points, egui::Stroke::new(stroke.width(), color))];
+        }
+        let mut shapes: Vec<egui::Shape> = stroke.points.iter()
+            .map(|point| egui::Shape::circle_filled(to_screen(point), width(point.pressure) / 2.0, color))
+            .collect();
+        for pair in stroke.points.windows(2) {
+            let segment_width = width((pair[0].pressure + pair[1].pressure) / 2.0);
+            shapes.push(egui::Shape::line_segment([to_screen(&pair[0]), to_screen(&pair[1])], egui::Stroke::new(segment_width, color)));
+        }
+        shapes
+    }
+    
+    fn draw_shape(&self, ui: &mut egui::Ui, shape: &ShapeElement, rect: egui::Rect, opacity: f32) {
+        ui.painter().extend(Self::shape_shapes(shape, rect, opacity));
+    }
+    
+    fn shape_shapes(shape: &ShapeElement, rect: egui::Rect, opacity: f32) -> Vec<egui::Shape> {
+        let color = egui_color(shape.color).gamma_multiply(opacity);
+        let to_screen = |p: crate::geometry::Vec2| egui::Pos2::new(rect.left() + p.x, rect.top() + p.y);
+        let stroke = egui::Stroke::new(shape.thickness, color);
+        
+        let (path, closed) = shape.kind.path();
+        let points: Vec<egui::Pos2> = path.iter().copied().map(to_screen).collect();
+        let mut shapes = Vec::new();
+        if closed {
+            // egui的convex_polygon只能填充凸多边形，手绘识别出的多边形可能是凹的，先三角剖分
+            if let Some(fill) = shape.fill {
+                let fill = egui_color(fill).gamma_multiply(opacity);
+                let mut mesh = egui::Mesh::default();
+                for point in &points {
+                    mesh.colored_vertex(*point, fill);
+                }
+                for [a, b, c] in crate::geometry::triangulate_polygon(&path) {
+                    mesh.add_triangle(a as u32, b as u32, c as u32);
+                }
+                shapes.push(egui::Shape::mesh(mesh));
+            }
+            shapes.push(egui::Shape::closed_line(points, stroke));
+        } else {
+            shapes.push(egui::Shape::line(points, stroke));
+        }
+        
+        if let ShapeKind::Arrow { start, end } = shape.kind {
+            let head: Vec<egui::Pos2> = ShapeKind::arrow_head(start, end, shape.thickness)
+                .into_iter()
+                .map(to_screen)
+                .collect();
+            shapes.push(egui::Shape::convex_polygon(head, color, egui::Stroke::NONE));
+        }
+        shapes
+    }
+    
+    // 按Markdown生成文本框的排版；bold_only时只显示粗体部分，用于叠加出伪粗体
+    fn text_box_layout(text: &TextBox, color: egui::Color32, bold_only: bool) -> egui::text::LayoutJob {
+        let mut job = egui::text::LayoutJob::default();
+        job.wrap.max_width = (text.width - TEXT_BOX_PADDING * 2.0).max(1.0);
+        
+        for (index, block) in markdown::parse(&text.content).iter().enumerate() {
+            let size = text.font_size * block.kind.font_scale();
+            let heading = matches!(block.kind, BlockKind::Heading(_));
+            let format = |visible: bool, code: bool, italics: bool| egui::text::TextFormat {
+                font_id: if code {
+                    egui::FontId::monospace(size)
+                } else {
+                    egui::FontId::proportional(size)
+                },
+                color: if visible { color } else { egui::Color32::TRANSPARENT },
+                background: if code && !bold_only {
+                    egui::Color32::from_rgba_premultiplied(0, 0, 0, 20)
+                } else {
+                    egui::Color32::TRANSPARENT
+                },
+                italics,
+                ..Default::default()
+            };
+            
+            if index > 0 {
+                job.append("\n", 0.0, format(false, false, false));
+            }
+            
+            if let Some(prefix) = block.kind.prefix() {
+                job.append(&prefix, 0.0, format(!bold_only, false, false));
+            }
+            
+            for span in &block.spans {
+                let visible = !bold_only || span.bold || heading;
+                let code = span.code || block.kind == BlockKind::Code;
+                job.append(&span.text, 0.0, format(visible, code, span.italic));
+            }
+        }
+        
+        job
+    }
+    
+    fn draw_text_box(&self, ui: &mut egui::Ui, text: &TextBox, rect: egui::Rect, opacity: f32) {
+        let box_rect = egui::Rect::from_min_size(
+            egui::Pos2::new(rect.left() + text.x, rect.top() + text.y),
+            egui::Vec2::new(text.width, text.height)
+        );
+        
+        // 按宽度折行，超出文本框高度的部分不显示，与PDF导出一致
+        let painter = ui.painter().with_clip_rect(box_rect.intersect(ui.clip_rect()));
+        painter.extend(Self::text_box_shapes(ui.ctx(), text, box_rect, opacity));
+    }
+    
+    fn text_box_shapes(ctx: &egui::Context, text: &TextBox, box_rect: egui::Rect, opacity: f32) -> Vec<egui::Shape> {
+        let mut shapes = Vec::new();
+        if let Some(background) = text.background {
+            let fill = egui_color(background).gamma_multiply(opacity);
+            shapes.push(egui::Shape::rect_filled(box_rect, 4.0, fill));
+        }
+        
+        let color = egui_color(text.color).gamma_multiply(opacity);
+        let origin = box_rect.min + egui::Vec2::splat(TEXT_BOX_PADDING);
+        
+        // egui默认字体没有粗体字重，粗体和标题通过错位叠加一次来加粗
+        let galley = ctx.fonts(|fonts| fonts.layout_job(Self::text_box_layout(text, color, false)));
+        let bold_galley = ctx.fonts(|fonts| fonts.layout_job(Self::text_box_layout(text, color, true)));
+        shapes.push(egui::Shape::galley(origin, galley, color));
+        shapes.push(egui::Shape::galley(origin + egui::Vec2::new(0.6, 0.0), bold_galley, color));
+        shapes
+    }
+    
+    fn handle_text_input(&mut self, response: &egui::Response, rect: egui::Rect, note: &mut Note) {
+        if !response.clicked() {
+            return;
+        }
+        let pointer_pos = match response.interact_pointer_pos() {
+            Some(pos) => pos,
+            None => return,
+        };
+        
+        self.commit_text_edit(&response.ctx, note);
+        
+        let position = crate::geometry::Vec2::new(pointer_pos.x - rect.left(), pointer_pos.y - rect.top());
+        let existing = note.pages.get(note.current_page)
+            .and_then(|page| page.element_at(position));
+        
+        // 点中已有文本框时编辑它，否则在点击处新建
+        let (draft, is_new) = match existing {
+            Some(Element::Text(text)) => (text.clone(), false),
+            _ => {
+                let mut text = TextBox::new(position.x, position.y, self.text_font_size, self.brush_color);
+                if self.text_sticky {
+                    text.background = Some(STICKY_NOTE_COLOR);
+                }
+                (text, true)
+            }
+        };
+        
+        self.editing_text = Some(TextEditState {
+            page_index: note.current_page,
+            draft,
+            is_new,
+            focus_requested: false,
+        });
+    }
+    
+    fn render_text_editor(&mut self, ui: &mut egui::Ui, rect: egui::Rect, note: &mut Note) {
+        let stale = match &self.editing_text {
+            Some(state) => self.tool != Tool::Text || state.page_index != note.current_page,
+            None => return,
+        };
+        if stale {
+            self.commit_text_edit(ui.ctx(), note);
+            return;
+        }
+        
+        let (color, fill) = match &self.editing_text {
+            Some(state) => (
+                egui_color(state.draft.color),
+                state.draft.background.map(egui_color),
+            ),
+            None => return,
+        };
+        let state = match &mut self.editing_text {
+            Some(state) => state,
+            None => return,
+        };
+        
+        let edit_rect = egui::Rect::from_min_size(
+            egui::Pos2::new(rect.left() + state.draft.x, rect.top() + state.draft.y),
+            egui::Vec2::new(state.draft.width, state.draft.height.max(state.draft.font_size * 2.0))
+        );
+        
+        if let Some(fill) = fill {
+            ui.painter().rect_filled(edit_rect, 4.0, fill);
+        }
+        
+        let response = ui.put(
+            edit_rect,
+            egui::TextEdit::multiline(&mut state.draft.content)
+                .font(egui::FontId::proportional(state.draft.font_size))
+                .text_color(color)
+                .desired_width(state.draft.width - TEXT_BOX_PADDING * 2.0)
+                .margin(egui::Vec2::splat(TEXT_BOX_PADDING))
+                .frame(true)
+        );
+        
+        if !state.focus_requested {
+            response.request_focus();
+            state.focus_requested = true;
+        }
+        
+        let escape = ui.input(|i| i.key_pressed(egui::Key::Escape));
+        if response.lost_focus() || escape {
+            self.commit_text_edit(ui.ctx(), note);
+        }
+    }
+    
+    // 结束编辑，把文本框的改动作为一次可撤销的操作写入笔记
+    fn commit_text_edit(&mut self, ctx: &egui::Context, note: &mut Note) {
+        let state = match self.editing_text.take() {
+            Some(state) => state,
+            None => return,
+        };
+        
+        let mut draft = state.draft;
+        let galley = ctx.fonts(|fonts| {
+            fonts.layout_job(Self::text_box_layout(&draft, egui::Color32::BLACK, false))
+        });
+        draft.height = galley.size().y + TEXT_BOX_PADDING * 2.0;
+        
+        let empty = draft.content.trim().is_empty();
+        if state.is_new {
+            // 编辑期间可能换了页，文本框加到开始编辑时的页面
+            if !empty {
+                if let Err(e) = note.add_element_to(state.page_index, Element::Text(draft)) {
+                    log::warn!("添加文本框失败: {}", e);
+                }
+            }
+        } else if empty {
+            let _ = note.remove_element(state.page_index, &draft.id);
+        } else {
+            let unchanged = note.pages.get(state.page_index)
+                .and_then(|page| page.find_element(&draft.id).map(|(l, e)| &page.layers[l].elements[e]))
+                .is_some_and(|element| matches!(
+                    element,
+                    Element::Text(text) if text.content == draft.content && text.height == draft.height
+                ));
+            if !unchanged {
+                let id = draft.id.clone();
+                let _ = note.edit_element(state.page_index, &id, |element| *element = Element::Text(draft));
+            }
+        }
+    }
+    
+    fn image_key(note_id: &str, asset: &str) -> String {
+        format!("{}/{}", note_id, asset)
+    }
+    
+    fn load_image_textures(&mut self, ctx: &egui::Context, note_id: &str, page: &Page) {
+        for element in page.elements() {
+            let image = match element {
+                Element::Image(image) => image,
+                _ => continue,
+            };
+            
+            let key = Self::image_key(note_id, &image.asset);
+            if self.image_textures.contains_key(&key) {
+                continue;
+            }
+            
+            let texture = match storage::load_image_asset(note_id, &image.asset) {
+                Ok(rgba) => {
+                    let size = [rgba.width() as usize, rgba.height() as usize];
+                    let color_image = egui::ColorImage::from_rgba_unmultiplied(size, rgba.as_raw());
+                    Some(ctx.load_texture(key.clone(), color_image, egui::TextureOptions::LINEAR))
+                }
+                Err(e) => {
+                    log::warn!("加载图片失败 {}: {}", key, e);
+                    None
+                }
+            };
+            self.image_textures.insert(key, texture);
+        }
+    }
+    
+    fn draw_image(&self, ui: &mut egui::Ui, image: &ImageElement, note_id: &str, rect: egui::Rect, opacity: f32) {
+        let image_rect = egui::Rect::from_min_size(
+            egui::Pos2::new(rect.left() + image.x, rect.top() + image.y),
+            egui::Vec2::new(image.width, image.height)
+        );
+        
+        match self.image_textures.get(&Self::image_key(note_id, &image.asset)) {
+            Some(Some(texture)) => {
+                ui.painter().add(Self::image_mesh(image_rect, image.rotation, texture.id(), opacity));
+            }
+            _ => {
+                // 图片丢失时显示占位框
+                ui.painter().rect_stroke(image_rect, 0.0, (1.0, egui::Color32::GRAY));
+            }
+        }
+    }
+    
+    fn image_mesh(image_rect: egui::Rect, rotation: f32, texture_id: egui::TextureId, opacity: f32) -> egui::Mesh {
+        let mut mesh = egui::Mesh::with_texture(texture_id);
+        mesh.add_rect_with_uv(
+            image_rect,
+            egui::Rect::from_min_max(egui::Pos2::ZERO, egui::Pos2::new(1.0, 1.0)),
+            egui::Color32::WHITE.gamma_multiply(opacity)
+        );
+        mesh.rotate(egui::emath::Rot2::from_angle(rotation), image_rect.center());
+        mesh
+    }
+    
+    // 把拖放进窗口的图片文件插入到当前页面的指针位置
+    fn handle_dropped_files(&mut self, ctx: &egui::Context) {
+        let (dropped_files, hover_pos) = ctx.input(|i| (i.raw.dropped_files.clone(), i.pointer.hover_pos()));
+        if dropped_files.is_empty() {
+            return;
+        }
+        
+        let (note, canvas_rect) = match (&mut self.current_note, self.canvas_rect) {
+            (Some(note), Some(canvas_rect)) => (note, canvas_rect),
+            _ => return,
+        };
+        
+        let drop_pos = hover_pos.unwrap_or(canvas_rect.center()) - self.canvas_origin;
+        
+        for file in dropped_files {
+            let data = match (&file.bytes, &file.path) {
+                (Some(bytes), _) => bytes.to_vec(),
+                (None, Some(path)) => match std::fs::read(path) {
+                    Ok(data) => data,
+                    Err(e) => {
+                        log::warn!("读取拖放文件失败 {:?}: {}", path, e);
+                        continue;
+                    }
+                },
+                (None, None) => continue,
+            };
+            
+            if let Err(e) = Self::insert_image(note, &data, drop_pos) {
+                log::warn!("无法插入图片 {}: {}", file.name, e);
+            }
+        }
+    }
+    
+    // 把图片插入到当前页面的pos处（左上角），图层可以编辑时才写入资源文件
+    fn insert_image(note: &mut Note, data: &[u8], pos: egui::Vec2) -> Result<(), String> {
+        let (asset, width, height) = storage::prepare_image_asset(data).map_err(|e| e.to_string())?;
+        let page_index = note.current_page;
+        note.editable_layer(page_index)?;
+        storage::write_image_asset(&note.id, &asset, data).map_err(|e| e.to_string())?;
+        
+        let max_width = note.pages.get(page_index).map_or(800.0, |page| page.width() * 0.8);
+        let image = ImageElement::fitted(asset, pos.x, pos.y, width, height, max_width);
+        note.add_element_to(page_index, Element::Image(image))
+    }
+    
+    // 收集本帧的笔/触摸采样点及其压力，不支持压感的设备按1.0处理。
+    // 一笔只跟随落笔的那个触点，搁在屏幕上的手掌或其他手指的触摸被忽略
+    fn pointer_samples(&mut self, response: &egui::Response) -> Vec<(egui::Pos2, f32)> {
+        let touches: Vec<((egui::TouchDeviceId, egui::TouchId), egui::Pos2, f32)> = response.ctx.input(|i| {
+            i.events.iter()
+                .filter_map(|event| match event {
+                    egui::Event::Touch {
+                        device_id,
+                        id,
+                        phase: egui::TouchPhase::Start | egui::TouchPhase::Move,
+                        pos,
+                        force: Some(force),
+                    } => Some(((*device_id, *id), *pos, *force)),
+                    _ => None,
+                })
+                .collect()
+        });
+        
+        if self.stroke_touch.is_none() {
+            self.stroke_touch = touches.first().map(|(touch, _, _)| *touch);
+        }
+        match self.stroke_touch {
+            Some(stroke_touch) => touches.into_iter()
+                .filter(|(touch, _, _)| *touch == stroke_touch)
+                .map(|(_, pos, force)| (pos, force))
+                .collect(),
+            None => response.interact_pointer_pos()
+                .map(|pos| vec![(pos, 1.0)])
+                .unwrap_or_default(),
+        }
+    }
+    
+    fn handle_drawing_input(&mut self, response: &egui::Response, rect: egui::Rect, note: &mut Note) {
+        if response.dragged() {
+            let now = std::time::SystemTime::now()
+                .duration_since(std::time::UNIX_EPOCH)
+                .unwrap()
+                .as_millis() as u64;
+            
+            for (pointer_pos, pressure) in self.pointer_samples(response) {
+                let point = Point {
+                    x: pointer_pos.x - rect.left(),
+                    y: pointer_pos.y - rect.top(),
+                    pressure,
+                    timestamp: now,
+                    tilt: None, // egui目前不提供笔的倾斜信息
+                };
+                
+                if self.drawing {
+                    // 书写中的笔画按采样点直接绘制，松开后才拟合轮廓
+                    if let Some(stroke) = &mut self.current_stroke {
+                        stroke.points.push(point);
+                    }
+                } else {
+                    self.drawing = true;
+                    self.current_stroke = Some(Stroke {
+                        points: vec![point],
+                        color: self.brush_color,
+                        thickness: self.brush_thickness,
+                        tool: self.stroke_tool,
+                        link: None,
+                        fitted: Default::default(),
+                    });
+                }
+            }
+            
+            // 笔停住一段时间后尝试识别成图形，继续移动则取消
+            if let Some(stroke) = &self.current_stroke {
+                if recognizer::is_held(&stroke.points, now, recognizer::HOLD_MS, recognizer::HOLD_TOLERANCE) {
+                    if self.recognized_shape.is_none() {
+                        self.recognized_shape = recognizer::recognize(&stroke.points);
+                    }
+                } else {
+                    self.recognized_shape = None;
+                }
+            }
+        } else if response.drag_released() {
+            if let Some(stroke) = self.current_stroke.take() {
+                match self.recognized_shape.take() {
+                    Some(kind) => note.add_element(Element::Shape(ShapeElement::new(kind, stroke.color, stroke.thickness))),
+                    None => note.add_stroke(smoothing::process_stroke(stroke, &self.smoothing)),
+                }
+            }
+            self.drawing = false;
+            self.stroke_touch = None;
+        }
+    }
+    
+    fn handle_eraser_input(&mut self, response: &egui::Response, rect: egui::Rect, note: &mut Note, mode: EraserMode) {
+        if response.dragged() {
+            if let Some(pointer_pos) = response.interact_pointer_pos() {
+                self.eraser_path.push(Point {
+                    x: pointer_pos.x - rect.left(),
+                    y: pointer_pos.y - rect.top(),
+                    pressure: 1.0,
+                    tilt: None,
+                    timestamp: std::time::SystemTime::now()
+                        .duration_since(std::time::UNIX_EPOCH)
+                        .unwrap()
+                        .as_millis() as u64,
+                });
+            }
+        } else if response.drag_released() {
+            // 整个擦除手势作为一次可撤销的操作提交
+            let path = std::mem::take(&mut self.eraser_path);
+            if !path.is_empty() {
+                note.erase(&path, self.eraser_radius, mode);
+            }
+        }
+    }
+    
+    // 把选中内容放到系统剪贴板
+    fn copy_selection(&mut self, ctx: &egui::Context, cut: bool) {
+        let (note, selection) = match (&mut self.current_note, &self.selection) {
+            (Some(note), Some(selection)) => (note, selection),
+            _ => return,
+        };
+        
+        let fragment = match Fragment::from_items(note, selection.page_index, &selection.items) {
+            Some(fragment) => fragment,
+            None => return,
+        };
+        
+        // 每次都同时写入内嵌片段数据的SVG文本和栅格化的图片，粘贴回来时从文本还原片段。
+        // 写入失败时只复制SVG文本
+        let text = match fragment.to_clipboard_text() {
+            Ok(text) => text,
+            Err(e) => {
+                log::warn!("复制失败: {}", e);
+                return;
+            }
+        };
+        let image = render_fragment(ctx, &fragment);
+        if let Err(e) = clipboard::write_fragment(&text, &image) {
+            log::warn!("复制图片失败，改为复制SVG: {}", e);
+            ctx.output_mut(|o| o.copied_text = text);
+        }
+        
+        if cut {
+            let _ = note.remove_items(selection.page_index, &selection.items);
+            self.selection = None;
+        }
+    }
+    
+    // 剪贴板文本是复制的片段时粘贴，否则返回false
+    fn paste_from_clipboard(&mut self, ctx: &egui::Context, text: &str) -> bool {
+        match Fragment::from_clipboard_text(text) {
+            Some(fragment) => {
+                self.paste_fragment(ctx, &fragment);
+                true
+            }
+            None => false,
+        }
+    }
+    
+    // 粘贴到当前页面的光标位置，光标不在页面上时放回原来的位置
+    fn paste_fragment(&mut self, ctx: &egui::Context, fragment: &Fragment) {
+        let note = match &mut self.current_note {
+            Some(note) => note,
+            None => return,
+        };
+        
+        let center = ctx.input(|i| i.pointer.hover_pos())
+            .zip(self.canvas_rect)
+            .filter(|(pos, canvas_rect)| canvas_rect.contains(*pos))
+            .map(|(pos, _)| Vec2::new(pos.x - self.canvas_origin.x, pos.y - self.canvas_origin.y));
+        
+        let page_index = note.current_page;
+        match clipboard::paste(note, page_index, fragment, center) {
+            Ok(items) => {
+                // 粘贴后选中新内容，方便继续移动
+                if !matches!(self.tool, Tool::Lasso | Tool::RectSelect) {
+                    self.tool = Tool::Lasso;
+                }
+                self.selection = Selection::new(page_index, items);
+            }
+            Err(e) => log::warn!("粘贴失败: {}", e),
+        }
+    }
+    
+    // 粘贴剪贴板中的图片（例如截图），以PNG保存到笔记的资源目录
+    fn paste_image_from_clipboard(&mut self, ctx: &egui::Context) {
+        let image = match clipboard::read_image() {
+            Ok(Some(image)) => image,
+            Ok(None) => return,
+            Err(e) => {
+                log::warn!("读取剪贴板图片失败: {}", e);
+                return;
+            }
+        };
+        
+        let (note, canvas_rect) = match (&mut self.current_note, self.canvas_rect) {
+            (Some(note), Some(canvas_rect)) => (note, canvas_rect),
+            _ => return,
+        };
+        let data = match clipboard::encode_png(&image) {
+            Ok(data) => data,
+            Err(e) => {
+                log::warn!("无法粘贴图片: {}", e);
+                return;
+            }
+        };
+        
+        let pos = ctx.input(|i| i.pointer.hover_pos())
+            .filter(|pos| canvas_rect.contains(*pos))
+            .unwrap_or(canvas_rect.center()) - self.canvas_origin;
+        if let Err(e) = Self::insert_image(note, &data, pos) {
+            log::warn!("无法粘贴图片: {}", e);
+        }
+    }
+    
+    // 切换工具或翻页后放弃选择
+    fn validate_selection(&mut self, page_index: usize) {
+        let selecting = matches!(self.tool, Tool::Lasso | Tool::RectSelect);
+        if !selecting || self.selection.as_ref().is_some_and(|selection| selection.page_index != page_index) {
+            self.selection = None;
+            self.selection_drag = None;
+        }
+    }
+    
+    fn handle_selection_input(&mut self, response: &egui::Response, rect: egui::Rect, note: &mut Note, lasso: bool) {
+        let to_page = |pos: egui::Pos2| Vec2::new(pos.x - rect.left(), pos.y - rect.top());
+        let page_index = note.current_page;
+        let page = match note.pages.get(page_index) {
+            Some(page) => page,
+            None => return,
+        };
+        
+        if response.drag_started() {
+            let start = match response.ctx.input(|i| i.pointer.press_origin()) {
+                Some(pos) => to_page(pos),
+                None => return,
+            };
+            
+            // 按在选框或手柄上时变换选中内容，否则开始新的圈选
+            let grabbed = self.selection.as_ref()
+                .and_then(|selection| selection.bounds(page))
+                .and_then(|bounds| Some((selection::handle_at(&bounds, start, HANDLE_RADIUS)?, bounds)));
+            self.selection_drag = Some(match grabbed {
+                Some((handle, bounds)) => SelectionDrag::Transform { handle, bounds, start, preview: None },
+                None => {
+                    self.selection = None;
+                    SelectionDrag::Select(vec![start])
+                }
+            });
+        }
+        
+        if response.dragged() {
+            let current = match response.interact_pointer_pos() {
+                Some(pos) => to_page(pos),
+                None => return,
+            };
+            match &mut self.selection_drag {
+                Some(SelectionDrag::Select(path)) => path.push(current),
+                Some(SelectionDrag::Transform { handle, bounds, start, preview }) => {
+                    if let Some(selection) = &self.selection {
+                        let transform = selection::drag_transform(*handle, bounds, *start, current);
+                        let mut transformed = page.clone();
+                        transformed.transform_items(&selection.items, &transform);
+                        *preview = Some((transform, transformed));
+                    }
+                }
+                None => {}
+            }
+        } else if response.drag_released() {
+            match self.selection_drag.take() {
+                Some(SelectionDrag::Select(path)) => {
+                    let polygon = if lasso {
+                        path
+                    } else {
+                        let corners = path.first().zip(path.last())
+                            .and_then(|(first, last)| Rect::from_points([*first, *last]));
+                        corners.map_or_else(Vec::new, |bounds| bounds.corners().to_vec())
+                    };
+                    self.selection = Selection::new(page_index, page.items_in_polygon(&polygon));
+                }
+                Some(SelectionDrag::Transform { preview: Some((transform, _)), .. }) => {
+                    // 整个拖动作为一次可撤销的操作提交
+                    if let Some(selection) = &self.selection {
+                        let _ = note.transform_items(page_index, &selection.items, &transform);
+                    }
+                }
+                _ => {}
+            }
+        } else if response.clicked() {
+            // 点在选框外时取消选择
+            let inside = response.interact_pointer_pos()
+                .zip(self.selection.as_ref().and_then(|selection| selection.bounds(page)))
+                .is_some_and(|(pos, bounds)| bounds.contains(to_page(pos)));
+            if !inside {
+                self.selection = None;
+            }
+        }
+    }
+    
+    fn draw_selection(&self, ui: &mut egui::Ui, rect: egui::Rect, note: &Note) {
+        let to_screen = |p: Vec2| egui::Pos2::new(rect.left() + p.x, rect.top() + p.y);
+        let color = egui::Color32::from_rgb(30, 136, 229);
+        let outline = egui::Stroke::new(1.0, color);
+        
+        if let Some(SelectionDrag::Select(path)) = &self.selection_drag {
+            let points: Vec<egui::Pos2> = path.iter().map(|p| to_screen(*p)).collect();
+            match (self.tool, points.first(), points.last()) {
+                (Tool::RectSelect, Some(first), Some(last)) => {
+                    ui.painter().rect_stroke(egui::Rect::from_two_pos(*first, *last), 0.0, outline);
+                }
+                _ if points.len() > 1 => {
+                    ui.painter().add(egui::Shape::closed_line(points, outline));
+                }
+                _ => {}
+            }
+        }
+        
+        let selection = match &self.selection {
+            Some(selection) => selection,
+            None => return,
+        };
+        let page = match &self.selection_drag {
+            Some(SelectionDrag::Transform { preview: Some((_, preview)), .. }) => preview,
+            _ => match note.pages.get(selection.page_index) {
+                Some(page) => page,
+                None => return,
+            },
+        };
+        let bounds = match selection.bounds(page) {
+            Some(bounds) => bounds,
+            None => return,
+        };
+        
+        let screen_bounds = egui::Rect::from_min_max(to_screen(bounds.min), to_screen(bounds.max));
+        ui.painter().rect_stroke(screen_bounds, 0.0, outline);
+        
+        let rotate = to_screen(selection::rotate_handle(&bounds));
+        ui.painter().line_segment([egui::Pos2::new(rotate.x, screen_bounds.top()), rotate], outline);
+        for handle in bounds.corners().iter().map(|c| to_screen(*c)).chain([rotate]) {
+            ui.painter().circle(handle, HANDLE_RADIUS / 2.0, egui::Color32::WHITE, outline);
+        }
+    }
+    
+    // 链接区域画成淡色的框；按住Ctrl时突出显示所有可以点击的链接
+    fn draw_links(&self, ui: &mut egui::Ui, page: &Page, rect: egui::Rect) {
+        let to_screen = |bounds: &Rect| egui::Rect::from_min_max(
+            egui::Pos2::new(rect.left() + bounds.min.x, rect.top() + bounds.min.y),
+            egui::Pos2::new(rect.left() + bounds.max.x, rect.top() + bounds.max.y),
+        );
+        let color = egui::Color32::from_rgb(30, 136, 229);
+        
+        if ui.input(|i| i.modifiers.command) {
+            for (bounds, _) in page.link_areas() {
+                ui.painter().rect_filled(to_screen(&bounds), 2.0, color.gamma_multiply(0.2));
+            }
+        } else {
+            for link in &page.links {
+                ui.painter().rect_stroke(to_screen(&link.rect), 2.0, egui::Stroke::new(1.0, color.gamma_multiply(0.4)));
+            }
+        }
+    }
+    
+    fn draw_eraser_path(&self, ui: &mut egui::Ui, rect: egui::Rect) {
+        let color = egui::Color32::from_rgba_premultiplied(120, 120, 120, 60);
+        let points: Vec<egui::Pos2> = self.eraser_path.iter()
+            .map(|p| egui::Pos2::new(rect.left() + p.x, rect.top() + p.y))
+            .collect();
+        
+        match points.as_slice() {
+            [] => {}
+            [only] => {
+                ui.painter().circle_filled(*only, self.eraser_radius, color);
+            }
+            _ => {
+                ui.painter().add(egui::Shape::line(points, (self.eraser_radius * 2.0, color)));
+            }
+        }
+    }
+    
+    fn create_new_note(&mut self) {
+        let new_note = Note::new(format!("新笔记 {}", self.notes.len() + 1));
+        self.notes.push(new_note.clone());
+        self.current_note = Some(new_note);
+    }
+}
+
+// 片段按页面上的样子栅格化（元素在下，笔迹在上），复制时作为图片放到系统剪贴板。需要在一帧之内调用
+pub fn render_fragment(ctx: &egui::Context, fragment: &Fragment) -> image::RgbaImage {
+    let area = egui::Rect::from_min_size(egui::Pos2::ZERO, egui::Vec2::new(fragment.width, fragment.height));
+    // 很大的片段降低分辨率，限制图片尺寸
+    let pixels_per_point = ctx.pixels_per_point().min(MAX_CLIPBOARD_IMAGE_SIZE / fragment.width.max(fragment.height).max(1.0));
+    
+    let mut textures = HashMap::new();
+    let mut shapes = Vec::new();
+    for (index, element) in fragment.elements.iter().enumerate() {
+        match element {
+            Element::Text(text) => {
+                let box_rect = egui::Rect::from_min_size(egui::Pos2::new(text.x, text.y), egui::Vec2::new(text.width, text.height));
+                shapes.extend(SpeedyNoteApp::text_box_shapes(ctx, text, box_rect, 1.0));
+            }
+            Element::Image(image) => match storage::load_image_asset(&fragment.source_note_id, &image.asset) {
+                Ok(rgba) => {
+                    let texture_id = egui::TextureId::User(index as u64);
+                    let size = [rgba.width() as usize, rgba.height() as usize];
+                    textures.insert(texture_id, egui::ColorImage::from_rgba_unmultiplied(size, rgba.as_raw()));
+                    let image_rect = egui::Rect::from_min_size(egui::Pos2::new(image.x, image.y), egui::Vec2::new(image.width, image.height));
+                    shapes.push(egui::Shape::mesh(SpeedyNoteApp::image_mesh(image_rect, image.rotation, texture_id, 1.0)));
+                }
+                Err(e) => log::warn!("复制时读取图片失败 {}: {}", image.asset, e),
+            },
+            Element::Shape(shape) => shapes.extend(SpeedyNoteApp::shape_shapes(shape, area, 1.0)),
+        }
+    }
+    
+    // 荧光笔画在其他笔迹下面
+    let mut strokes: Vec<&Stroke> = fragment.strokes.iter().collect();
+    strokes.sort_by_key(|stroke| !stroke.tool.is_underlay());
+    for stroke in strokes {
+        shapes.extend(SpeedyNoteApp::stroke_shapes(stroke, area, 1.0));
+    }
+    
+    // 文字排版之后字体图集里才有用到的字形
+    let atlas = ctx.fonts(|fonts| fonts.image());
+    textures.insert(egui::TextureId::default(), egui::ColorImage {
+        size: atlas.size,
+        pixels: atlas.srgba_pixels(None).collect(),
+    });
+    
+    let image = layer_composite::rasterize_textured(shapes, area, pixels_per_point, &textures);
+    let bytes: Vec<u8> = image.pixels.iter().flat_map(|pixel| pixel.to_srgba_unmultiplied()).collect();
+    image::RgbaImage::from_raw(image.size[0] as u32, image.size[1] as u32, bytes).unwrap_or_default()
+}
+
+// 颜色的透明度直接用作egui颜色的alpha
+fn egui_color(color: Color) -> egui::Color32 {
+    egui::Color32::from_rgba_unmultiplied(color.r, color.g, color.b, color.a)
 }
\ No newline at end of file